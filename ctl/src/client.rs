@@ -0,0 +1,84 @@
+use crate::Backend;
+use eyre::bail;
+use eyre::eyre;
+use eyre::Result;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use starb_ipc::Client;
+use std::env;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+/// Connect to starb. `None` if it isn't running.
+pub fn connect() -> Result<Option<Client>> {
+    let addr = env::var("STARB_IPC_ADDR").unwrap_or_else(|_| starb_ipc::DEFAULT_ADDR.to_owned());
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| eyre!("`{addr}` isn't a valid address"))?;
+
+    let Ok(stream) = TcpStream::connect_timeout(&addr, Duration::from_millis(500u64))
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(Client::new(stream, &__token()?)?))
+}
+
+/// From `STARB_IPC_TOKEN`, otherwise the token file starb leaves next to SE.
+fn __token() -> Result<String> {
+    if let Ok(token) = env::var("STARB_IPC_TOKEN") {
+        return Ok(token);
+    }
+
+    crate::se_folders()
+        .into_iter()
+        .find_map(|folder| starb_ipc::read_token(&folder).ok())
+        .ok_or_else(|| {
+            eyre!(
+                "starb is running, but its {} wasn't found. Run this from SE's system folder, or \
+                 set STARB_IPC_TOKEN",
+                starb_ipc::TOKEN_FILE,
+            )
+        })
+}
+
+impl Backend for Client {
+    fn plugins(&mut self) -> Result<Vec<Value>> {
+        match self.call("plugins.list", Value::Null)? {
+            Value::Array(plugins) => Ok(plugins),
+            _ => bail!("starb sent something that isn't a list of plugins"),
+        }
+    }
+
+    fn set(&mut self, key: &str, changes: Map<String, Value>) -> Result<Value> {
+        self.call("plugins.set", json!({ "key": key, "settings": changes }))
+    }
+
+    /// All at once, so starb knows it's a profile.
+    fn import(&mut self, profile: Map<String, Value>) -> Result<Vec<(String, Result<Value>)>> {
+        let mut results = self.call("plugins.import", json!({ "settings": profile }))?;
+
+        let (Value::Object(imported), Value::Object(failed)) =
+            (results["imported"].take(), results["failed"].take())
+        else {
+            bail!("starb sent something that isn't the result of an import");
+        };
+
+        let mut results = imported
+            .into_iter()
+            .map(|(key, settings)| (key, Ok(settings)))
+            .chain(failed.into_iter().map(|(key, e)| {
+                let e = eyre!("{}", e.as_str().unwrap_or("Unknown error"));
+
+                (key, Err(e))
+            }))
+            .collect::<Vec<_>>();
+
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(results)
+    }
+}
@@ -0,0 +1,280 @@
+//! Command-line client for starb's control API.
+//!
+//! When starb isn't running, this edits starb's settings instead. Those are
+//! picked up the next time SE is started.
+
+#![allow(clippy::print_stderr, clippy::print_stdout)]
+
+mod client;
+mod offline;
+
+use eyre::bail;
+use eyre::eyre;
+use eyre::Result;
+use offline::SettingsFile;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use starb_ipc::Client;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: starb-ctl <command>
+
+Commands:
+    list                            List every plugin and its settings
+    get <plugin>                    Show a plugin's settings
+    enable <plugin>                 Enable a plugin
+    disable <plugin>                Disable a plugin
+    set <plugin> <value>            Set a plugin's only setting
+    set <plugin> <name>=<value>...  Set some of a plugin's settings
+    context [plugin]                Show what the context tab shows (starb must be running)
+    self-test                       Check every patch is in place (starb must be running)
+    watch                           Print events as they happen (starb must be running)
+    export <file>                   Save every plugin's settings to a profile
+    import <file>                   Load every plugin's settings from a profile
+
+Environment:
+    STARB_IPC_ADDR                  Address of the control API, if it isn't the default
+    STARB_IPC_TOKEN                 Token of the control API, if starb_ipc_token isn't found
+    STARB_SETTINGS                  starb's settings file, if it isn't in the default location
+
+starb's settings are in AppData, unless SE has a starb_portable file in its system folder. Run
+this from there (or put it there) to use those instead. That's also where starb leaves the token
+for its control API, starb_ipc_token.
+
+Example:
+    starb-ctl set no_max_systems_found 50000";
+
+/// Somewhere to get and change plugin settings from.
+pub trait Backend {
+    /// Every plugin, the same as the control API's `plugins.list`.
+    fn plugins(&mut self) -> Result<Vec<Value>>;
+
+    /// Change some of a plugin's settings. Returns all of its settings
+    /// afterwards.
+    fn set(&mut self, key: &str, changes: Map<String, Value>) -> Result<Value>;
+
+    /// Change several plugins' settings at once, from a profile of plugin key
+    /// -> settings. Returns each plugin's result.
+    fn import(&mut self, profile: Map<String, Value>) -> Result<Vec<(String, Result<Value>)>> {
+        Ok(profile
+            .into_iter()
+            .map(|(key, settings)| {
+                let result = match settings {
+                    Value::Object(settings) => self.set(&key, settings),
+                    _ => Err(eyre!("Settings must be an object")),
+                };
+
+                (key, result)
+            })
+            .collect())
+    }
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1usize).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match __run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => {
+            eprintln!("{USAGE}");
+            ExitCode::from(2u8)
+        },
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Returns `false` if `args` made no sense.
+fn __run(args: &[&str]) -> Result<bool> {
+    let mut client = client::connect()?;
+
+    match *args {
+        ["context"] => __print(&__client(&mut client)?.call("context.get", Value::Null)?),
+        ["context", key] => {
+            let context = __client(&mut client)?.call("context.get", json!({ "key": key }))?;
+
+            __print(&context);
+        },
+        ["self-test"] => {
+            let report = __client(&mut client)?.call("selftest.run", Value::Null)?;
+
+            print!("{}", report["diagnostics"].as_str().unwrap_or_default());
+
+            if report["passed"] != Value::Bool(true) {
+                bail!("Self-test failed");
+            }
+        },
+        ["watch"] => {
+            let client = __client(&mut client)?;
+
+            client.call("events.subscribe", Value::Null)?;
+
+            loop {
+                __print(&client.next_notification()?);
+            }
+        },
+        _ => {
+            let mut backend: Box<dyn Backend> = if let Some(client) = client {
+                Box::new(client)
+            }
+            else {
+                let settings = SettingsFile::open()?;
+
+                eprintln!(
+                    "starb isn't running, editing {} instead. Changes will be applied when SE is \
+                     next started.",
+                    settings.path().display(),
+                );
+
+                Box::new(settings)
+            };
+
+            return __run_backend(backend.as_mut(), args);
+        },
+    }
+
+    Ok(true)
+}
+
+/// Folders SE's files (like `starb_portable`) could be in: The current one, and
+/// the one starb-ctl is in.
+fn se_folders() -> Vec<PathBuf> {
+    [
+        env::current_dir().ok(),
+        env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn __run_backend(backend: &mut dyn Backend, args: &[&str]) -> Result<bool> {
+    match *args {
+        ["list"] => {
+            for plugin in backend.plugins()? {
+                println!(
+                    "{} ({}): {}",
+                    plugin["key"].as_str().unwrap_or_default(),
+                    plugin["name"].as_str().unwrap_or_default(),
+                    plugin["settings"],
+                );
+            }
+        },
+        ["get", key] => __print(&__settings(backend, key)?),
+        ["enable", key] => __print(&__enable(backend, key, true)?),
+        ["disable", key] => __print(&__enable(backend, key, false)?),
+        ["set", key, value] if !value.contains('=') => {
+            let Value::Object(settings) = __settings(backend, key)?
+            else {
+                bail!("`{key}` has no settings");
+            };
+
+            let mut names = settings.keys();
+            let (Some(name), None) = (names.next(), names.next())
+            else {
+                bail!("`{key}` has more than one setting, use <name>=<value> instead");
+            };
+
+            let changes = __changes(&[(name, __parse_value(value))]);
+
+            __print(&backend.set(key, changes)?);
+        },
+        ["set", key, ref values @ ..] if !values.is_empty() => {
+            let mut changes = Map::new();
+
+            for value in values {
+                let Some((name, value)) = value.split_once('=')
+                else {
+                    return Ok(false);
+                };
+
+                changes.insert(name.to_owned(), __parse_value(value));
+            }
+
+            __print(&backend.set(key, changes)?);
+        },
+        ["export", path] => {
+            let profile = backend
+                .plugins()?
+                .into_iter()
+                .filter_map(|mut plugin| {
+                    let key = plugin["key"].as_str()?.to_owned();
+
+                    Some((key, plugin["settings"].take()))
+                })
+                .collect::<Map<_, _>>();
+
+            fs::write(path, serde_json::to_string_pretty(&profile)?)?;
+
+            println!("Exported {} plugins to {path}", profile.len());
+        },
+        ["import", path] => {
+            let profile = serde_json::from_str::<Map<String, Value>>(&fs::read_to_string(path)?)?;
+
+            for (key, result) in backend.import(profile)? {
+                match result {
+                    Ok(_) => println!("Imported {key}"),
+                    Err(e) => eprintln!("Failed to import {key}: {e}"),
+                }
+            }
+        },
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+fn __client(client: &mut Option<Client>) -> Result<&mut Client> {
+    client
+        .as_mut()
+        .ok_or_else(|| eyre!("starb isn't running (or its control API is disabled)"))
+}
+
+fn __settings(backend: &mut dyn Backend, key: &str) -> Result<Value> {
+    backend
+        .plugins()?
+        .into_iter()
+        .find(|plugin| plugin["key"] == key)
+        .map(|mut plugin| plugin["settings"].take())
+        .ok_or_else(|| eyre!("No plugin with key `{key}`"))
+}
+
+/// Plugins without an `enabled` setting are always on, and would ignore it.
+fn __enable(backend: &mut dyn Backend, key: &str, enabled: bool) -> Result<Value> {
+    if __settings(backend, key)?.get("enabled").is_none() {
+        bail!("`{key}` can't be enabled or disabled");
+    }
+
+    backend.set(key, __changes(&[("enabled", enabled.into())]))
+}
+
+fn __changes(changes: &[(&str, Value)]) -> Map<String, Value> {
+    changes
+        .iter()
+        .map(|&(name, ref value)| (name.to_owned(), value.clone()))
+        .collect()
+}
+
+/// JSON if it's valid JSON, so `true` and `50000` work as expected. A string
+/// otherwise.
+fn __parse_value(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()))
+}
+
+fn __print(value: &Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+    );
+}
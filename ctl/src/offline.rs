@@ -0,0 +1,373 @@
+use crate::Backend;
+use directories_next::ProjectDirs;
+use eyre::bail;
+use eyre::eyre;
+use eyre::Result;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::mem::discriminant;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Same as starb's `ipc::SETTINGS_KEY`.
+const SETTINGS_KEY: &str = "starb_ctl_settings";
+/// Same as starb's `ipc::PENDING_SETTINGS_KEY`.
+const PENDING_SETTINGS_KEY: &str = "starb_ctl_pending_settings";
+/// Same as starb's `storage::PORTABLE_MARKER`.
+const PORTABLE_MARKER: &str = "starb_portable";
+/// Same as starb's `storage::PORTABLE_FILE`.
+const PORTABLE_FILE: &str = "starb_settings.ron";
+
+/// starb's settings file, for when starb isn't running.
+///
+/// This can't change plugin settings directly. Instead, changes are written
+/// to a pending list that starb applies the next time it starts. The copy of
+/// every plugin's settings starb leaves behind is updated too, so this shows
+/// the changes.
+pub struct SettingsFile {
+    path: PathBuf,
+    kv: HashMap<String, String>,
+}
+
+impl SettingsFile {
+    /// Open starb's settings, from `STARB_SETTINGS` if it's set.
+    pub fn open() -> Result<Self> {
+        let path = match env::var_os("STARB_SETTINGS") {
+            Some(path) => PathBuf::from(path),
+            None => __default_path()?,
+        };
+
+        Self::__open(path)
+    }
+
+    fn __open(path: PathBuf) -> Result<Self> {
+        let Ok(file) = fs::File::open(&path)
+        else {
+            bail!(
+                "Couldn't find starb's settings at {}. Has starb been started before?",
+                path.display()
+            );
+        };
+
+        Ok(Self {
+            kv: ron::de::from_reader(file)?,
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn __get_json<T: serde::de::DeserializeOwned + Default>(&self, key: &str) -> Result<T> {
+        match self.kv.get(key) {
+            Some(value) => Ok(serde_json::from_str(&ron::from_str::<String>(value)?)?),
+            None => Ok(T::default()),
+        }
+    }
+
+    fn __set_json(&mut self, key: &str, value: &impl serde::Serialize) -> Result<()> {
+        let value = ron::to_string(&serde_json::to_string(value)?)?;
+
+        self.kv.insert(key.to_owned(), value);
+
+        Ok(())
+    }
+
+    /// Save the same way eframe does: write a temp file next to it, keep the
+    /// old one as `.bak`, then move the new one into place. starb could start
+    /// while this is running, and shouldn't find half a file.
+    fn __save(&self) -> Result<()> {
+        let ron = ron::ser::to_string_pretty(&self.kv, ron::ser::PrettyConfig::default())?;
+        let temp = __suffixed(&self.path, ".tmp");
+
+        let mut file = fs::File::create(&temp)?;
+
+        file.write_all(ron.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+
+        // Only if it's good, or it could replace the last good backup
+        if fs::read_to_string(&self.path)
+            .ok()
+            .is_some_and(|old| ron::from_str::<HashMap<String, String>>(&old).is_ok())
+        {
+            fs::rename(&self.path, __suffixed(&self.path, ".bak"))?;
+        }
+
+        fs::rename(&temp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl Backend for SettingsFile {
+    fn plugins(&mut self) -> Result<Vec<Value>> {
+        self.__get_json(SETTINGS_KEY)
+    }
+
+    fn set(&mut self, key: &str, changes: Map<String, Value>) -> Result<Value> {
+        let mut plugins = self.plugins()?;
+
+        let settings = plugins
+            .iter_mut()
+            .find(|plugin| plugin["key"] == key)
+            .and_then(|plugin| plugin.get_mut("settings"))
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| eyre!("No plugin with key `{key}`, or it has no settings"))?;
+
+        // starb can't tell us what's wrong, so check what we can here
+        for (name, value) in &changes {
+            match settings.get(name) {
+                Some(old) if discriminant(old) == discriminant(value) => {},
+                Some(old) => bail!("`{name}` should look like `{old}`, not `{value}`"),
+                None => bail!("`{key}` has no setting called `{name}`"),
+            }
+        }
+
+        settings.extend(changes.clone());
+
+        let settings = Value::Object(settings.clone());
+
+        let mut pending = self.__get_json::<Map<String, Value>>(PENDING_SETTINGS_KEY)?;
+
+        match pending.get_mut(key).and_then(Value::as_object_mut) {
+            Some(pending) => pending.extend(changes),
+            None => {
+                pending.insert(key.to_owned(), Value::Object(changes));
+            },
+        }
+
+        self.__set_json(SETTINGS_KEY, &plugins)?;
+        self.__set_json(PENDING_SETTINGS_KEY, &pending)?;
+        self.__save()?;
+
+        Ok(settings)
+    }
+}
+
+/// `path` with `suffix` after its extension, like eframe's `app.ron.bak`.
+fn __suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+
+    path.push(suffix);
+
+    PathBuf::from(path)
+}
+
+/// Where starb puts its settings by default. That's next to SE if it's
+/// portable, otherwise `AppData`.
+fn __default_path() -> Result<PathBuf> {
+    if let Some(folder) = crate::se_folders()
+        .into_iter()
+        .find(|folder| folder.join(PORTABLE_MARKER).exists())
+    {
+        return Ok(folder.join(PORTABLE_FILE));
+    }
+
+    let app_name = format!("Star Browser Utilities v{}", env!("CARGO_PKG_VERSION"));
+
+    ProjectDirs::from("", "", &app_name)
+        .map(|dirs| dirs.data_dir().join("app.ron"))
+        .ok_or_else(|| eyre!("Couldn't find where starb's settings are"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A settings file like starb leaves behind, in a folder of its own.
+    fn __settings_file(name: &str) -> Result<SettingsFile> {
+        let dir = env::temp_dir().join(format!("starb_ctl_{}_{name}", std::process::id()));
+
+        // Left over from a previous run, if it exists
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+
+        fs::create_dir_all(&dir)?;
+
+        let mut settings = SettingsFile {
+            path: dir.join("app.ron"),
+            kv: HashMap::new(),
+        };
+        let plugins = json!([
+            {
+                "key": "no_max_systems_found",
+                "name": "No Max Systems Found",
+                "settings": { "enabled": true, "max": 10_000u32 },
+            },
+            {
+                "key": "no_search_locking",
+                "name": "No Search Locking",
+                "settings": { "enabled": true },
+            },
+            {
+                "key": "appearance",
+                "name": "Appearance",
+                "settings": { "theme": "Dark" },
+            },
+        ]);
+
+        settings
+            .kv
+            .insert("other".to_owned(), "\"left alone\"".to_owned());
+        settings.__set_json(SETTINGS_KEY, &plugins)?;
+        settings.__save()?;
+
+        SettingsFile::__open(settings.path)
+    }
+
+    fn __changes(changes: &Value) -> Map<String, Value> {
+        changes.as_object().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn set() -> Result<()> {
+        let mut settings = __settings_file("set")?;
+
+        assert_eq!(
+            settings.set(
+                "no_max_systems_found",
+                __changes(&json!({ "max": 50_000u32 }))
+            )?,
+            json!({ "enabled": true, "max": 50_000u32 })
+        );
+        assert!(
+            settings
+                .set("no_max_systems_found", __changes(&json!({ "max": "lots" })))
+                .is_err(),
+            "Set a number to a string"
+        );
+        assert!(
+            settings
+                .set("no_max_systems_found", __changes(&json!({ "min": 1u32 })))
+                .is_err(),
+            "Set a setting that doesn't exist"
+        );
+        assert!(
+            settings.set("missing", Map::new()).is_err(),
+            "Set a plugin that doesn't exist"
+        );
+
+        settings.set(
+            "no_max_systems_found",
+            __changes(&json!({ "enabled": false })),
+        )?;
+
+        // Everything made it to disk, through a temp file, with a backup
+        let mut reopened = SettingsFile::__open(settings.path.clone())?;
+
+        assert_eq!(
+            reopened.plugins()?[0usize]["settings"],
+            json!({ "enabled": false, "max": 50_000u32 })
+        );
+        assert_eq!(
+            reopened.__get_json::<Map<String, Value>>(PENDING_SETTINGS_KEY)?
+                ["no_max_systems_found"],
+            json!({ "enabled": false, "max": 50_000u32 })
+        );
+        assert_eq!(reopened.kv["other"], "\"left alone\"");
+        assert!(
+            __suffixed(&settings.path, ".bak").exists(),
+            "Didn't keep a backup"
+        );
+        assert!(
+            !__suffixed(&settings.path, ".tmp").exists(),
+            "Left the temp file behind"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn enable() -> Result<()> {
+        let mut settings = __settings_file("enable")?;
+
+        assert!(
+            crate::__run_backend(&mut settings, &["disable", "no_search_locking"])?,
+            "Didn't understand `disable`"
+        );
+        assert_eq!(
+            settings.plugins()?[1usize]["settings"],
+            json!({ "enabled": false })
+        );
+
+        // Rather than saying it worked, and doing nothing
+        assert!(
+            crate::__run_backend(&mut settings, &["disable", "appearance"]).is_err(),
+            "Disabled a plugin that can't be"
+        );
+        assert_eq!(
+            settings.plugins()?[2usize]["settings"],
+            json!({ "theme": "Dark" })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_import() -> Result<()> {
+        let mut settings = __settings_file("export_import")?;
+        let profile = settings.path.with_file_name("profile.json");
+        let profile_path = profile.to_string_lossy().into_owned();
+
+        assert!(
+            crate::__run_backend(&mut settings, &["export", &profile_path])?,
+            "Didn't understand `export`"
+        );
+
+        let exported = fs::read_to_string(&profile)?;
+
+        settings.set(
+            "no_max_systems_found",
+            __changes(&json!({ "max": 50_000u32 })),
+        )?;
+        settings.set("no_search_locking", __changes(&json!({ "enabled": false })))?;
+
+        assert!(
+            crate::__run_backend(&mut settings, &["import", &profile_path])?,
+            "Didn't understand `import`"
+        );
+
+        let mut reopened = SettingsFile::__open(settings.path.clone())?;
+
+        assert_eq!(
+            reopened.plugins()?,
+            __settings_file("export_import_fresh")?.plugins()?
+        );
+
+        // And exporting again gives the same profile
+        assert!(
+            crate::__run_backend(&mut reopened, &["export", &profile_path])?,
+            "Didn't understand `export`"
+        );
+        assert_eq!(fs::read_to_string(&profile)?, exported);
+
+        // Bad entries don't stop the rest
+        let results = reopened.import(__changes(&json!({
+            "missing": {},
+            "no_search_locking": { "enabled": false },
+            "no_max_systems_found": 5u32,
+        })))?;
+
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (result.0.as_str(), result.1.is_ok()))
+                .collect::<Vec<_>>(),
+            [
+                ("missing", false),
+                ("no_max_systems_found", false),
+                ("no_search_locking", true),
+            ]
+        );
+
+        Ok(())
+    }
+}
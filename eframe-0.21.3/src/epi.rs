@@ -376,6 +376,13 @@ pub struct NativeOptions {
     /// Wayland desktop currently not supported.
     pub centered: bool,
 
+    /// The file to persist app state to (requires the "persistence" feature).
+    ///
+    /// If `None`, a file in the data folder the OS likes is used, named after `app_name`.
+    ///
+    /// Default: `None`.
+    pub persistence_path: Option<std::path::PathBuf>,
+
     /// Configures wgpu instance/device/adapter/surface creation and renderloop.
     #[cfg(feature = "wgpu")]
     pub wgpu_options: egui_wgpu::WgpuConfiguration,
@@ -387,6 +394,8 @@ impl Clone for NativeOptions {
         Self {
             icon_data: self.icon_data.clone(),
 
+            persistence_path: self.persistence_path.clone(),
+
             #[cfg(any(feature = "glow", feature = "wgpu"))]
             event_loop_builder: None, // Skip any builder callbacks if cloning
 
@@ -440,6 +449,8 @@ impl Default for NativeOptions {
 
             centered: false,
 
+            persistence_path: None,
+
             #[cfg(feature = "wgpu")]
             wgpu_options: egui_wgpu::WgpuConfiguration::default(),
         }
@@ -287,7 +287,18 @@ pub fn handle_app_output(
 // ----------------------------------------------------------------------------
 
 /// For loading/saving app state and/or egui memory to disk.
-pub fn create_storage(_app_name: &str) -> Option<Box<dyn epi::Storage>> {
+///
+/// `_persistence_path` is the file to use, if not the default one for `_app_name`.
+pub fn create_storage(
+    _app_name: &str,
+    _persistence_path: Option<&std::path::Path>,
+) -> Option<Box<dyn epi::Storage>> {
+    #[cfg(feature = "persistence")]
+    if let Some(path) = _persistence_path {
+        return Some(Box::new(
+            super::file_storage::FileStorage::from_ron_filepath(path),
+        ));
+    }
     #[cfg(feature = "persistence")]
     if let Some(storage) = super::file_storage::FileStorage::from_app_name(_app_name) {
         return Some(Box::new(storage));
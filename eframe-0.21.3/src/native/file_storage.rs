@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    io::Write as _,
     path::{Path, PathBuf},
 };
 
@@ -7,6 +8,10 @@ use std::{
 
 /// A key-value store backed by a [RON](https://github.com/ron-rs/ron) file on disk.
 /// Used to restore egui state, glium window position/size and app state.
+///
+/// Saving writes to a temporary file next to the real one and then renames it over, so a crash
+/// mid-save never leaves a truncated file behind. The previous file is kept as a backup (with a
+/// `.bak` extension) and used instead if the real one can't be read.
 pub struct FileStorage {
     ron_filepath: PathBuf,
     kv: HashMap<String, String>,
@@ -28,7 +33,7 @@ impl FileStorage {
         let ron_filepath: PathBuf = ron_filepath.into();
         tracing::debug!("Loading app state from {:?}…", ron_filepath);
         Self {
-            kv: read_ron(&ron_filepath).unwrap_or_default(),
+            kv: load(&ron_filepath),
             ron_filepath,
             dirty: false,
             last_save_join_handle: None,
@@ -80,21 +85,105 @@ impl crate::Storage for FileStorage {
                 join_handle.join().ok();
             }
 
-            let join_handle = std::thread::spawn(move || {
-                let file = std::fs::File::create(&file_path).unwrap();
-                let config = Default::default();
-                ron::ser::to_writer_pretty(file, &kv, config).unwrap();
-                tracing::trace!("Persisted to {:?}", file_path);
-            });
+            let persist = move || match save(&file_path, &kv) {
+                Ok(()) => tracing::trace!("Persisted to {:?}", file_path),
+                Err(err) => tracing::error!("Failed to persist to {:?}: {}", file_path, err),
+            };
 
-            self.last_save_join_handle = Some(join_handle);
+            // Not being able to start a thread is no reason to lose the save
+            match std::thread::Builder::new()
+                .name("eframe_persist".to_owned())
+                .spawn(persist.clone())
+            {
+                Ok(join_handle) => self.last_save_join_handle = Some(join_handle),
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to start saving thread, saving here instead: {}",
+                        err
+                    );
+                    persist();
+                }
+            }
         }
     }
 }
 
 // ----------------------------------------------------------------------------
 
-fn read_ron<T>(ron_path: impl AsRef<Path>) -> Option<T>
+/// Where the last good file is kept.
+fn backup_path(ron_path: &Path) -> PathBuf {
+    let mut path = ron_path.as_os_str().to_owned();
+    path.push(".bak");
+    path.into()
+}
+
+/// Where a save is written before it replaces the real file.
+fn temp_path(ron_path: &Path) -> PathBuf {
+    let mut path = ron_path.as_os_str().to_owned();
+    path.push(".tmp");
+    path.into()
+}
+
+/// Read `ron_path`, or its backup if it's missing or can't be parsed.
+fn load(ron_path: &Path) -> HashMap<String, String> {
+    let err = match read_ron(ron_path) {
+        Ok(Some(kv)) => return kv,
+        Ok(None) => None,
+        Err(err) => Some(err),
+    };
+
+    let backup_path = backup_path(ron_path);
+
+    match read_ron(&backup_path) {
+        Ok(Some(kv)) => {
+            tracing::warn!(
+                "Failed to read {:?} ({}), recovered from {:?}",
+                ron_path,
+                err.as_deref().unwrap_or("missing"),
+                backup_path
+            );
+            kv
+        }
+        Ok(None) => {
+            if let Some(err) = err {
+                tracing::error!("Failed to read {:?}, starting over: {}", ron_path, err);
+            }
+            HashMap::default()
+        }
+        Err(backup_err) => {
+            tracing::error!(
+                "Failed to read {:?} ({}) and its backup ({}), starting over",
+                ron_path,
+                err.as_deref().unwrap_or("missing"),
+                backup_err
+            );
+            HashMap::default()
+        }
+    }
+}
+
+/// Replace `ron_path` with `kv`, keeping the old file as a backup if it's good.
+fn save(ron_path: &Path, kv: &HashMap<String, String>) -> std::io::Result<()> {
+    let ron = ron::ser::to_string_pretty(kv, Default::default())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+    let temp_path = temp_path(ron_path);
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(ron.as_bytes())?;
+    // Make sure it's actually on disk before it replaces anything
+    file.sync_all()?;
+    drop(file);
+
+    // Only rotate files that can be read, or a bad file could replace the last good one
+    if matches!(read_ron::<HashMap<String, String>>(ron_path), Ok(Some(_))) {
+        std::fs::rename(ron_path, backup_path(ron_path))?;
+    }
+
+    std::fs::rename(&temp_path, ron_path)
+}
+
+/// `Ok(None)` if the file doesn't exist.
+fn read_ron<T>(ron_path: impl AsRef<Path>) -> Result<Option<T>, String>
 where
     T: serde::de::DeserializeOwned,
 {
@@ -102,16 +191,153 @@ where
         Ok(file) => {
             let reader = std::io::BufReader::new(file);
             match ron::de::from_reader(reader) {
-                Ok(value) => Some(value),
-                Err(err) => {
-                    tracing::warn!("Failed to parse RON: {}", err);
-                    None
-                }
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(format!("Failed to parse RON: {}", err)),
             }
         }
-        Err(_err) => {
-            // File probably doesn't exist. That's fine.
-            None
-        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Storage as _;
+
+    /// An empty directory of its own for each test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "eframe_file_storage_{}_{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Save `key` = `value`, waiting for it to finish.
+    fn save_value(ron_path: &Path, key: &str, value: &str) {
+        let mut storage = FileStorage::from_ron_filepath(ron_path);
+        storage.set_string(key, value.to_owned());
+        storage.flush();
+    }
+
+    #[test]
+    fn round_trip() {
+        let ron_path = test_dir("round_trip").join("app.ron");
+
+        save_value(&ron_path, "key", "value");
+
+        let storage = FileStorage::from_ron_filepath(&ron_path);
+        assert_eq!(storage.get_string("key").as_deref(), Some("value"));
+        assert!(!temp_path(&ron_path).exists());
+        // Nothing to back up the first time
+        assert!(!backup_path(&ron_path).exists());
+    }
+
+    #[test]
+    fn keeps_backup() {
+        let ron_path = test_dir("keeps_backup").join("app.ron");
+
+        save_value(&ron_path, "key", "first");
+        save_value(&ron_path, "key", "second");
+
+        let backup: HashMap<String, String> = read_ron(backup_path(&ron_path)).unwrap().unwrap();
+        assert_eq!(backup["key"], "first");
+
+        let storage = FileStorage::from_ron_filepath(&ron_path);
+        assert_eq!(storage.get_string("key").as_deref(), Some("second"));
+    }
+
+    #[test]
+    fn recovers_from_truncated_file() {
+        let ron_path = test_dir("recovers_from_truncated_file").join("app.ron");
+
+        save_value(&ron_path, "key", "first");
+        save_value(&ron_path, "key", "second");
+
+        // Like a crash halfway through writing it
+        let ron = std::fs::read(&ron_path).unwrap();
+        std::fs::write(&ron_path, &ron[..ron.len() / 2]).unwrap();
+
+        let storage = FileStorage::from_ron_filepath(&ron_path);
+        assert_eq!(storage.get_string("key").as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn recovers_from_missing_file() {
+        let ron_path = test_dir("recovers_from_missing_file").join("app.ron");
+
+        save_value(&ron_path, "key", "first");
+        save_value(&ron_path, "key", "second");
+
+        // Like a crash between the two renames
+        std::fs::remove_file(&ron_path).unwrap();
+
+        let storage = FileStorage::from_ron_filepath(&ron_path);
+        assert_eq!(storage.get_string("key").as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn bad_file_doesnt_replace_backup() {
+        let ron_path = test_dir("bad_file_doesnt_replace_backup").join("app.ron");
+
+        save_value(&ron_path, "key", "first");
+        save_value(&ron_path, "key", "second");
+        std::fs::write(&ron_path, "{\"key\": ").unwrap();
+        save_value(&ron_path, "key", "third");
+
+        let backup: HashMap<String, String> = read_ron(backup_path(&ron_path)).unwrap().unwrap();
+        assert_eq!(backup["key"], "first");
+
+        let storage = FileStorage::from_ron_filepath(&ron_path);
+        assert_eq!(storage.get_string("key").as_deref(), Some("third"));
+    }
+
+    #[test]
+    fn ignores_stale_temp_file() {
+        let ron_path = test_dir("ignores_stale_temp_file").join("app.ron");
+
+        save_value(&ron_path, "key", "first");
+        // Like a crash halfway through writing the next one
+        std::fs::write(temp_path(&ron_path), "{\"key\": \"sec").unwrap();
+
+        let storage = FileStorage::from_ron_filepath(&ron_path);
+        assert_eq!(storage.get_string("key").as_deref(), Some("first"));
+        drop(storage);
+
+        save_value(&ron_path, "key", "second");
+
+        let storage = FileStorage::from_ron_filepath(&ron_path);
+        assert_eq!(storage.get_string("key").as_deref(), Some("second"));
+    }
+
+    #[test]
+    fn nothing_to_recover() {
+        let dir = test_dir("nothing_to_recover");
+        let ron_path = dir.join("app.ron");
+
+        assert!(FileStorage::from_ron_filepath(&ron_path).kv.is_empty());
+
+        std::fs::write(&ron_path, "garbage").unwrap();
+        std::fs::write(backup_path(&ron_path), "more garbage").unwrap();
+
+        assert!(FileStorage::from_ron_filepath(&ron_path).kv.is_empty());
+    }
+
+    #[test]
+    fn reports_errors() {
+        // Can't be created, since its directory doesn't exist
+        let ron_path = test_dir("reports_errors").join("missing").join("app.ron");
+
+        assert!(save(&ron_path, &HashMap::default()).is_err());
+
+        // Flushing logs the same error instead of panicking
+        save_value(&ron_path, "key", "value");
+        assert!(!ron_path.exists());
     }
 }
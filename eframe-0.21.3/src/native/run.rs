@@ -2,6 +2,7 @@
 //! When making changes to one you often also want to apply it to the other.
 
 use std::time::{Duration, Instant};
+#[cfg(windows)]
 use winit::platform::windows::EventLoopBuilderExtWindows;
 use winit::event_loop::{
     ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy, EventLoopWindowTarget,
@@ -84,6 +85,10 @@ fn create_event_loop_builder(
         hook(&mut event_loop_builder);
     }
 
+    // starb creates the event loop outside of SE's main thread, which only Windows allows
+    #[cfg(windows)]
+    event_loop_builder.with_any_thread(true);
+
     event_loop_builder
 }
 
@@ -104,7 +109,7 @@ fn with_event_loop<R>(
         // create the event loop lazily here
         let mut event_loop = event_loop.borrow_mut();
         let event_loop = event_loop
-            .get_or_insert_with(|| create_event_loop_builder(&mut native_options).with_any_thread(true).build());
+            .get_or_insert_with(|| create_event_loop_builder(&mut native_options).build());
         f(event_loop, native_options)
     })
 }
@@ -661,7 +666,10 @@ mod glow_integration {
         }
 
         fn init_run_state(&mut self, event_loop: &EventLoopWindowTarget<UserEvent>) -> Result<()> {
-            let storage = epi_integration::create_storage(&self.app_name);
+            let storage = epi_integration::create_storage(
+                &self.app_name,
+                self.native_options.persistence_path.as_deref(),
+            );
 
             let (gl_window, gl) = Self::create_glutin_windowed_context(
                 event_loop,
@@ -994,7 +1002,7 @@ mod glow_integration {
                 run_and_return(event_loop, glow_eframe)
             })
         } else {
-            let event_loop = create_event_loop_builder(&mut native_options).with_any_thread(true).build();
+            let event_loop = create_event_loop_builder(&mut native_options).build();
             let glow_eframe = GlowWinitApp::new(&event_loop, app_name, native_options, app_creator);
             run_and_exit(event_loop, glow_eframe);
         }
@@ -1292,7 +1300,10 @@ mod wgpu_integration {
                             self.set_window(window)?;
                         }
                     } else {
-                        let storage = epi_integration::create_storage(&self.app_name);
+                        let storage = epi_integration::create_storage(
+                            &self.app_name,
+                            self.native_options.persistence_path.as_deref(),
+                        );
                         let window = Self::create_window(
                             event_loop,
                             storage.as_deref(),
@@ -1410,7 +1421,7 @@ mod wgpu_integration {
                 run_and_return(event_loop, wgpu_eframe)
             })
         } else {
-            let event_loop = create_event_loop_builder(&mut native_options).with_any_thread(true).build();
+            let event_loop = create_event_loop_builder(&mut native_options).build();
             let wgpu_eframe = WgpuWinitApp::new(&event_loop, app_name, native_options, app_creator);
             run_and_exit(event_loop, wgpu_eframe);
         }
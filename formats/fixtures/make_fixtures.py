@@ -0,0 +1,199 @@
+#!/usr/bin/env python3
+"""Writes the PE files used by `pe.rs`'s tests. These are the smallest ones the
+parser accepts, with nothing but what's being tested. Run this from `fixtures`."""
+
+import struct
+
+SECTION_RVA = 0x1000
+FILE_ALIGNMENT = 0x200
+
+
+def export_section(dll_name, base, functions, names):
+    """`functions` is a list of RVAs or forwarder strings (0 = unused slot),
+    `names` is a list of (name, index into functions)."""
+    names = sorted(names)
+    # Layout: directory, functions, names, name ordinals, then strings
+    directory_size = 40
+    functions_at = SECTION_RVA + directory_size
+    names_at = functions_at + 4 * len(functions)
+    ordinals_at = names_at + 4 * len(names)
+    strings_at = ordinals_at + 2 * len(names)
+
+    strings = b""
+
+    def add_string(s):
+        nonlocal strings
+        rva = strings_at + len(strings)
+        strings += s.encode() + b"\0"
+        return rva
+
+    dll_name_rva = add_string(dll_name)
+    function_rvas = [add_string(f) if isinstance(f, str) else f for f in functions]
+    name_rvas = [add_string(name) for name, _ in names]
+
+    directory = struct.pack(
+        "<IIHHIIIIIII",
+        0,
+        0,
+        0,
+        0,
+        dll_name_rva,
+        base,
+        len(functions),
+        len(names),
+        functions_at,
+        names_at,
+        ordinals_at,
+    )
+
+    section = directory
+    section += b"".join(struct.pack("<I", rva) for rva in function_rvas)
+    section += b"".join(struct.pack("<I", rva) for rva in name_rvas)
+    section += b"".join(struct.pack("<H", index) for _, index in names)
+    section += strings
+
+    return section
+
+
+def image(sections, directories, pe32_plus=True, timestamp=0):
+    """`sections` is a list of (name, data), placed one after another from
+    `SECTION_RVA`, 0x1000 apart. `directories` maps data directory indices to
+    (section index, size)."""
+    optional_size = 240 if pe32_plus else 224
+    headers = 0x40 + 4 + 20 + optional_size + 40 * len(sections)
+
+    dos = b"MZ" + b"\0" * 0x3A + struct.pack("<I", 0x40)
+    coff = struct.pack(
+        "<HHIIIHH",
+        0x8664 if pe32_plus else 0x14C,
+        len(sections),
+        timestamp,
+        0,
+        0,
+        optional_size,
+        0x2022,
+    )
+
+    optional = struct.pack("<H", 0x20B if pe32_plus else 0x10B)
+    optional += b"\0" * ((108 if pe32_plus else 92) - len(optional))
+    optional += struct.pack("<I", 16)
+    for i in range(16):
+        if i in directories:
+            section, size = directories[i]
+            optional += struct.pack("<II", SECTION_RVA + 0x1000 * section, size)
+        else:
+            optional += struct.pack("<II", 0, 0)
+    assert len(optional) == optional_size
+
+    section_headers = b""
+    raw = b""
+    raw_offset = FILE_ALIGNMENT
+
+    for i, (name, data) in enumerate(sections):
+        raw_size = -(-len(data) // FILE_ALIGNMENT) * FILE_ALIGNMENT
+        section_headers += struct.pack(
+            "<8sIIIIIIHHI",
+            name,
+            len(data),
+            SECTION_RVA + 0x1000 * i,
+            raw_size,
+            raw_offset,
+            0,
+            0,
+            0,
+            0,
+            0x40000040,
+        )
+        raw += data + b"\0" * (raw_size - len(data))
+        raw_offset += raw_size
+
+    image = dos + b"PE\0\0" + coff + optional + section_headers
+    assert len(image) == headers
+    image += b"\0" * (FILE_ALIGNMENT - len(image))
+
+    return image + raw
+
+
+def patched(image, offset, value):
+    """`image` with the u32 at `offset` replaced by `value`."""
+    return image[:offset] + struct.pack("<I", value) + image[offset + 4 :]
+
+
+def dll(section, pe32_plus=True, exports=True):
+    directories = {0: (0, len(section))} if exports else {}
+
+    return image([(b".edata", section)], directories, pe32_plus)
+
+
+def version_resource(rva, version):
+    """Resource section containing only a version resource, at `rva`."""
+
+    def directory(entries):
+        return struct.pack("<IIHHHH", 0, 0, 0, 0, 0, len(entries)) + b"".join(
+            struct.pack("<II", id, offset) for id, offset in entries
+        )
+
+    ms = (version[0] << 16) | version[1]
+    ls = (version[2] << 16) | version[3]
+    key = "VS_VERSION_INFO\0".encode("utf-16-le")
+    fixed = struct.pack(
+        "<13I", 0xFEEF04BD, 0x10000, ms, ls, ms, ls, 0x3F, 0, 0x4, 0x1, 0, 0, 0
+    )
+    info = struct.pack("<HHH", 0, len(fixed), 0) + key
+    info += b"\0" * (-len(info) % 4) + fixed
+    info = struct.pack("<H", len(info)) + info[2:]
+
+    # Type (16 = RT_VERSION) -> name (1) -> language (0x409) -> data
+    root = directory([(16, 0x80000000 | 0x18)])
+    names = directory([(1, 0x80000000 | 0x30)])
+    languages = directory([(0x409, 0x48)])
+    data_offset = 0x58
+    data = struct.pack("<IIII", rva + data_offset, len(info), 0, 0)
+
+    section = root + names + languages + data
+    assert len(section) == data_offset
+
+    return section + info
+
+
+# Ordinals 5..10; 7 is unused, 8 is ordinal-only, 9 is forwarded
+exports = export_section(
+    "fixture.dll",
+    5,
+    [0x2000, 0x2010, 0, 0x2020, "NTDLL.RtlAllocateHeap", 0x2030],
+    [("Beta", 1), ("Alpha", 0), ("Forwarded", 4), ("Last", 5)],
+)
+
+with open("exports64.dll", "wb") as f:
+    f.write(dll(exports))
+
+with open("exports32.dll", "wb") as f:
+    f.write(dll(exports, pe32_plus=False))
+
+with open("no_exports.dll", "wb") as f:
+    f.write(dll(b"\0", exports=False))
+
+# Malformed, so the arithmetic on these overflows. Offsets are of the export
+# directory's RVA and size, and the first section header's VirtualSize and
+# PointerToRawData. The export directory is moved past the start of the section
+# for the last, so finding it overflows
+with open("overflow_export_size.dll", "wb") as f:
+    f.write(patched(dll(exports), 0xCC, 0xFFFFFFFF))
+
+with open("overflow_section_size.dll", "wb") as f:
+    f.write(patched(dll(exports), 0x150, 0xFFFFFFFF))
+
+with open("overflow_raw_offset.dll", "wb") as f:
+    f.write(patched(patched(dll(exports), 0x15C, 0xFFFFFFF0), 0xC8, SECTION_RVA + 0x20))
+
+# An exe with code, a timestamp and a version resource, like SE's
+with open("se.exe", "wb") as f:
+    resources = version_resource(SECTION_RVA + 0x1000, (0, 990, 46, 1830))
+
+    f.write(
+        image(
+            [(b".text", bytes(range(256)) * 4), (b".rsrc", resources)],
+            {2: (1, len(resources))},
+            timestamp=0x64500000,
+        )
+    )
@@ -0,0 +1,6 @@
+//! File formats starb needs to read, that don't depend on Windows. These are
+//! tested on the host, with `cargo test -p starb-formats --target
+//! x86_64-unknown-linux-gnu`.
+
+pub mod pe;
+pub mod vdf;
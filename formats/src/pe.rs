@@ -0,0 +1,538 @@
+//! Just enough of the PE format to read a DLL's export table, and tell SE's
+//! exe apart from other versions of it.
+//!
+//! <https://learn.microsoft.com/en-us/windows/win32/debug/pe-format>
+
+use eyre::bail;
+use eyre::ensure;
+use eyre::eyre;
+use eyre::Result;
+
+/// A single export of a DLL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Export {
+    pub ordinal: u16,
+    /// `None` if this can only be imported by ordinal.
+    pub name: Option<String>,
+    /// Where this export is forwarded to, like `NTDLL.RtlAllocateHeap`.
+    pub forwarder: Option<String>,
+}
+
+/// Every export of a DLL, sorted by ordinal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportTable {
+    /// Name the DLL gives itself. This isn't necessarily its file name.
+    pub dll_name: String,
+    pub exports: Vec<Export>,
+}
+
+/// SE's version, from the version resource. Like `0.990.46.1830`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileVersion(pub [u16; 4usize]);
+
+impl std::fmt::Display for FileVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [major, minor, patch, build] = self.0;
+
+        write!(f, "{major}.{minor}.{patch}.{build}")
+    }
+}
+
+/// Where a section is, in memory and in the file.
+struct Section {
+    name: [u8; 8usize],
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_offset: u32,
+    raw_size: u32,
+}
+
+/// A PE file (exe or DLL), as it is on disk.
+pub struct Pe<'bytes> {
+    bytes: &'bytes [u8],
+    timestamp: u32,
+    /// (RVA, size) of each data directory.
+    directories: Vec<(u32, u32)>,
+    sections: Vec<Section>,
+}
+
+impl<'bytes> Pe<'bytes> {
+    pub fn new(bytes: &'bytes [u8]) -> Result<Self> {
+        ensure!(
+            bytes.get(..2usize) == Some(b"MZ"),
+            "Not a PE file, no DOS header"
+        );
+
+        let pe = __u32(bytes, 0x3Cusize)? as usize;
+
+        ensure!(
+            bytes.get(pe..pe + 4usize) == Some(b"PE\0\0"),
+            "Not a PE file, no PE signature"
+        );
+
+        let coff = pe + 4usize;
+        let section_count = __u16(bytes, coff + 2usize)? as usize;
+        let timestamp = __u32(bytes, coff + 4usize)?;
+        let optional_size = __u16(bytes, coff + 16usize)? as usize;
+        let optional = coff + 20usize;
+
+        // Data directories are 16 bytes further along in PE32+, since ImageBase and
+        // friends are 64-bit
+        let directories = match __u16(bytes, optional)? {
+            0x10Bu16 => optional + 96usize,
+            0x20Bu16 => optional + 112usize,
+            magic => bail!("Unknown optional header magic {magic:#X}"),
+        };
+
+        // NumberOfRvaAndSizes, right before the data directories
+        let directory_count = __u32(bytes, directories - 4usize)? as usize;
+        let directories = (0usize..directory_count.min(16usize))
+            .map(|i| {
+                let directory = directories + i * 8usize;
+
+                Ok((__u32(bytes, directory)?, __u32(bytes, directory + 4usize)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let sections = (0usize..section_count)
+            .map(|i| {
+                let header = optional + optional_size + i * 40usize;
+                let name = bytes
+                    .get(header..header + 8usize)
+                    .ok_or_else(|| eyre!("Unexpected end of file at {header:#X}"))?;
+
+                Ok(Section {
+                    name: name.try_into()?,
+                    virtual_size: __u32(bytes, header + 8usize)?,
+                    virtual_address: __u32(bytes, header + 12usize)?,
+                    raw_size: __u32(bytes, header + 16usize)?,
+                    raw_offset: __u32(bytes, header + 20usize)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            bytes,
+            timestamp,
+            directories,
+            sections,
+        })
+    }
+
+    /// When this was linked, in seconds since 1970. Some linkers put a hash
+    /// here instead, but it's unique either way.
+    #[must_use]
+    pub const fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// Contents of the section `name` (like `.text`) on disk.
+    #[must_use]
+    pub fn section(&self, name: &str) -> Option<&'bytes [u8]> {
+        let section = self.sections.iter().find(|section| {
+            let len = section
+                .name
+                .iter()
+                .position(|&b| b == 0u8)
+                .unwrap_or(8usize);
+
+            &section.name[..len] == name.as_bytes()
+        })?;
+
+        // Raw size is padded, virtual size isn't. Some linkers leave virtual size as
+        // 0, though
+        let start = section.raw_offset as usize;
+        let len = match section.virtual_size {
+            0u32 => section.raw_size,
+            virtual_size => section.raw_size.min(virtual_size),
+        } as usize;
+
+        self.bytes.get(start..start + len)
+    }
+
+    /// Every export, sorted by ordinal. Empty if there's no export table.
+    pub fn exports(&self) -> Result<ExportTable> {
+        let bytes = self.bytes;
+        let (export_rva, export_size) = self.__directory(0usize);
+
+        if export_rva == 0u32 {
+            return Ok(ExportTable {
+                dll_name: String::new(),
+                exports: vec![],
+            });
+        }
+
+        let export_end = export_rva
+            .checked_add(export_size)
+            .ok_or_else(|| eyre!("Export directory at {export_rva:#X} is too large"))?;
+        let file = |rva: u32| self.__rva_to_offset(rva);
+        let directory = file(export_rva)?;
+
+        let dll_name = __str(bytes, file(__u32(bytes, directory + 12usize)?)?)?;
+        let base = __u32(bytes, directory + 16usize)?;
+        let function_count = __u32(bytes, directory + 20usize)? as usize;
+        let name_count = __u32(bytes, directory + 24usize)? as usize;
+        let functions = file(__u32(bytes, directory + 28usize)?)?;
+        let names = file(__u32(bytes, directory + 32usize)?)?;
+        let name_ordinals = file(__u32(bytes, directory + 36usize)?)?;
+
+        let mut exports = vec![];
+
+        for i in 0usize..function_count {
+            let rva = __u32(bytes, functions + i * 4usize)?;
+
+            // Unused slot, between two ordinals that are used
+            if rva == 0u32 {
+                continue;
+            }
+
+            let ordinal = u16::try_from(base as usize + i)
+                .map_err(|e| eyre!("Ordinal {} is too large: {e}", base as usize + i))?;
+
+            // Forwarders point to a string inside of the export directory, rather than
+            // code
+            let forwarder = if (export_rva..export_end).contains(&rva) {
+                Some(__str(bytes, file(rva)?)?)
+            }
+            else {
+                None
+            };
+
+            exports.push(Export {
+                ordinal,
+                name: None,
+                forwarder,
+            });
+        }
+
+        for i in 0usize..name_count {
+            let name = __str(bytes, file(__u32(bytes, names + i * 4usize)?)?)?;
+            // This is an index into the functions, not an ordinal; despite the name
+            let index = __u16(bytes, name_ordinals + i * 2usize)? as usize;
+
+            let Some(export) = exports
+                .iter_mut()
+                .find(|export| export.ordinal as usize == base as usize + index)
+            else {
+                bail!("`{name}` refers to function {index}, which doesn't exist");
+            };
+
+            export.name = Some(name);
+        }
+
+        Ok(ExportTable { dll_name, exports })
+    }
+
+    /// File version from the version resource, if there is one.
+    pub fn file_version(&self) -> Result<Option<FileVersion>> {
+        let bytes = self.bytes;
+        let (resource_rva, _) = self.__directory(2usize);
+
+        if resource_rva == 0u32 {
+            return Ok(None);
+        }
+
+        let root = self.__rva_to_offset(resource_rva)?;
+
+        // Type (RT_VERSION) -> name -> language -> data. There's only ever one
+        // version resource, so take the first name and language
+        let Some(mut entry) = __resource_entry(bytes, root, root, Some(16u32))?
+        else {
+            return Ok(None);
+        };
+
+        for _ in 0usize..2usize {
+            let Some(next) = __resource_entry(bytes, root, entry, None)?
+            else {
+                return Ok(None);
+            };
+
+            entry = next;
+        }
+
+        let data = self.__rva_to_offset(__u32(bytes, entry)?)?;
+        let size = __u32(bytes, entry + 4usize)? as usize;
+        let info = data
+            .checked_add(size)
+            .and_then(|end| bytes.get(data..end))
+            .ok_or_else(|| eyre!("Version resource is past the end of the file"))?;
+
+        // VS_FIXEDFILEINFO starts with this, after a header and a key of a known
+        // length. Searching for it is simpler, though
+        let Some(fixed) = (0usize..info.len().saturating_sub(16usize))
+            .step_by(4usize)
+            .find(|&i| __u32(info, i).ok() == Some(0xFEEF04BDu32))
+        else {
+            bail!("Version resource has no VS_FIXEDFILEINFO");
+        };
+
+        let ms = __u32(info, fixed + 8usize)?;
+        let ls = __u32(info, fixed + 12usize)?;
+
+        Ok(Some(FileVersion([
+            (ms >> 16u32) as u16,
+            ms as u16,
+            (ls >> 16u32) as u16,
+            ls as u16,
+        ])))
+    }
+
+    fn __directory(&self, index: usize) -> (u32, u32) {
+        self.directories.get(index).copied().unwrap_or_default()
+    }
+
+    fn __rva_to_offset(&self, rva: u32) -> Result<usize> {
+        for section in &self.sections {
+            let size = section.virtual_size.max(section.raw_size);
+            let end = section
+                .virtual_address
+                .checked_add(size)
+                .ok_or_else(|| eyre!("Section at {:#X} is too large", section.virtual_address))?;
+
+            if !(section.virtual_address..end).contains(&rva) {
+                continue;
+            }
+
+            return rva
+                .checked_sub(section.virtual_address)
+                .and_then(|offset| offset.checked_add(section.raw_offset))
+                .map(|offset| offset as usize)
+                .ok_or_else(|| eyre!("RVA {rva:#X} is past the end of the file"));
+        }
+
+        bail!("RVA {rva:#X} isn't in any section")
+    }
+}
+
+/// Read the export table of the DLL in `bytes`. Returns an empty table if it
+/// has none.
+pub fn parse(bytes: &[u8]) -> Result<ExportTable> {
+    Pe::new(bytes)?.exports()
+}
+
+/// Read an export list written by hand, for when there's no DLL to read. Each
+/// line is an ordinal, optionally followed by a name. `#` starts a comment.
+pub fn parse_list(dll_name: &str, list: &str) -> Result<ExportTable> {
+    let mut exports = vec![];
+
+    for (i, line) in list.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let ordinal = parts.next().unwrap_or_default();
+        let name = parts.next().map(str::to_owned);
+
+        let Ok(ordinal) = ordinal.parse()
+        else {
+            bail!("Line {}: `{ordinal}` isn't an ordinal", i + 1usize);
+        };
+
+        ensure!(
+            parts.next().is_none(),
+            "Line {}: expected an ordinal and a name, found more",
+            i + 1usize
+        );
+
+        exports.push(Export {
+            ordinal,
+            name,
+            forwarder: None,
+        });
+    }
+
+    exports.sort_by_key(|export| export.ordinal);
+
+    Ok(ExportTable {
+        dll_name: dll_name.to_owned(),
+        exports,
+    })
+}
+
+/// Entry of the resource directory at `directory` with the ID `id`, or the
+/// first if `id` is `None`. Returns where what the entry points to is.
+fn __resource_entry(
+    bytes: &[u8],
+    root: usize,
+    directory: usize,
+    id: Option<u32>,
+) -> Result<Option<usize>> {
+    let named = __u16(bytes, directory + 12usize)? as usize;
+    let ids = __u16(bytes, directory + 14usize)? as usize;
+
+    for i in 0usize..named + ids {
+        let entry = directory + 16usize + i * 8usize;
+        let name = __u32(bytes, entry)?;
+
+        if id.is_some_and(|id| id != name) {
+            continue;
+        }
+
+        // High bit means it's another directory rather than data, either way it's
+        // relative to the root
+        let offset = __u32(bytes, entry + 4usize)? & 0x7FFFFFFFu32;
+
+        return Ok(Some(root + offset as usize));
+    }
+
+    Ok(None)
+}
+
+fn __u16(bytes: &[u8], offset: usize) -> Result<u16> {
+    bytes
+        .get(offset..offset + 2usize)
+        .map(|b| u16::from_le_bytes([b[0usize], b[1usize]]))
+        .ok_or_else(|| eyre!("Unexpected end of file at {offset:#X}"))
+}
+
+fn __u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    bytes
+        .get(offset..offset + 4usize)
+        .map(|b| u32::from_le_bytes([b[0usize], b[1usize], b[2usize], b[3usize]]))
+        .ok_or_else(|| eyre!("Unexpected end of file at {offset:#X}"))
+}
+
+/// Null-terminated string at `offset`.
+fn __str(bytes: &[u8], offset: usize) -> Result<String> {
+    let rest = bytes
+        .get(offset..)
+        .ok_or_else(|| eyre!("Unexpected end of file at {offset:#X}"))?;
+    let len = rest
+        .iter()
+        .position(|&b| b == 0u8)
+        .ok_or_else(|| eyre!("Unterminated string at {offset:#X}"))?;
+
+    Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `fixtures/make_fixtures.py` puts in `exports32.dll` and
+    /// `exports64.dll`.
+    fn __expected() -> ExportTable {
+        let export = |ordinal, name: Option<&str>, forwarder: Option<&str>| Export {
+            ordinal,
+            name: name.map(str::to_owned),
+            forwarder: forwarder.map(str::to_owned),
+        };
+
+        ExportTable {
+            dll_name: "fixture.dll".to_owned(),
+            exports: vec![
+                export(5u16, Some("Alpha"), None),
+                export(6u16, Some("Beta"), None),
+                // 7 is unused
+                export(8u16, None, None),
+                export(9u16, Some("Forwarded"), Some("NTDLL.RtlAllocateHeap")),
+                export(10u16, Some("Last"), None),
+            ],
+        }
+    }
+
+    #[test]
+    fn pe32_plus() -> Result<()> {
+        let table = parse(include_bytes!("../fixtures/exports64.dll"))?;
+
+        assert_eq!(table, __expected());
+
+        Ok(())
+    }
+
+    #[test]
+    fn pe32() -> Result<()> {
+        let table = parse(include_bytes!("../fixtures/exports32.dll"))?;
+
+        assert_eq!(table, __expected());
+
+        Ok(())
+    }
+
+    #[test]
+    fn no_exports() -> Result<()> {
+        let table = parse(include_bytes!("../fixtures/no_exports.dll"))?;
+
+        assert!(table.exports.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = include_bytes!("../fixtures/exports64.dll");
+
+        assert!(parse(&bytes[..0x210usize]).is_err());
+        assert!(parse(b"MZ").is_err());
+        assert!(parse(b"not a dll").is_err());
+    }
+
+    #[test]
+    fn overflow() {
+        let export_size = parse(include_bytes!("../fixtures/overflow_export_size.dll"));
+        let section_size = parse(include_bytes!("../fixtures/overflow_section_size.dll"));
+        let raw_offset = parse(include_bytes!("../fixtures/overflow_raw_offset.dll"));
+
+        assert!(export_size.is_err());
+        assert!(section_size.is_err());
+        assert!(raw_offset.is_err());
+    }
+
+    #[test]
+    fn se() -> Result<()> {
+        let pe = Pe::new(include_bytes!("../fixtures/se.exe"))?;
+
+        assert_eq!(pe.timestamp(), 0x64500000u32);
+        assert_eq!(
+            pe.file_version()?,
+            Some(FileVersion([0u16, 990u16, 46u16, 1830u16]))
+        );
+        assert_eq!(
+            pe.file_version()?.map(|version| version.to_string()),
+            Some("0.990.46.1830".to_owned())
+        );
+
+        let text = pe.section(".text").ok_or_else(|| eyre!("No `.text`"))?;
+
+        assert_eq!(text.len(), 1024usize, "`.text` has the wrong size");
+        assert_eq!(
+            text[..4usize],
+            [0u8, 1u8, 2u8, 3u8],
+            "`.text` has the wrong contents"
+        );
+        assert!(pe.section(".data").is_none());
+        assert!(pe.exports()?.exports.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn no_version() -> Result<()> {
+        let pe = Pe::new(include_bytes!("../fixtures/exports64.dll"))?;
+
+        assert_eq!(pe.file_version()?, None);
+        assert_eq!(pe.timestamp(), 0u32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn list() -> Result<()> {
+        let table = parse_list(
+            "fixture.dll",
+            "# Comment\n10 Last\n5 Alpha\n6 Beta # Trailing comment\n\n8\n9 Forwarded\n",
+        )?;
+
+        let mut expected = __expected();
+        expected.exports[3usize].forwarder = None;
+
+        assert_eq!(table, expected);
+        assert!(parse_list("fixture.dll", "Alpha 5").is_err());
+        assert!(parse_list("fixture.dll", "5 Alpha Beta").is_err());
+
+        Ok(())
+    }
+}
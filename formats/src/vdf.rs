@@ -0,0 +1,202 @@
+//! Valve's `KeyValues` format, which Steam uses for `appmanifest_*.acf` and
+//! friends.
+//!
+//! ```text
+//! "AppState"
+//! {
+//!     "appid"     "314650"
+//!     "buildid"   "11154210"
+//! }
+//! ```
+
+use eyre::bail;
+use eyre::eyre;
+use eyre::Result;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A value, either a string or more key-value pairs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Vdf {
+    String(String),
+    /// In order. Keys can be repeated.
+    Object(Vec<(String, Self)>),
+}
+
+impl Vdf {
+    /// First value under `key`, ignoring case like Steam does.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        match *self {
+            Self::String(_) => None,
+            Self::Object(ref pairs) => pairs
+                .iter()
+                .find(|pair| pair.0.eq_ignore_ascii_case(key))
+                .map(|pair| &pair.1),
+        }
+    }
+
+    /// Follow `path`, like `["AppState", "buildid"]`.
+    #[must_use]
+    pub fn path(&self, path: &[&str]) -> Option<&Self> {
+        path.iter().try_fold(self, |vdf, key| vdf.get(key))
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Self::String(ref string) => Some(string),
+            Self::Object(_) => None,
+        }
+    }
+}
+
+/// Parse a whole file. This is always an object, usually with one key.
+pub fn parse(text: &str) -> Result<Vdf> {
+    let mut chars = text.chars().peekable();
+    let root = __parse_pairs(&mut chars, false)?;
+
+    Ok(Vdf::Object(root))
+}
+
+fn __parse_pairs(chars: &mut Peekable<Chars<'_>>, nested: bool) -> Result<Vec<(String, Vdf)>> {
+    let mut pairs = vec![];
+
+    loop {
+        let key = match __next_token(chars)? {
+            Some(Token::String(key)) => key,
+            Some(Token::Close) if nested => return Ok(pairs),
+            None if !nested => return Ok(pairs),
+            Some(Token::Close) => bail!("Unexpected `}}`"),
+            Some(Token::Open) => bail!("Expected a key, found `{{`"),
+            None => bail!("Expected `}}`, found the end of the file"),
+        };
+
+        let value = match __next_token(chars)? {
+            Some(Token::String(value)) => Vdf::String(value),
+            Some(Token::Open) => Vdf::Object(__parse_pairs(chars, true)?),
+            Some(Token::Close) => bail!("Expected a value for `{key}`, found `}}`"),
+            None => bail!("Expected a value for `{key}`, found the end of the file"),
+        };
+
+        pairs.push((key, value));
+    }
+}
+
+enum Token {
+    String(String),
+    Open,
+    Close,
+}
+
+fn __next_token(chars: &mut Peekable<Chars<'_>>) -> Result<Option<Token>> {
+    loop {
+        let Some(c) = chars.next()
+        else {
+            return Ok(None);
+        };
+
+        match c {
+            c if c.is_whitespace() => {},
+            '{' => return Ok(Some(Token::Open)),
+            '}' => return Ok(Some(Token::Close)),
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            },
+            '"' => return __quoted(chars).map(|string| Some(Token::String(string))),
+            // Unquoted strings are allowed too, they end at whitespace
+            c => {
+                let mut string = c.to_string();
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '{' | '}' | '"') {
+                        break;
+                    }
+
+                    string.push(c);
+                    chars.next();
+                }
+
+                return Ok(Some(Token::String(string)));
+            },
+        }
+    }
+}
+
+fn __quoted(chars: &mut Peekable<Chars<'_>>) -> Result<String> {
+    let mut string = String::new();
+
+    loop {
+        match chars
+            .next()
+            .ok_or_else(|| eyre!("Unterminated string `{string}`"))?
+        {
+            '"' => return Ok(string),
+            '\\' => match chars.next() {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some(c) => string.push(c),
+                None => bail!("Unterminated string `{string}`"),
+            },
+            c => string.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appmanifest() -> Result<()> {
+        let vdf = parse(include_str!("../fixtures/appmanifest_314650.acf"))?;
+
+        assert_eq!(
+            vdf.path(&["AppState", "buildid"]).and_then(Vdf::as_str),
+            Some("11154210")
+        );
+        assert_eq!(
+            vdf.path(&["appstate", "AppID"]).and_then(Vdf::as_str),
+            Some("314650")
+        );
+        assert_eq!(
+            vdf.path(&["AppState", "installdir"]).and_then(Vdf::as_str),
+            Some("SpaceEngine")
+        );
+        assert!(vdf.path(&["AppState", "UserConfig"]).is_some());
+        assert!(vdf.path(&["AppState", "buildid", "nope"]).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn escapes_and_comments() -> Result<()> {
+        let vdf = parse("// Comment\n\"a\\\"b\" \"c\\\\d\\n\" unquoted {\"x\" y} // Trailing")?;
+
+        assert_eq!(
+            vdf,
+            Vdf::Object(vec![
+                ("a\"b".to_owned(), Vdf::String("c\\d\n".to_owned())),
+                (
+                    "unquoted".to_owned(),
+                    Vdf::Object(vec![("x".to_owned(), Vdf::String("y".to_owned()))])
+                ),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse("\"a\" {").is_err());
+        assert!(parse("\"a\"").is_err());
+        assert!(parse("}").is_err());
+        assert!(parse("\"a\" \"b").is_err());
+        assert!(parse("{ \"a\" \"b\" }").is_err());
+    }
+}
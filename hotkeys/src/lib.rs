@@ -0,0 +1,375 @@
+//! Chords like `Ctrl+Shift+F5`, and what they're bound to. This doesn't
+//! depend on Windows (starb polls the keys), so it's tested on the host, with
+//! `cargo test -p starb-hotkeys --target x86_64-unknown-linux-gnu`.
+
+use eyre::bail;
+use eyre::eyre;
+use eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+pub const VK_SHIFT: u16 = 0x10u16;
+pub const VK_CONTROL: u16 = 0x11u16;
+pub const VK_MENU: u16 = 0x12u16;
+
+/// Keys that have a name other than their character, as (name, virtual-key
+/// code). The first of each code is the one that's shown.
+const KEY_NAMES: &[(&str, u16)] = &[
+    ("Backspace", 0x08u16),
+    ("Tab", 0x09u16),
+    ("Enter", 0x0Du16),
+    ("Return", 0x0Du16),
+    ("Pause", 0x13u16),
+    ("Escape", 0x1Bu16),
+    ("Esc", 0x1Bu16),
+    ("Space", 0x20u16),
+    ("PageUp", 0x21u16),
+    ("PgUp", 0x21u16),
+    ("PageDown", 0x22u16),
+    ("PgDn", 0x22u16),
+    ("End", 0x23u16),
+    ("Home", 0x24u16),
+    ("Left", 0x25u16),
+    ("Up", 0x26u16),
+    ("Right", 0x27u16),
+    ("Down", 0x28u16),
+    ("Insert", 0x2Du16),
+    ("Ins", 0x2Du16),
+    ("Delete", 0x2Eu16),
+    ("Del", 0x2Eu16),
+    ("NumpadMultiply", 0x6Au16),
+    ("NumpadAdd", 0x6Bu16),
+    ("NumpadSubtract", 0x6Du16),
+    ("NumpadDecimal", 0x6Eu16),
+    ("NumpadDivide", 0x6Fu16),
+];
+
+/// A key, and exactly which modifiers must be held with it.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Chord {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    /// Virtual-key code.
+    pub key: u16,
+}
+
+impl Chord {
+    /// Whether this was just pressed, i.e., its key is down in `now` but wasn't
+    /// in `before`, and exactly its modifiers are held. Both take a
+    /// virtual-key code, and return whether it's down.
+    pub fn pressed(self, before: impl Fn(u16) -> bool, now: impl Fn(u16) -> bool) -> bool {
+        !before(self.key)
+            && now(self.key)
+            && now(VK_CONTROL) == self.ctrl
+            && now(VK_SHIFT) == self.shift
+            && now(VK_MENU) == self.alt
+    }
+}
+
+impl FromStr for Chord {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut chord = Self {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            key: 0u16,
+        };
+        let mut key = None;
+
+        for part in s.split('+').map(str::trim) {
+            let modifier = match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut chord.ctrl,
+                "shift" => &mut chord.shift,
+                "alt" => &mut chord.alt,
+                _ => {
+                    let Some(code) = __key_code(part)
+                    else {
+                        bail!("`{part}` isn't a key starb knows");
+                    };
+
+                    if key.replace(code).is_some() {
+                        bail!("`{s}` has more than one key, only modifiers can be combined");
+                    }
+
+                    continue;
+                },
+            };
+
+            if *modifier {
+                bail!("`{part}` is in `{s}` twice");
+            }
+
+            *modifier = true;
+        }
+
+        chord.key = key.ok_or_else(|| eyre!("`{s}` has no key, only modifiers"))?;
+
+        Ok(chord)
+    }
+}
+
+impl TryFrom<String> for Chord {
+    type Error = eyre::Report;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Chord> for String {
+    fn from(chord: Chord) -> Self {
+        chord.to_string()
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+
+        match __key_name(self.key) {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "{:#04X}", self.key),
+        }
+    }
+}
+
+/// What a binding does.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", content = "name", rename_all = "snake_case")]
+pub enum Target {
+    /// Flip one of the plugin's on/off settings.
+    Toggle(String),
+    /// Run one of the plugin's actions, by name.
+    Action(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Toggle(ref name) => write!(f, "Toggle `{name}`"),
+            Self::Action(ref name) => write!(f, "Run `{name}`"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Binding {
+    pub chord: Chord,
+    /// Key of the plugin.
+    pub plugin: String,
+    pub target: Target,
+}
+
+/// Indices of every pair of bindings with the same chord.
+#[must_use]
+pub fn conflicts(bindings: &[Binding]) -> Vec<(usize, usize)> {
+    let mut conflicts = vec![];
+
+    for (i, a) in bindings.iter().enumerate() {
+        for (j, b) in bindings.iter().enumerate().skip(i + 1usize) {
+            if a.chord == b.chord {
+                conflicts.push((i, j));
+            }
+        }
+    }
+
+    conflicts
+}
+
+fn __key_code(name: &str) -> Option<u16> {
+    let upper = name.to_ascii_uppercase();
+
+    // A-Z and 0-9 are their own code
+    if let [c @ (b'A'..=b'Z' | b'0'..=b'9')] = *upper.as_bytes() {
+        return Some(u16::from(c));
+    }
+
+    if let Some(n) = upper
+        .strip_prefix('F')
+        .and_then(|n| n.parse::<u16>().ok())
+        .filter(|n| (1u16..=24u16).contains(n))
+    {
+        return Some(0x6Fu16 + n);
+    }
+
+    if let Some(n) = upper
+        .strip_prefix("NUMPAD")
+        .and_then(|n| n.parse::<u16>().ok())
+        .filter(|n| *n <= 9u16)
+    {
+        return Some(0x60u16 + n);
+    }
+
+    KEY_NAMES
+        .iter()
+        .find(|key| key.0.eq_ignore_ascii_case(name))
+        .map(|key| key.1)
+}
+
+fn __key_name(code: u16) -> Option<String> {
+    match code {
+        0x30u16..=0x39u16 | 0x41u16..=0x5Au16 => Some(char::from(code as u8).to_string()),
+        0x60u16..=0x69u16 => Some(format!("Numpad{}", code - 0x60u16)),
+        0x70u16..=0x87u16 => Some(format!("F{}", code - 0x6Fu16)),
+        _ => KEY_NAMES
+            .iter()
+            .find(|key| key.1 == code)
+            .map(|key| key.0.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn __binding(chord: &str, target: &str) -> Result<Binding> {
+        Ok(Binding {
+            chord: chord.parse()?,
+            plugin: "no_search_locking".to_owned(),
+            target: Target::Toggle(target.to_owned()),
+        })
+    }
+
+    #[test]
+    fn parse() -> Result<()> {
+        let chord = "ctrl + shift+F5".parse::<Chord>()?;
+
+        assert!(
+            chord.ctrl && chord.shift && !chord.alt,
+            "Wrong modifiers: {chord:?}"
+        );
+        assert_eq!(chord.key, 0x74u16, "Wrong key: {chord:?}");
+        assert_eq!(chord.to_string(), "Ctrl+Shift+F5", "Wrong name");
+
+        assert_eq!(
+            "Alt+esc".parse::<Chord>()?.to_string(),
+            "Alt+Escape",
+            "Aliases should be shown as their first name"
+        );
+        assert_eq!("numpad7".parse::<Chord>()?.key, 0x67u16, "Wrong numpad key");
+        assert_eq!(
+            "Q".parse::<Chord>()?.key,
+            u16::from(b'Q'),
+            "Wrong letter key"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        for chord in ["", "Ctrl+Shift", "Ctrl+Ctrl+A", "A+B", "F25", "Hyper+A"] {
+            assert!(
+                chord.parse::<Chord>().is_err(),
+                "`{chord}` should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        for code in (0u16..=0xFFu16).filter(|code| __key_name(*code).is_some()) {
+            let chord = Chord {
+                ctrl: true,
+                shift: false,
+                alt: true,
+                key: code,
+            };
+
+            assert_eq!(
+                chord.to_string().parse::<Chord>()?,
+                chord,
+                "{code:#04X} doesn't roundtrip"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn pressed() -> Result<()> {
+        let chord = "Ctrl+F5".parse::<Chord>()?;
+        let down = |keys: &'static [u16]| move |key| keys.contains(&key);
+
+        assert!(
+            chord.pressed(down(&[VK_CONTROL]), down(&[VK_CONTROL, 0x74u16])),
+            "Should be pressed when it goes down"
+        );
+        assert!(
+            !chord.pressed(down(&[VK_CONTROL, 0x74u16]), down(&[VK_CONTROL, 0x74u16])),
+            "Shouldn't be pressed while it's held"
+        );
+        assert!(
+            !chord.pressed(down(&[]), down(&[0x74u16])),
+            "Modifiers must match exactly"
+        );
+        assert!(
+            !chord.pressed(down(&[]), down(&[VK_CONTROL, VK_SHIFT, 0x74u16])),
+            "Modifiers must match exactly"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn conflicting() -> Result<()> {
+        let bindings = vec![
+            __binding("Ctrl+F5", "enabled")?,
+            __binding("Ctrl+F6", "enabled")?,
+            __binding("control+f5", "other")?,
+        ];
+
+        assert_eq!(
+            conflicts(&bindings),
+            [(0usize, 2usize)],
+            "Only the same chords should conflict"
+        );
+        assert!(
+            conflicts(&[
+                __binding("F5", "enabled")?,
+                __binding("Ctrl+F5", "enabled")?
+            ])
+            .is_empty(),
+            "Different modifiers shouldn't conflict"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn serde() -> Result<()> {
+        let binding = __binding("Shift+Space", "enabled")?;
+        let json = serde_json::to_value(&binding)?;
+
+        assert_eq!(json["chord"], "Shift+Space", "Chords should be strings");
+        assert_eq!(
+            json["target"],
+            json!({ "type": "toggle", "name": "enabled" }),
+            "Wrong target"
+        );
+        assert_eq!(
+            serde_json::from_value::<Binding>(json)?,
+            binding,
+            "Doesn't roundtrip"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,751 @@
+//! starb's control API, so scripts and the like can poke at starb without
+//! clicking around in the GUI. This is the protocol, server and client; what
+//! each method actually does is up to starb's [`Handler`]. It doesn't depend on
+//! Windows, so it's tested on the host, with `cargo test -p starb-ipc --target
+//! x86_64-unknown-linux-gnu`.
+//!
+//! This speaks JSON-RPC 2.0 over localhost TCP, one message per line. Any web
+//! page can send requests to localhost, so every connection must start with
+//! `auth`, with the token starb writes to [`TOKEN_FILE`] next to SE's exe when
+//! it starts. Anything that isn't a valid request (like HTTP) closes the
+//! connection. Methods:
+//!
+//! * `auth`: `{ "token": .. }`. Must be the first request.
+//! * `plugins.list`: Every plugin, with its settings.
+//! * `plugins.get`: `{ "key": .. }`. Settings of a single plugin.
+//! * `plugins.set`: `{ "key": .., "settings": { .. } }`. Change a plugin's
+//!   settings. Fields not in `settings` are left as they are.
+//! * `plugins.import`: `{ "settings": { <key>: { .. } } }`. Same as
+//!   `plugins.set` for each plugin, like when importing a profile. Returns
+//!   `imported`, each plugin's settings, and `failed`, each plugin's error.
+//! * `context.get`: `{ "key": .. }` (optional). What the context tab shows.
+//! * `restart.list`: Which plugins want SE restarted, and why.
+//! * `selftest.run`: Read back every patch and hook, same as the context tab's
+//!   self-test.
+//! * `search.history`: How long past searches took, and every plugin's settings
+//!   at the time.
+//! * `events.subscribe`: Receive an `event` notification for every event, until
+//!   the connection is closed.
+
+use eyre::bail;
+use eyre::eyre;
+use eyre::Result;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::debug;
+use tracing::trace;
+use tracing::warn;
+
+/// Address the control API listens on, unless overridden by `STARB_IPC_ADDR`.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:46231";
+/// File next to SE's exe with the token of the running starb's control API.
+pub const TOKEN_FILE: &str = "starb_ipc_token";
+
+/// How long to wait on a client before giving up on writing to it.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1u64);
+/// How every HTTP request line starts. These are closed without an answer,
+/// since whatever sent them isn't speaking this.
+const HTTP_METHODS: [&str; 9usize] = [
+    "CONNECT ", "DELETE ", "GET ", "HEAD ", "OPTIONS ", "PATCH ", "POST ", "PUT ", "TRACE ",
+];
+
+/// Both the connection's own thread and [`Server::notify`] write to it, so
+/// this must be locked to not interleave messages.
+type Writer = Arc<Mutex<TcpStream>>;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const INVALID_PARAMS: i64 = -32602i64;
+    pub const INVALID_REQUEST: i64 = -32600i64;
+    pub const METHOD_NOT_FOUND: i64 = -32601i64;
+    pub const PARSE_ERROR: i64 = -32700i64;
+    pub const SERVER_ERROR: i64 = -32000i64;
+    /// Missing or wrong `auth`.
+    pub const UNAUTHORIZED: i64 = -32001i64;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<eyre::Report> for RpcError {
+    fn from(e: eyre::Report) -> Self {
+        Self::new(Self::SERVER_ERROR, e.to_string())
+    }
+}
+
+/// What the control API's methods do. Plugins are referred to by their key.
+pub trait Handler: Send + Sync + 'static {
+    /// Every plugin, with its settings.
+    fn plugins(&self) -> Value;
+
+    /// A plugin's settings. `None` if there's no such plugin.
+    fn settings(&self, key: &str) -> Option<Value>;
+
+    /// Change some of a plugin's settings, returning all of them afterwards.
+    /// `None` if there's no such plugin.
+    fn set(&self, key: &str, changes: &Map<String, Value>) -> Option<Result<Value>>;
+
+    /// Called after `plugins.import`, with the plugins it changed.
+    fn imported(&self, keys: Vec<String>);
+
+    /// What the context tab shows, for every plugin if `key` is `None`.
+    fn context(&self, key: Option<&str>) -> Value;
+
+    fn restarts(&self) -> Value;
+
+    fn self_test(&self) -> Value;
+
+    fn search_history(&self) -> Value;
+}
+
+/// The control API, for a [`Handler`].
+pub struct Server<H: Handler> {
+    handler: H,
+    token: String,
+    /// Connections that called `events.subscribe`.
+    subscribers: Mutex<Vec<Writer>>,
+}
+
+impl<H: Handler> Server<H> {
+    /// Clients must `auth` with `token` first, see [`write_token`].
+    pub const fn new(handler: H, token: String) -> Self {
+        Self {
+            handler,
+            token,
+            subscribers: Mutex::new(vec![]),
+        }
+    }
+
+    /// Accept connections on `listener` forever, each on their own thread.
+    pub fn serve(server: &Arc<Self>, listener: &TcpListener) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = Arc::clone(server);
+
+                    thread::spawn(move || {
+                        if let Err(e) = server.__handle_connection(stream) {
+                            debug!("Control API connection closed: {e}");
+                        }
+                    });
+                },
+                Err(e) => warn!("Failed to accept control API connection: {e}"),
+            }
+        }
+    }
+
+    /// Send a notification to everyone who subscribed. Subscribers that went
+    /// away are dropped.
+    pub fn notify(&self, method: &str, params: &impl Serialize) {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+
+        trace!(%message, "Notifying subscribers");
+
+        self.subscribers
+            .lock()
+            .retain(|writer| __write_message(writer, &message).is_ok());
+    }
+
+    fn __handle_connection(&self, stream: TcpStream) -> Result<()> {
+        let peer = stream.peer_addr()?;
+
+        // Don't let a client that stopped reading hang whoever's notifying it
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+        let writer = Arc::new(Mutex::new(stream.try_clone()?));
+        let mut authenticated = false;
+
+        debug!(%peer, "Control API connection opened");
+
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // Like a browser's `fetch`. Its body could be a valid request, so
+            // this can't wait until the headers fail to parse
+            if HTTP_METHODS.iter().any(|method| line.starts_with(method)) {
+                bail!("Got HTTP from {peer}");
+            }
+
+            let request = match __parse(&line) {
+                Ok(request) => request,
+                Err(e) => {
+                    let message = e.message.clone();
+
+                    __write_message(&writer, &__response(&Value::Null, Err(e)))?;
+
+                    bail!("Invalid request from {peer}: {message}");
+                },
+            };
+
+            trace!(?request, "Control API request");
+
+            let result = if authenticated {
+                match request.method.as_str() {
+                    "auth" => Ok(Value::Bool(true)),
+                    "events.subscribe" => {
+                        self.subscribers.lock().push(Arc::clone(&writer));
+
+                        Ok(Value::Bool(true))
+                    },
+                    method => __dispatch(&self.handler, method, &request.params),
+                }
+            }
+            else {
+                let result = self.__auth(&request);
+
+                authenticated = result.is_ok();
+
+                result.map(|()| Value::Bool(true))
+            };
+
+            // No id = notification, which is never answered
+            if let Some(id) = request.id.as_ref() {
+                __write_message(&writer, &__response(id, result))?;
+            }
+
+            if !authenticated {
+                bail!("{peer} didn't authenticate");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn __auth(&self, request: &Request) -> Result<(), RpcError> {
+        if request.method != "auth" {
+            return Err(RpcError::new(
+                RpcError::UNAUTHORIZED,
+                "`auth` must be called first",
+            ));
+        }
+
+        let token = __param_str(&request.params, "token")?;
+
+        // Compare all of it either way, so it can't be guessed a byte at a time
+        let matches = token.len() == self.token.len()
+            && token
+                .bytes()
+                .zip(self.token.bytes())
+                .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+                == 0u8;
+
+        if matches {
+            Ok(())
+        }
+        else {
+            Err(RpcError::new(RpcError::UNAUTHORIZED, "Wrong token"))
+        }
+    }
+}
+
+/// Connection to a running starb's control API.
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    next_id: u64,
+}
+
+impl Client {
+    /// Use `stream`, which is connected to starb, and authenticate with
+    /// `token`.
+    pub fn new(stream: TcpStream, token: &str) -> Result<Self> {
+        let mut client = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            next_id: 0u64,
+        };
+
+        client.call("auth", json!({ "token": token }))?;
+
+        Ok(client)
+    }
+
+    /// Call `method`, and wait for its result.
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1u64;
+
+        let mut request = Map::new();
+
+        request.insert("jsonrpc".to_owned(), json!("2.0"));
+        request.insert("id".to_owned(), json!(id));
+        request.insert("method".to_owned(), json!(method));
+        request.insert("params".to_owned(), params);
+
+        serde_json::to_writer(&mut self.writer, &request)?;
+        self.writer.write_all(b"\n")?;
+
+        loop {
+            let mut message = self.__read()?;
+
+            // Notifications can arrive at any time, these aren't what we're waiting for
+            if message["id"] != id {
+                continue;
+            }
+
+            if let Some(error) = message.get("error") {
+                bail!("{}", error["message"].as_str().unwrap_or("Unknown error"));
+            }
+
+            return Ok(message["result"].take());
+        }
+    }
+
+    /// Wait for the next notification, like an `event`.
+    pub fn next_notification(&mut self) -> Result<Value> {
+        loop {
+            let message = self.__read()?;
+
+            if message.get("id").is_none() {
+                return Ok(message);
+            }
+        }
+    }
+
+    fn __read(&mut self) -> Result<Value> {
+        let mut line = String::new();
+
+        if self.reader.read_line(&mut line)? == 0usize {
+            bail!("starb closed the connection");
+        }
+
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+/// Make a new token and write it to [`TOKEN_FILE`] in `folder`, for
+/// `starb-ctl` to read.
+pub fn write_token(folder: &Path) -> Result<String> {
+    // `RandomState` is seeded randomly by the OS, which is plenty for
+    // something web pages can't read anyway
+    let token = (0u64..2u64)
+        .map(|i| {
+            let mut hasher = RandomState::new().build_hasher();
+
+            hasher.write_u64(i);
+
+            format!("{:016x}", hasher.finish())
+        })
+        .collect::<String>();
+
+    fs::write(folder.join(TOKEN_FILE), &token)?;
+
+    Ok(token)
+}
+
+/// Read the token [`write_token`] wrote to `folder`.
+pub fn read_token(folder: &Path) -> Result<String> {
+    let path = folder.join(TOKEN_FILE);
+
+    fs::read_to_string(&path)
+        .map(|token| token.trim().to_owned())
+        .map_err(|e| eyre!("Couldn't read {}: {e}", path.display()))
+}
+
+fn __parse(line: &str) -> Result<Request, RpcError> {
+    let message = serde_json::from_str::<Value>(line)
+        .map_err(|e| RpcError::new(RpcError::PARSE_ERROR, e.to_string()))?;
+
+    serde_json::from_value(message)
+        .map_err(|e| RpcError::new(RpcError::INVALID_REQUEST, e.to_string()))
+}
+
+fn __response(id: &Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message },
+        }),
+    }
+}
+
+fn __dispatch(handler: &impl Handler, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "plugins.list" => Ok(handler.plugins()),
+        "plugins.get" => {
+            let key = __param_str(params, "key")?;
+
+            handler.settings(key).ok_or_else(|| __no_plugin(key))
+        },
+        "plugins.set" => {
+            let key = __param_str(params, "key")?;
+
+            __set(handler, key, __param_object(params, "settings")?)
+        },
+        "plugins.import" => {
+            let mut imported = Map::new();
+            let mut failed = Map::new();
+
+            for (key, changes) in __param_object(params, "settings")? {
+                let result = match *changes {
+                    Value::Object(ref changes) => __set(handler, key, changes),
+                    _ => Err(RpcError::new(
+                        RpcError::INVALID_PARAMS,
+                        "Settings must be an object",
+                    )),
+                };
+
+                match result {
+                    Ok(settings) => imported.insert(key.clone(), settings),
+                    Err(e) => failed.insert(key.clone(), Value::String(e.message)),
+                };
+            }
+
+            handler.imported(imported.keys().cloned().collect());
+
+            Ok(json!({ "imported": imported, "failed": failed }))
+        },
+        "context.get" => Ok(handler.context(params.get("key").and_then(Value::as_str))),
+        "restart.list" => Ok(handler.restarts()),
+        "selftest.run" => Ok(handler.self_test()),
+        "search.history" => Ok(handler.search_history()),
+        method => Err(RpcError::new(
+            RpcError::METHOD_NOT_FOUND,
+            format!("Unknown method `{method}`"),
+        )),
+    }
+}
+
+fn __set(
+    handler: &impl Handler,
+    key: &str,
+    changes: &Map<String, Value>,
+) -> Result<Value, RpcError> {
+    Ok(handler
+        .set(key, changes)
+        .ok_or_else(|| __no_plugin(key))??)
+}
+
+fn __no_plugin(key: &str) -> RpcError {
+    RpcError::new(
+        RpcError::INVALID_PARAMS,
+        format!("No plugin with key `{key}`"),
+    )
+}
+
+fn __param_str<'params>(params: &'params Value, name: &str) -> Result<&'params str, RpcError> {
+    params.get(name).and_then(Value::as_str).ok_or_else(|| {
+        RpcError::new(
+            RpcError::INVALID_PARAMS,
+            format!("Missing string parameter `{name}`"),
+        )
+    })
+}
+
+fn __param_object<'params>(
+    params: &'params Value,
+    name: &str,
+) -> Result<&'params Map<String, Value>, RpcError> {
+    params.get(name).and_then(Value::as_object).ok_or_else(|| {
+        RpcError::new(
+            RpcError::INVALID_PARAMS,
+            format!("`{name}` must be an object"),
+        )
+    })
+}
+
+fn __write_message(writer: &Writer, message: &Value) -> Result<()> {
+    let mut writer = writer.lock();
+
+    serde_json::to_writer(&mut *writer, message)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Read;
+    use std::net::SocketAddr;
+    use std::process;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    /// One plugin, `fake`, with a `max` setting.
+    #[derive(Default)]
+    struct Fake {
+        max: Mutex<u32>,
+        imported: Mutex<Vec<String>>,
+    }
+
+    impl Handler for Fake {
+        fn plugins(&self) -> Value {
+            json!([{ "key": "fake", "settings": { "max": *self.max.lock() } }])
+        }
+
+        fn settings(&self, key: &str) -> Option<Value> {
+            (key == "fake").then(|| json!({ "max": *self.max.lock() }))
+        }
+
+        fn set(&self, key: &str, changes: &Map<String, Value>) -> Option<Result<Value>> {
+            (key == "fake").then(|| {
+                if let Some(max) = changes.get("max") {
+                    *self.max.lock() = serde_json::from_value(max.clone())?;
+                }
+
+                Ok(json!({ "max": *self.max.lock() }))
+            })
+        }
+
+        fn imported(&self, keys: Vec<String>) {
+            *self.imported.lock() = keys;
+        }
+
+        fn context(&self, key: Option<&str>) -> Value {
+            json!({ "key": key })
+        }
+
+        fn restarts(&self) -> Value {
+            json!([])
+        }
+
+        fn self_test(&self) -> Value {
+            json!({ "passed": true })
+        }
+
+        fn search_history(&self) -> Value {
+            json!([])
+        }
+    }
+
+    /// Serve `Fake` on a port of its own.
+    fn __serve() -> Result<(Arc<Server<Fake>>, SocketAddr)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = Arc::new(Server::new(Fake::default(), TOKEN.to_owned()));
+        let serving = Arc::clone(&server);
+
+        thread::spawn(move || Server::serve(&serving, &listener));
+
+        Ok((server, addr))
+    }
+
+    fn __connect(addr: SocketAddr) -> Result<TcpStream> {
+        let stream = TcpStream::connect(addr)?;
+
+        // Rather than hanging the tests if something's wrong
+        stream.set_read_timeout(Some(Duration::from_secs(5u64)))?;
+
+        Ok(stream)
+    }
+
+    /// Send `lines` as is, and read everything until starb closes the
+    /// connection.
+    fn __send_raw(addr: SocketAddr, lines: &str) -> Result<String> {
+        let mut stream = __connect(addr)?;
+        let mut response = String::new();
+
+        stream.write_all(lines.as_bytes())?;
+        stream.read_to_string(&mut response)?;
+
+        Ok(response)
+    }
+
+    #[test]
+    fn dispatch() -> Result<()> {
+        let fake = Fake::default();
+
+        assert_eq!(
+            __dispatch(
+                &fake,
+                "plugins.set",
+                &json!({ "key": "fake", "settings": { "max": 5u32 } })
+            ),
+            Ok(json!({ "max": 5u32 }))
+        );
+        assert_eq!(
+            __dispatch(&fake, "plugins.get", &json!({ "key": "fake" })),
+            Ok(json!({ "max": 5u32 }))
+        );
+        assert_eq!(
+            __dispatch(&fake, "plugins.get", &json!({ "key": "missing" })).map_err(|e| e.code),
+            Err(RpcError::INVALID_PARAMS)
+        );
+        assert_eq!(
+            __dispatch(
+                &fake,
+                "plugins.set",
+                &json!({ "key": "fake", "settings": 5u32 })
+            )
+            .map_err(|e| e.code),
+            Err(RpcError::INVALID_PARAMS)
+        );
+        assert_eq!(
+            __dispatch(&fake, "missing", &Value::Null).map_err(|e| e.code),
+            Err(RpcError::METHOD_NOT_FOUND)
+        );
+
+        // A plugin with a setting called `error` isn't mistaken for a failure
+        let imported = __dispatch(
+            &fake,
+            "plugins.import",
+            &json!({ "settings": {
+                "fake": { "max": 7u32 },
+                "missing": {},
+                "bad": 5u32,
+            } }),
+        )
+        .map_err(|e| eyre!("{}", e.message))?;
+
+        assert_eq!(imported["imported"], json!({ "fake": { "max": 7u32 } }));
+        assert_eq!(
+            imported["failed"]
+                .as_object()
+                .map(|failed| failed.keys().cloned().collect::<Vec<_>>()),
+            Some(vec!["bad".to_owned(), "missing".to_owned()])
+        );
+        assert_eq!(*fake.imported.lock(), ["fake"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn loopback() -> Result<()> {
+        let (server, addr) = __serve()?;
+        let mut client = Client::new(__connect(addr)?, TOKEN)?;
+        let mut subscriber = Client::new(__connect(addr)?, TOKEN)?;
+
+        assert_eq!(
+            client.call("plugins.list", Value::Null)?,
+            json!([{ "key": "fake", "settings": { "max": 0u32 } }])
+        );
+        assert_eq!(
+            subscriber.call("events.subscribe", Value::Null)?,
+            Value::Bool(true)
+        );
+
+        server.notify("event", &json!({ "event": "test" }));
+
+        assert_eq!(
+            subscriber.next_notification()?["params"],
+            json!({ "event": "test" })
+        );
+
+        // Errors from the handler are answered, and don't close the connection
+        assert!(
+            client
+                .call("plugins.set", json!({ "key": "missing", "settings": {} }))
+                .is_err(),
+            "Set a plugin that doesn't exist"
+        );
+        assert_eq!(
+            client.call(
+                "plugins.set",
+                json!({ "key": "fake", "settings": { "max": 20u32 } })
+            )?,
+            json!({ "max": 20u32 })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unauthorized() -> Result<()> {
+        let (server, addr) = __serve()?;
+
+        assert!(
+            Client::new(__connect(addr)?, "wrong").is_err(),
+            "Connected with the wrong token"
+        );
+
+        // Anything but `auth` first is answered, then closed
+        let set = json!({
+            "jsonrpc": "2.0",
+            "id": 1u32,
+            "method": "plugins.set",
+            "params": { "key": "fake", "settings": { "max": 5u32 } },
+        });
+        let response = __send_raw(addr, &format!("{set}\n{set}\n"))?;
+
+        assert_eq!(response.lines().count(), 1usize, "{response}");
+        assert!(response.contains("-32001"), "{response}");
+
+        // Like a browser's `fetch`. The body's valid, but isn't even looked at
+        let response = __send_raw(
+            addr,
+            &format!("POST / HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n{set}\n"),
+        )?;
+
+        assert_eq!(response, "");
+
+        // Same with a bad line after authenticating
+        let auth = json!({
+            "jsonrpc": "2.0",
+            "id": 0u32,
+            "method": "auth",
+            "params": { "token": TOKEN },
+        });
+        let response = __send_raw(addr, &format!("{auth}\nnot json\n{set}\n"))?;
+
+        assert_eq!(response.lines().count(), 2usize, "{response}");
+        assert!(response.contains("-32700"), "{response}");
+
+        assert_eq!(*server.handler.max.lock(), 0u32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn token() -> Result<()> {
+        let dir = env::temp_dir().join(format!("starb_ipc_{}", process::id()));
+
+        fs::create_dir_all(&dir)?;
+
+        let token = write_token(&dir)?;
+
+        assert_eq!(token.len(), 32usize);
+        assert_eq!(read_token(&dir)?, token);
+        assert_ne!(write_token(&dir)?, token);
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
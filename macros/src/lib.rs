@@ -0,0 +1,180 @@
+//! Proc macros for starb. These are re-exported by `speng-starb`, use them from
+//! there.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::format_ident;
+use quote::quote;
+use syn::parse_macro_input;
+use syn::Data;
+use syn::DeriveInput;
+use syn::Error;
+use syn::Fields;
+use syn::LitInt;
+use syn::Result;
+
+/// Typed access to a struct in SE's memory, without raw offsets. See
+/// `speng_starb::game` for how to use it.
+///
+/// Every field needs `#[game(offset = ..)]`. The struct can have
+/// `#[game(size = ..)]`, which checks every field fits at compile time.
+/// Otherwise, it ends after its last field, padded to its alignment.
+#[proc_macro_derive(GameStruct, attributes(game))]
+pub fn derive_game_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    __game_struct(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn __game_struct(input: &DeriveInput) -> Result<proc_macro2::TokenStream> {
+    let Data::Struct(data) = &input.data
+    else {
+        return Err(Error::new(
+            Span::call_site(),
+            "Only structs can be game structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields
+    else {
+        return Err(Error::new(
+            Span::call_site(),
+            "Game structs need named fields",
+        ));
+    };
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "Game structs can't be generic",
+        ));
+    }
+
+    let size = __attr(&input.attrs, "size")?;
+    let (vis, name) = (&input.vis, &input.ident);
+    let handle = format_ident!("{name}Ref");
+    let handle_doc = format!("A [`{name}`] in SE's memory.");
+
+    let mut accessors = vec![];
+    let mut reads = vec![];
+    let mut ends = vec![];
+    let mut checks = vec![];
+
+    for field in &fields.named {
+        let ident = field.ident.as_ref().expect("Fields are named");
+        let Some(offset) = __attr(&field.attrs, "offset")?
+        else {
+            return Err(Error::new_spanned(field, "Missing `#[game(offset = ..)]`"));
+        };
+        let (vis, ty) = (&field.vis, &field.ty);
+        let docs = field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("doc"));
+        let offset_doc = format!("At offset `{offset:#X}`.");
+
+        accessors.push(quote! {
+            #(#docs)*
+            ///
+            #[doc = #offset_doc]
+            #[must_use]
+            #vis fn #ident(self) -> <#ty as ::speng_starb::game::Field>::Ref {
+                ::speng_starb::game::Located::at(self.address.wrapping_add(#offset))
+            }
+        });
+        reads.push(quote! {
+            #ident: <#ty as ::speng_starb::game::Field>::read(address.wrapping_add(#offset))?
+        });
+        ends.push(quote! { #offset + <#ty as ::speng_starb::game::Field>::SIZE });
+
+        if let Some(size) = size {
+            let message = format!("`{ident}` doesn't fit in `{name}`'s size of {size:#X}");
+
+            checks.push(quote! {
+                ::core::assert!(
+                    #offset + <#ty as ::speng_starb::game::Field>::SIZE <= #size,
+                    #message
+                );
+            });
+        }
+    }
+
+    let size = match size {
+        Some(size) => quote! { #size },
+        None => {
+            let end = ends.into_iter().fold(quote! { 0usize }, |size, end| {
+                quote! { ::speng_starb::game::__private::max(#size, #end) }
+            });
+
+            quote! {
+                ::speng_starb::game::__private::round_up(
+                    #end,
+                    ::core::mem::align_of::<#name>(),
+                )
+            }
+        },
+    };
+
+    Ok(quote! {
+        #[doc = #handle_doc]
+        #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+        #vis struct #handle {
+            address: usize,
+        }
+
+        impl #handle {
+            #(#accessors)*
+
+            /// Copy the whole thing out of SE's memory.
+            #vis fn read(self) -> ::speng_starb::game::__private::Result<#name> {
+                <#name as ::speng_starb::game::Field>::read(self.address)
+            }
+        }
+
+        impl ::speng_starb::game::Located for #handle {
+            fn at(address: usize) -> Self {
+                Self { address }
+            }
+
+            fn address(self) -> usize {
+                self.address
+            }
+        }
+
+        impl ::speng_starb::game::Field for #name {
+            type Ref = #handle;
+
+            const SIZE: usize = #size;
+
+            fn read(address: usize) -> ::speng_starb::game::__private::Result<Self> {
+                ::core::result::Result::Ok(Self {
+                    #(#reads,)*
+                })
+            }
+        }
+
+        const _: () = {
+            #(#checks)*
+        };
+    })
+}
+
+/// Value of `#[game(name = ..)]`, if it's there.
+fn __attr(attrs: &[syn::Attribute], name: &str) -> Result<Option<usize>> {
+    let mut value = None;
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("game")) {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident(name) {
+                return Err(meta.error(format!("Expected `{name}`")));
+            }
+
+            value = Some(meta.value()?.parse::<LitInt>()?.base10_parse::<usize>()?);
+
+            Ok(())
+        })?;
+    }
+
+    Ok(value)
+}
@@ -0,0 +1,85 @@
+// Generated by starb-proxy-gen from fixture.dll's exports
+
+/// DLL being proxied.
+pub const TARGET: &str = "fixture.dll";
+/// (name, ordinal) of each export, in the order of the stubs.
+pub const EXPORTS: [(Option<&str>, u16); 5] = [
+    (Some("Alpha"), 5),
+    (Some("Beta"), 6),
+    (None, 8),
+    (Some("Forwarded"), 9),
+    (Some("Last"), 10),
+];
+
+core::arch::global_asm!(
+    ".text",
+    ".globl __starb_proxy_0",
+    "__starb_proxy_0:",
+    "mov rax, qword ptr [rip + {slots} + 0]",
+    "test rax, rax",
+    "jz __starb_proxy_0_resolve",
+    "jmp rax",
+    "__starb_proxy_0_resolve:",
+    "mov eax, 0",
+    "jmp __starb_proxy_resolve",
+    ".globl __starb_proxy_1",
+    "__starb_proxy_1:",
+    "mov rax, qword ptr [rip + {slots} + 8]",
+    "test rax, rax",
+    "jz __starb_proxy_1_resolve",
+    "jmp rax",
+    "__starb_proxy_1_resolve:",
+    "mov eax, 1",
+    "jmp __starb_proxy_resolve",
+    ".globl __starb_proxy_2",
+    "__starb_proxy_2:",
+    "mov rax, qword ptr [rip + {slots} + 16]",
+    "test rax, rax",
+    "jz __starb_proxy_2_resolve",
+    "jmp rax",
+    "__starb_proxy_2_resolve:",
+    "mov eax, 2",
+    "jmp __starb_proxy_resolve",
+    ".globl __starb_proxy_3",
+    "__starb_proxy_3:",
+    "mov rax, qword ptr [rip + {slots} + 24]",
+    "test rax, rax",
+    "jz __starb_proxy_3_resolve",
+    "jmp rax",
+    "__starb_proxy_3_resolve:",
+    "mov eax, 3",
+    "jmp __starb_proxy_resolve",
+    ".globl __starb_proxy_4",
+    "__starb_proxy_4:",
+    "mov rax, qword ptr [rip + {slots} + 32]",
+    "test rax, rax",
+    "jz __starb_proxy_4_resolve",
+    "jmp rax",
+    "__starb_proxy_4_resolve:",
+    "mov eax, 4",
+    "jmp __starb_proxy_resolve",
+    "__starb_proxy_resolve:",
+    "push rcx",
+    "push rdx",
+    "push r8",
+    "push r9",
+    "sub rsp, 0x68",
+    "movdqu xmmword ptr [rsp + 0x20], xmm0",
+    "movdqu xmmword ptr [rsp + 0x30], xmm1",
+    "movdqu xmmword ptr [rsp + 0x40], xmm2",
+    "movdqu xmmword ptr [rsp + 0x50], xmm3",
+    "mov ecx, eax",
+    "call {resolve}",
+    "movdqu xmm0, xmmword ptr [rsp + 0x20]",
+    "movdqu xmm1, xmmword ptr [rsp + 0x30]",
+    "movdqu xmm2, xmmword ptr [rsp + 0x40]",
+    "movdqu xmm3, xmmword ptr [rsp + 0x50]",
+    "add rsp, 0x68",
+    "pop r9",
+    "pop r8",
+    "pop rdx",
+    "pop rcx",
+    "jmp rax",
+    slots = sym SLOTS,
+    resolve = sym __resolve,
+);
@@ -0,0 +1,247 @@
+//! Generates the exports of starb's proxy DLL, from the export table of the
+//! DLL it's pretending to be. This is used by the proxy's build script.
+//!
+//! Every export becomes a stub that jumps to the real DLL's export of the same
+//! name (or ordinal), which is looked up the first time it's called. This way,
+//! signatures don't matter, and nothing's loaded while the loader lock is held.
+//!
+//! Exports are read with [`starb_formats::pe`], which is tested separately.
+
+use eyre::Result;
+pub use starb_formats::pe;
+use starb_formats::pe::ExportTable;
+use std::fmt::Write as _;
+
+/// Rust source for the proxy, to be `include!`d. This expects these in scope:
+///
+/// * `SLOTS: [AtomicUsize; EXPORTS.len()]`, where each stub caches the real
+///   function.
+/// * `unsafe extern "system" fn __resolve(index: usize) -> usize`, which looks
+///   up `EXPORTS[index]`, stores it in `SLOTS[index]` and returns it.
+pub fn generate_rs(table: &ExportTable, target: &str) -> Result<String> {
+    let mut rs = String::new();
+
+    writeln!(
+        rs,
+        "// Generated by starb-proxy-gen from {target}'s exports\n"
+    )?;
+    writeln!(rs, "/// DLL being proxied.")?;
+    writeln!(rs, "pub const TARGET: &str = {target:?};")?;
+    writeln!(
+        rs,
+        "/// (name, ordinal) of each export, in the order of the stubs."
+    )?;
+    writeln!(
+        rs,
+        "pub const EXPORTS: [(Option<&str>, u16); {}] = [",
+        table.exports.len()
+    )?;
+
+    for export in &table.exports {
+        writeln!(rs, "    ({:?}, {}),", export.name, export.ordinal)?;
+    }
+
+    writeln!(rs, "];\n")?;
+
+    writeln!(rs, "core::arch::global_asm!(")?;
+    writeln!(rs, "    \".text\",")?;
+
+    for i in 0usize..table.exports.len() {
+        for line in [
+            format!(".globl __starb_proxy_{i}"),
+            format!("__starb_proxy_{i}:"),
+            format!("mov rax, qword ptr [rip + {{slots}} + {}]", i * 8usize),
+            "test rax, rax".to_owned(),
+            format!("jz __starb_proxy_{i}_resolve"),
+            "jmp rax".to_owned(),
+            format!("__starb_proxy_{i}_resolve:"),
+            format!("mov eax, {i}"),
+            "jmp __starb_proxy_resolve".to_owned(),
+        ] {
+            writeln!(rs, "    {line:?},")?;
+        }
+    }
+
+    // Save the arguments (rcx, rdx, r8, r9, xmm0-3) while resolving, then jump to
+    // the real function as if it was called in the first place. Stack's aligned
+    // to 16 bytes at the call
+    for line in [
+        "__starb_proxy_resolve:",
+        "push rcx",
+        "push rdx",
+        "push r8",
+        "push r9",
+        "sub rsp, 0x68",
+        "movdqu xmmword ptr [rsp + 0x20], xmm0",
+        "movdqu xmmword ptr [rsp + 0x30], xmm1",
+        "movdqu xmmword ptr [rsp + 0x40], xmm2",
+        "movdqu xmmword ptr [rsp + 0x50], xmm3",
+        "mov ecx, eax",
+        "call {resolve}",
+        "movdqu xmm0, xmmword ptr [rsp + 0x20]",
+        "movdqu xmm1, xmmword ptr [rsp + 0x30]",
+        "movdqu xmm2, xmmword ptr [rsp + 0x40]",
+        "movdqu xmm3, xmmword ptr [rsp + 0x50]",
+        "add rsp, 0x68",
+        "pop r9",
+        "pop r8",
+        "pop rdx",
+        "pop rcx",
+        "jmp rax",
+    ] {
+        writeln!(rs, "    {line:?},")?;
+    }
+
+    writeln!(rs, "    slots = sym SLOTS,")?;
+    writeln!(rs, "    resolve = sym __resolve,")?;
+    writeln!(rs, ");")?;
+
+    Ok(rs)
+}
+
+/// Module-definition file exporting every stub under its original name and
+/// ordinal, for GNU ld.
+pub fn generate_def(table: &ExportTable) -> Result<String> {
+    let mut def = String::new();
+
+    writeln!(def, "EXPORTS")?;
+
+    for (i, export) in table.exports.iter().enumerate() {
+        match export.name.as_deref() {
+            Some(name) => writeln!(def, "    {name}=__starb_proxy_{i} @{}", export.ordinal)?,
+            None => writeln!(
+                def,
+                "    __starb_proxy_ordinal_{0}=__starb_proxy_{i} @{0} NONAME",
+                export.ordinal
+            )?,
+        }
+    }
+
+    Ok(def)
+}
+
+/// Same as [`generate_def`], but as arguments for MSVC's link. rustc already
+/// passes its own `/DEF`, so these have to be separate.
+#[must_use]
+pub fn generate_link_args(table: &ExportTable) -> Vec<String> {
+    table
+        .exports
+        .iter()
+        .enumerate()
+        .map(|(i, export)| match export.name.as_deref() {
+            Some(name) => format!("/EXPORT:{name}=__starb_proxy_{i},@{}", export.ordinal),
+            None => format!(
+                "/EXPORT:__starb_proxy_ordinal_{0}=__starb_proxy_{i},@{0},NONAME",
+                export.ordinal
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `formats`' fixture, with an ordinal-only and a forwarded export. The
+    /// forwarded one's stub is like any other, since looking it up in the real
+    /// DLL follows the forwarder.
+    fn __table() -> Result<ExportTable> {
+        pe::parse(include_bytes!("../../formats/fixtures/exports64.dll"))
+    }
+
+    #[test]
+    fn rs() -> Result<()> {
+        assert_eq!(
+            generate_rs(&__table()?, "fixture.dll")?,
+            include_str!("../fixtures/exports.rs")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn def() -> Result<()> {
+        assert_eq!(
+            generate_def(&__table()?)?,
+            include_str!("../fixtures/exports.def")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn link_args() -> Result<()> {
+        assert_eq!(
+            generate_link_args(&__table()?),
+            include_str!("../fixtures/link_args.txt")
+                .lines()
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn pe32() -> Result<()> {
+        let table = pe::parse(include_bytes!("../../formats/fixtures/exports32.dll"))?;
+
+        assert_eq!(generate_def(&table)?, generate_def(&__table()?)?);
+
+        Ok(())
+    }
+
+    /// The lists used when the DLL being proxied can't be read, one for each
+    /// of the proxy's features.
+    #[test]
+    fn lists() -> Result<()> {
+        for (dll, list, len) in [
+            (
+                "version.dll",
+                include_str!("../../proxy/exports/version.txt"),
+                17usize,
+            ),
+            (
+                "winmm.dll",
+                include_str!("../../proxy/exports/winmm.txt"),
+                180usize,
+            ),
+            (
+                "dinput8.dll",
+                include_str!("../../proxy/exports/dinput8.txt"),
+                6usize,
+            ),
+        ] {
+            let table = pe::parse_list(dll, list)?;
+
+            assert_eq!(table.exports.len(), len, "{dll}");
+            assert!(
+                table.exports.iter().all(|export| export.name.is_some()),
+                "{dll} has an export without a name"
+            );
+
+            let def = generate_def(&table)?;
+
+            // Every ordinal once, or the linker would refuse
+            let mut ordinals = table
+                .exports
+                .iter()
+                .map(|export| export.ordinal)
+                .collect::<Vec<_>>();
+
+            ordinals.dedup();
+
+            assert_eq!(ordinals.len(), len, "{dll} has duplicate ordinals");
+            assert_eq!(def.lines().count(), len + 1usize, "{dll}");
+        }
+
+        assert_eq!(
+            generate_def(&pe::parse_list(
+                "dinput8.dll",
+                include_str!("../../proxy/exports/dinput8.txt")
+            )?)?,
+            include_str!("../fixtures/dinput8.def")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! Generates the proxy's exports from the export table of the DLL it's
+//! pretending to be. That's `version.dll`, unless the `winmm` or `dinput8`
+//! feature is enabled.
+//!
+//! The DLL is read from `STARB_PROXY_DLL` if it's set, then from System32 when
+//! building on Windows. Otherwise, the list in `exports` is used.
+
+use eyre::bail;
+use eyre::Result;
+use starb_proxy_gen::pe;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn main() -> Result<()> {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=exports");
+    println!("cargo:rerun-if-env-changed=STARB_PROXY_DLL");
+
+    let target = match (
+        env::var_os("CARGO_FEATURE_WINMM").is_some(),
+        env::var_os("CARGO_FEATURE_DINPUT8").is_some(),
+    ) {
+        (false, false) => "version.dll",
+        (true, false) => "winmm.dll",
+        (false, true) => "dinput8.dll",
+        (true, true) => bail!("Only one of `winmm` and `dinput8` can be enabled"),
+    };
+
+    let system32 = env::var_os("SystemRoot").map(|root| PathBuf::from(root).join("System32"));
+    let list = PathBuf::from("exports").join(target.replace(".dll", ".txt"));
+
+    let table = if let Some(dll) = env::var_os("STARB_PROXY_DLL") {
+        pe::parse(&fs::read(dll)?)?
+    }
+    else if let Some(dll) = system32
+        .map(|system32| system32.join(target))
+        .filter(|dll| dll.exists())
+    {
+        pe::parse(&fs::read(dll)?)?
+    }
+    else if list.exists() {
+        pe::parse_list(target, &fs::read_to_string(list)?)?
+    }
+    else {
+        bail!(
+            "Can't find {target}'s exports. Set STARB_PROXY_DLL to a copy of it, like the one in \
+             your Wine prefix"
+        );
+    };
+
+    if table.exports.is_empty() {
+        bail!("{target} has no exports, this isn't the right DLL");
+    }
+
+    let out = PathBuf::from(env::var("OUT_DIR")?);
+
+    fs::write(
+        out.join("exports.rs"),
+        starb_proxy_gen::generate_rs(&table, target)?,
+    )?;
+
+    // Only Windows has exports like these. Building for anything else is just for
+    // testing, like `config`'s tests
+    if env::var("CARGO_CFG_TARGET_OS")? != "windows" {
+        return Ok(());
+    }
+
+    if env::var("CARGO_CFG_TARGET_ENV")? == "msvc" {
+        for arg in starb_proxy_gen::generate_link_args(&table) {
+            println!("cargo:rustc-cdylib-link-arg={arg}");
+        }
+    }
+    else {
+        let def = out.join("exports.def");
+
+        fs::write(&def, starb_proxy_gen::generate_def(&table)?)?;
+
+        println!("cargo:rustc-cdylib-link-arg={}", def.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,263 @@
+//! `starb_proxy.toml`, which lists other mods' DLLs to load alongside starb.
+//! This is how starb gets along with other mods that are also `version.dll`.
+//!
+//! ```toml
+//! # Another mod's version.dll, renamed. Its exports are used instead of
+//! # System32's
+//! forward = "version_other_mod.dll"
+//!
+//! [[load]]
+//! path = "other_mod.dll"
+//! # Either "before" or "after" starb. Default: "after"
+//! when = "before"
+//! # Lower is loaded first. Default: 0
+//! order = 10
+//! # Default: true
+//! enabled = true
+//! ```
+//!
+//! Paths are relative to SE's exe. None of this depends on Windows, so the
+//! tests can be run on the host with `cargo test -p speng-starb-proxy --target
+//! x86_64-unknown-linux-gnu`.
+
+use eyre::bail;
+use eyre::Result;
+use serde::Deserialize;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Name of this file, next to SE's exe.
+pub const FILE_NAME: &str = "starb_proxy.toml";
+/// starb itself. This is always loaded.
+pub const STARB: &str = "speng_starb.dll";
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// DLL to forward the proxy's exports to, instead of System32's.
+    pub forward: Option<PathBuf>,
+    /// Extra DLLs to load.
+    #[serde(default)]
+    pub load: Vec<Load>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Load {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub when: When,
+    #[serde(default)]
+    pub order: i32,
+    #[serde(default = "__enabled")]
+    pub enabled: bool,
+}
+
+/// When to load a DLL, relative to starb.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum When {
+    Before,
+    #[default]
+    After,
+}
+
+impl Config {
+    /// Parse and check `toml`. `target` is the DLL the proxy is pretending to
+    /// be, like `version.dll`.
+    pub fn parse(toml: &str, target: &str) -> Result<Self> {
+        let config = toml::from_str::<Self>(toml)?;
+
+        if let Some(forward) = config.forward.as_ref() {
+            // This would be the proxy itself, which would call itself forever
+            if __is(forward, target) {
+                bail!("`forward` can't be {target}, that's starb's proxy. Rename the other one");
+            }
+        }
+
+        for (i, load) in config.load.iter().enumerate() {
+            if __is(&load.path, STARB) || __is(&load.path, target) {
+                bail!(
+                    "`{}` is part of starb, it doesn't need to be loaded",
+                    load.path.display()
+                );
+            }
+
+            if config.load[..i]
+                .iter()
+                .any(|other| __same_file(&other.path, &load.path))
+            {
+                bail!("`{}` is listed more than once", load.path.display());
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Every DLL to load, in order, including starb. Relative paths are joined
+    /// onto `dir`.
+    ///
+    /// DLLs to load before starb come first, then starb, then the rest.
+    /// Each group is sorted by `order`, then by where they are in the file.
+    #[must_use]
+    pub fn load_order(&self, dir: &Path) -> Vec<PathBuf> {
+        let group = |when| {
+            let mut group = self
+                .load
+                .iter()
+                .filter(|load| load.enabled && load.when == when)
+                .collect::<Vec<_>>();
+
+            // This is stable, so ties stay in the order they're written in
+            group.sort_by_key(|load| load.order);
+            group.into_iter().map(|load| dir.join(&load.path))
+        };
+
+        group(When::Before)
+            .chain([dir.join(STARB)])
+            .chain(group(When::After))
+            .collect()
+    }
+
+    /// DLL to forward the proxy's exports to, if it isn't System32's.
+    #[must_use]
+    pub fn forward(&self, dir: &Path) -> Option<PathBuf> {
+        self.forward.as_ref().map(|forward| dir.join(forward))
+    }
+}
+
+const fn __enabled() -> bool {
+    true
+}
+
+/// Whether `path` is just `name`, ignoring case like Windows does.
+fn __is(path: &Path, name: &str) -> bool {
+    path.parent() == Some(Path::new(""))
+        && path
+            .to_str()
+            .is_some_and(|path| path.eq_ignore_ascii_case(name))
+}
+
+fn __same_file(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy()
+        .replace('/', "\\")
+        .eq_ignore_ascii_case(&b.to_string_lossy().replace('/', "\\"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "version.dll";
+
+    #[test]
+    fn empty() -> Result<()> {
+        let config = Config::parse("", TARGET)?;
+
+        assert_eq!(config, Config::default());
+        assert_eq!(config.load_order(Path::new("se")), [
+            Path::new("se").join(STARB)
+        ]);
+        assert_eq!(config.forward(Path::new("se")), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn defaults() -> Result<()> {
+        let config = Config::parse("[[load]]\npath = \"a.dll\"", TARGET)?;
+
+        assert_eq!(config.load, [Load {
+            path: PathBuf::from("a.dll"),
+            when: When::After,
+            order: 0i32,
+            enabled: true,
+        }]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn order() -> Result<()> {
+        let config = Config::parse(
+            r#"
+            forward = "version_other.dll"
+
+            [[load]]
+            path = "after_2.dll"
+            order = 2
+
+            [[load]]
+            path = "before_1.dll"
+            when = "before"
+            order = 1
+
+            [[load]]
+            path = "after_1.dll"
+            order = 1
+
+            [[load]]
+            path = "before_0.dll"
+            when = "before"
+
+            [[load]]
+            path = "disabled.dll"
+            when = "before"
+            enabled = false
+
+            [[load]]
+            path = "after_1_too.dll"
+            order = 1
+
+            [[load]]
+            path = "C:/mods/absolute.dll"
+            order = -1
+            "#,
+            TARGET,
+        )?;
+
+        let dir = Path::new("se");
+
+        assert_eq!(config.load_order(dir), [
+            dir.join("before_0.dll"),
+            dir.join("before_1.dll"),
+            dir.join(STARB),
+            dir.join("C:/mods/absolute.dll"),
+            dir.join("after_1.dll"),
+            dir.join("after_1_too.dll"),
+            dir.join("after_2.dll"),
+        ]);
+        assert_eq!(config.forward(dir), Some(dir.join("version_other.dll")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() {
+        // Typos shouldn't be silently ignored
+        assert!(Config::parse("[[load]]\npath = \"a.dll\"\nwhen = \"during\"", TARGET).is_err());
+        assert!(Config::parse("[[load]]\npaht = \"a.dll\"", TARGET).is_err());
+        assert!(Config::parse("fowrard = \"a.dll\"", TARGET).is_err());
+
+        assert!(Config::parse("forward = \"VERSION.dll\"", TARGET).is_err());
+        assert!(Config::parse("[[load]]\npath = \"speng_starb.dll\"", TARGET).is_err());
+        assert!(Config::parse(
+            "[[load]]\npath = \"a.dll\"\n[[load]]\npath = \"A.dll\"",
+            TARGET
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn forward_elsewhere() -> Result<()> {
+        // A version.dll somewhere else is fine
+        let config = Config::parse("forward = \"other/version.dll\"", TARGET)?;
+
+        assert_eq!(
+            config.forward(Path::new("se")),
+            Some(Path::new("se").join("other/version.dll"))
+        );
+
+        Ok(())
+    }
+}
@@ -1,26 +1,50 @@
-#![feature(decl_macro)]
+mod config;
 
+use config::Config;
 use once_cell::sync::OnceCell;
-use paste::paste;
-use std::ffi::c_void;
+use std::env::current_exe;
+use std::ffi::c_char;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
 use std::mem::transmute;
-use std::num::NonZeroIsize;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
 use std::thread;
 use windows_sys::core::PCSTR;
-use windows_sys::core::PCWSTR;
-use windows_sys::core::PSTR;
-use windows_sys::core::PWSTR;
 use windows_sys::s;
 use windows_sys::Win32::Foundation::HMODULE;
 use windows_sys::Win32::Foundation::MAX_PATH;
-use windows_sys::Win32::Storage::FileSystem::GET_FILE_VERSION_INFO_FLAGS;
-use windows_sys::Win32::Storage::FileSystem::VER_FIND_FILE_FLAGS;
-use windows_sys::Win32::Storage::FileSystem::VER_FIND_FILE_STATUS;
 use windows_sys::Win32::System::LibraryLoader::GetProcAddress;
 use windows_sys::Win32::System::LibraryLoader::LoadLibraryA;
 use windows_sys::Win32::System::SystemInformation::GetSystemDirectoryA;
 use windows_sys::Win32::System::SystemServices::DLL_PROCESS_ATTACH;
+use windows_sys::Win32::UI::WindowsAndMessaging::MessageBoxA;
+use windows_sys::Win32::UI::WindowsAndMessaging::MB_ICONERROR;
+use windows_sys::Win32::UI::WindowsAndMessaging::MB_ICONWARNING;
+use windows_sys::Win32::UI::WindowsAndMessaging::MESSAGEBOX_STYLE;
+
+// `TARGET`, `EXPORTS` and a stub for each export. See `build.rs`
+include!(concat!(env!("OUT_DIR"), "/exports.rs"));
+
+/// Same as starb's `ABI_VERSION`. Bump both whenever how they talk to each
+/// other changes.
+const ABI_VERSION: u32 = 1u32;
+
+/// Same as starb's `StartResult`.
+const STARTED: u32 = 0u32;
+const ABI_MISMATCH: u32 = 1u32;
+const ALREADY_STARTED: u32 = 2u32;
+
+/// Address of each export in the real DLL, once it's been used.
+// SAFETY: `AtomicUsize` is guaranteed to be the same as `usize` in memory
+static SLOTS: [AtomicUsize; EXPORTS.len()] = unsafe { transmute([0usize; EXPORTS.len()]) };
 
 #[no_mangle]
 extern "system" fn DllMain(_: HMODULE, reason: u32, _: usize) -> bool {
@@ -32,78 +56,207 @@ extern "system" fn DllMain(_: HMODULE, reason: u32, _: usize) -> bool {
 }
 
 fn __inject() {
+    __log(&format!("Proxying {TARGET}"));
+
     // We do this to defer loading of libraries (opengl32, really) until later.
     // Why does this work?? I DON'T KNOW!!
-    unsafe { LoadLibraryA(s!("speng_starb.dll")) };
+    for dll in __config().load_order(&__se_dir()) {
+        let Ok(path) = CString::new(dll.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+
+        let module = unsafe { LoadLibraryA(path.as_ptr().cast()) };
+
+        if module == 0isize {
+            __warn(&format!(
+                "Failed to load {}: {}",
+                dll.display(),
+                io::Error::last_os_error()
+            ));
+
+            continue;
+        }
+
+        __log(&format!("Loaded {}", dll.display()));
+
+        if dll.file_name().is_some_and(|name| name == config::STARB) {
+            __start_starb(module);
+        }
+    }
 }
 
-// WARNING: SPAGHETTI CODE BELOW
+/// What `GetProcAddress` returns, when it finds something.
+type Proc = unsafe extern "system" fn() -> isize;
 
-// FIXME: Somehow my old build script doesn't work but this does lol
-pub(crate) macro __lazy_export($(fn $f:ident($($i:ident: $a:ty),*) -> $r:ty);+;) {
-    #[inline]
-    #[must_use]
-    pub fn __h_version() -> HMODULE {
-        static VERSION: OnceCell<HMODULE> = OnceCell::new();
+/// Make sure starb is the one we expect, then start it.
+fn __start_starb(starb: HMODULE) {
+    let (Some(abi_version), Some(version), Some(start)) = (unsafe {
+        (
+            GetProcAddress(starb, s!("starb_abi_version")),
+            GetProcAddress(starb, s!("starb_version")),
+            GetProcAddress(starb, s!("starb_start")),
+        )
+    })
+    else {
+        __error(
+            "speng_starb.dll is older than this proxy, so starb wasn't started. Please copy both \
+             speng_starb.dll and the proxy from the same version of starb.",
+        );
 
-        *VERSION.get_or_init(|| unsafe {
-            // mess...
-            let mut buffer = [0u8; MAX_PATH as usize];
-            let buffer_len = GetSystemDirectoryA(buffer.as_mut_ptr(), buffer.len() as u32);
+        return;
+    };
 
-            // 0 == failed
-            assert_ne!(buffer_len, 0u32);
+    // SAFETY: These are starb's exports, which are only changed along with
+    // `ABI_VERSION`
+    let (abi_version, version, start) = unsafe {
+        (
+            transmute::<Proc, unsafe extern "system" fn() -> u32>(abi_version)(),
+            CStr::from_ptr(transmute::<
+                Proc,
+                unsafe extern "system" fn() -> *const c_char,
+            >(version)()),
+            transmute::<Proc, unsafe extern "system" fn(u32) -> u32>(start),
+        )
+    };
+    let version = version.to_string_lossy();
 
-            let dir =
-                PathBuf::from(String::from_utf8(buffer[..buffer_len as usize].to_vec()).unwrap())
-                    .join("version.dll");
+    __log(&format!("starb v{version}, ABI version {abi_version}"));
 
-            let dir = [dir.to_str().unwrap().as_bytes(), &[0u8]].concat();
+    if abi_version != ABI_VERSION {
+        __error(&format!(
+            "speng_starb.dll (v{version}) has ABI version {abi_version}, but this proxy expects \
+             {ABI_VERSION}, so starb wasn't started. Please copy both speng_starb.dll and the \
+             proxy from the same version of starb."
+        ));
 
-            // 0 == failed
-            NonZeroIsize::new(LoadLibraryA(dir.as_ptr())).unwrap().get()
-        })
+        return;
     }
 
-    paste! {
-        $(
-            #[allow(clippy::many_single_char_names)]
-            #[export_name = "" $f ""]
-            unsafe extern "system" fn [<__ $f:snake>]($($i: $a),*) -> $r {
-                static [<$f:snake:upper>]: OnceCell<usize> = OnceCell::new();
-
-                unsafe {
-                    transmute::<usize, unsafe extern "system" fn($($a),*) -> $r>(
-                        *[<$f:snake:upper>].get_or_init(|| {
-                            GetProcAddress(
-                                __h_version(),
-                                format!("{}\0", stringify!($f)).as_ptr(),
-                            )
-                            .unwrap() as usize
-                        }),
-                    )($($i),*)
-                }
-            }
-        )*
+    // SAFETY: Same as above
+    match unsafe { start(ABI_VERSION) } {
+        STARTED => __log("Started starb"),
+        ABI_MISMATCH => __error("starb refused to start, since its ABI version is different"),
+        ALREADY_STARTED => __warn("starb is already running, so it wasn't started again"),
+        result => __log(&format!("starb_start returned something unknown: {result}")),
+    }
+}
+
+/// `starb_proxy.toml`, or the default if there isn't one.
+fn __config() -> &'static Config {
+    static CONFIG: OnceCell<Config> = OnceCell::new();
+
+    CONFIG.get_or_init(|| {
+        let path = __se_dir().join(config::FILE_NAME);
+
+        let Ok(toml) = fs::read_to_string(&path)
+        else {
+            return Config::default();
+        };
+
+        Config::parse(&toml, TARGET).unwrap_or_else(|e| {
+            __warn(&format!(
+                "{} is invalid, so only starb will be loaded: {e}",
+                path.display()
+            ));
+
+            Config::default()
+        })
+    })
+}
+
+/// Folder SE's exe is in.
+fn __se_dir() -> PathBuf {
+    current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .unwrap_or_default()
+}
+
+/// Write `message` to `starb_proxy.log`, next to SE's exe. starb has its own
+/// log, but that isn't around until starb's started.
+fn __log(message: &str) {
+    static LOG: OnceCell<Option<Mutex<File>>> = OnceCell::new();
+
+    let log = LOG.get_or_init(|| {
+        File::create(__se_dir().join("starb_proxy.log"))
+            .ok()
+            .map(Mutex::new)
+    });
+
+    if let Some(log) = log.as_ref() {
+        if let Ok(mut log) = log.lock() {
+            let _ = writeln!(log, "{message}");
+        }
     }
 }
 
-#[rustfmt::skip]
-__lazy_export! {
-    fn GetFileVersionInfoA(a: PCSTR, b: u32, c: u32, d: *mut c_void) -> i32;
-    fn GetFileVersionInfoExA(a: GET_FILE_VERSION_INFO_FLAGS, b: PCSTR, c: u32, d: u32, e: *mut c_void) -> i32;
-    fn GetFileVersionInfoExW(a: GET_FILE_VERSION_INFO_FLAGS, b: PCWSTR, c: u32, d: u32, e: *mut c_void) -> i32;
-    fn GetFileVersionInfoSizeA(a: PCSTR, b: *mut u32) -> u32;
-    fn GetFileVersionInfoSizeExA(a: GET_FILE_VERSION_INFO_FLAGS, b: PCSTR, c: *mut u32) -> u32;
-    fn GetFileVersionInfoSizeExW(a: GET_FILE_VERSION_INFO_FLAGS, b: PCWSTR, c: *mut u32) -> u32;
-    fn GetFileVersionInfoSizeW(a: PCWSTR, b: *mut u32) -> u32;
-    fn GetFileVersionInfoW(a: PCWSTR, b: u32, c: u32, d: *mut c_void) -> i32;
-    fn VerFindFileA(a: VER_FIND_FILE_FLAGS, b: PCSTR, c: PCSTR, d: PCSTR, e: PSTR, f: *mut u32, g: PSTR, h: *mut u32) -> VER_FIND_FILE_STATUS;
-    fn VerFindFileW(a: VER_FIND_FILE_FLAGS, b: PCWSTR, c: PCWSTR, d: PCWSTR, e: PWSTR, f: *mut u32, g: PWSTR, h: *mut u32) -> VER_FIND_FILE_STATUS;
-    fn VerInstallFileA(a: VER_FIND_FILE_FLAGS, b: PCSTR, c: PCSTR, d: PCSTR, e: PSTR, f: PSTR, g: PSTR, h: *mut u32) -> VER_FIND_FILE_STATUS;
-    fn VerInstallFileW(a: VER_FIND_FILE_FLAGS, b: PCWSTR, c: PCWSTR, d: PCWSTR, e: PWSTR, f: PWSTR, g: PWSTR, h: *mut u32) -> VER_FIND_FILE_STATUS;
-    fn VerLanguageNameA(a: u32, b: PSTR, c: u32) -> u32;
-    fn VerLanguageNameW(a: u32, b: PWSTR, c: u32) -> u32;
-    fn VerQueryValueA(a: *const c_void, b: PCSTR, c: *mut *mut c_void, d: *mut u32) -> i32;
-    fn VerQueryValueW(a: *const c_void, b: PCWSTR, c: *mut *mut c_void, d: *mut u32) -> i32;
+/// Log `message`, and show it.
+fn __warn(message: &str) {
+    __log(&format!("WARN: {message}"));
+    __message_box(message, MB_ICONWARNING);
+}
+
+/// Same as `__warn`, but for when starb can't start.
+fn __error(message: &str) {
+    __log(&format!("ERROR: {message}"));
+    __message_box(message, MB_ICONERROR);
+}
+
+fn __message_box(message: &str, style: MESSAGEBOX_STYLE) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+
+    unsafe { MessageBoxA(0isize, message.as_ptr().cast(), s!("starb proxy"), style) };
+}
+
+/// The real DLL. This is System32's, unless `starb_proxy.toml` says otherwise.
+#[inline]
+#[must_use]
+fn __h_target() -> HMODULE {
+    static TARGET_MODULE: OnceCell<HMODULE> = OnceCell::new();
+
+    *TARGET_MODULE.get_or_init(|| {
+        let path = if let Some(forward) = __config().forward(&__se_dir()) {
+            forward
+        }
+        else {
+            __system32().join(TARGET)
+        };
+        let name = CString::new(path.to_string_lossy().into_owned()).unwrap_or_default();
+        let module = unsafe { LoadLibraryA(name.as_ptr().cast()) };
+
+        // 0 == failed. Every export would fail without it anyway
+        assert_ne!(module, 0isize, "Failed to load {}", path.display());
+
+        module
+    })
+}
+
+fn __system32() -> PathBuf {
+    // mess...
+    let mut buffer = [0u8; MAX_PATH as usize];
+    let len = unsafe { GetSystemDirectoryA(buffer.as_mut_ptr(), MAX_PATH) };
+
+    // 0 == failed
+    assert_ne!(len, 0u32, "Failed to find System32");
+
+    PathBuf::from(String::from_utf8_lossy(&buffer[..len as usize]).into_owned())
+}
+
+/// Called by a stub the first time it's used. Finds the real function, so the
+/// stub can jump to it.
+unsafe extern "system" fn __resolve(index: usize) -> usize {
+    let (name, ordinal) = EXPORTS[index];
+
+    let address = match name {
+        Some(name) => unsafe { GetProcAddress(__h_target(), format!("{name}\0").as_ptr()) },
+        // Ordinals are passed as the low word of the name
+        None => unsafe { GetProcAddress(__h_target(), ordinal as usize as PCSTR) },
+    }
+    .unwrap_or_else(|| panic!("{TARGET} has no export {name:?} (ordinal {ordinal})"))
+        as usize;
+
+    SLOTS[index].store(address, Ordering::Relaxed);
+
+    address
 }
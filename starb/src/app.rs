@@ -1,8 +1,33 @@
+use crate::appearance::Appearance;
+use crate::crash;
+use crate::crash::CrashWindow;
+use crate::events;
+use crate::events::Event;
+use crate::hotkeys;
+use crate::hotkeys::HotkeyEditor;
+use crate::ipc;
+use crate::logging;
+use crate::logging::LogViewer;
 use crate::plugin::Plugin;
 use crate::plugins::no_max_search_radius::NoMaxSearchRadius;
 use crate::plugins::no_max_systems_found::NoMaxSystemsFound;
 use crate::plugins::no_search_locking::NoSearchLocking;
 use crate::plugins::non_negative_search_radius::NonNegativeSearchRadius;
+use crate::restart;
+use crate::restart::Relaunch;
+use crate::restart::SystemLauncher;
+use crate::safe_mode;
+use crate::scanner::Scanner;
+use crate::search;
+use crate::search::SearchPanel;
+use crate::self_test;
+use crate::self_test::Report;
+use crate::tabs::Tab;
+use crate::tabs::TabUi;
+use crate::tabs::Tabs;
+use crate::tick;
+use crate::version;
+use crate::version::SeVersion;
 use eframe::App;
 use eframe::CreationContext;
 use eframe::Frame;
@@ -14,23 +39,23 @@ use egui::Context;
 use egui::RichText;
 use egui::ScrollArea;
 use egui::TopBottomPanel;
+use egui::Ui;
 use hashbrown::HashSet;
+use once_cell::sync::Lazy;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 use serde::Deserialize;
 use serde::Serialize;
 use std::ptr::addr_of_mut;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use steamworks_sys::SteamAPI_ISteamApps_GetAppBuildId;
-use steamworks_sys::SteamAPI_Init;
-use steamworks_sys::SteamAPI_RestartAppIfNecessary;
-use steamworks_sys::SteamAPI_Shutdown;
-use steamworks_sys::SteamAPI_SteamApps_v008;
 use tracing::error;
 use tracing::info;
 use tracing::trace;
+use tracing::warn;
 use windows_sys::Win32::Foundation::LPARAM;
 use windows_sys::Win32::System::SystemServices::UNICODE_STRING_MAX_CHARS;
 use windows_sys::Win32::System::Threading::GetCurrentProcessId;
@@ -39,46 +64,80 @@ use windows_sys::Win32::UI::WindowsAndMessaging::GetClassNameW;
 use windows_sys::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
 use windows_sys::Win32::UI::WindowsAndMessaging::IsWindowVisible;
 
-static PLUGINS: OnceCell<Arc<Mutex<Plugins>>> = OnceCell::new();
+pub(crate) static PLUGINS: OnceCell<Arc<Mutex<Plugins>>> = OnceCell::new();
 
-type PluginTy = Box<dyn Plugin + Send + Sync + 'static>;
+/// Whether SE's main window has opened yet, i.e., whether it's too late for
+/// `Early` plugins to change anything.
+static SE_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// (name, reason). This isn't in [`StarApp`] so it can be requested from
+/// outside of the GUI.
+static REQUIRES_RESTART: Lazy<Mutex<HashSet<(String, String)>>> = Lazy::new(Mutex::default);
+
+/// SE's version, once it's been checked.
+static SE_VERSION: OnceCell<SeVersion> = OnceCell::new();
+
+pub(crate) type PluginTy = Box<dyn Plugin + Send + Sync + 'static>;
 /// true = starb, false = custom
-type Plugins = Vec<(PluginTy, bool)>;
-
-#[derive(Default)]
-pub enum Tab {
-    #[default]
-    StarbPlugins,
-    Filters,
-    Context,
-    CustomPlugins,
-}
+pub(crate) type Plugins = Vec<(PluginTy, bool)>;
 
 #[derive(Deserialize, Serialize)]
 pub struct StarApp {
+    /// ID of the selected tab.
+    #[serde(default = "__default_tab")]
+    tab: String,
+    #[serde(skip)]
+    tabs: Tabs,
+    #[serde(skip)]
+    log_viewer: LogViewer,
     #[serde(skip)]
-    tab: Tab,
+    hotkey_editor: HotkeyEditor,
+    /// Only its watches are saved.
+    #[serde(default)]
+    scanner: Scanner,
     #[serde(skip)]
-    tab_internal: (bool, bool, bool, bool),
+    search_panel: SearchPanel,
     #[serde(skip)]
-    requires_restart: HashSet<(String, String)>,
+    last_crash: Option<CrashWindow>,
+    /// Last self-test, shown in the context tab.
+    #[serde(skip)]
+    self_test: Option<Report>,
+    /// Which restarts were pending when the user chose to restart later.
+    #[serde(skip)]
+    restart_deferred: HashSet<(String, String)>,
+    #[serde(skip)]
+    restart_error: Option<String>,
     #[serde(skip)]
     allowed_to_close: bool,
     #[serde(skip)]
     show_confirmation_dialog: bool,
     show_confirmation_dialog_disabled: bool,
+    /// What to log, unless `STARB_LOG` is set. `None` is
+    /// [`logging::DEFAULT_FILTER`].
+    #[serde(default)]
+    log_filter: Option<String>,
+    #[serde(default)]
+    appearance: Appearance,
 }
 
 impl Default for StarApp {
     fn default() -> Self {
         Self {
-            tab: Tab::StarbPlugins,
-            // Spaghetti I think
-            tab_internal: (true, false, false, false),
-            requires_restart: HashSet::new(),
+            tab: __default_tab(),
+            tabs: Tabs::default(),
+            log_viewer: LogViewer::default(),
+            hotkey_editor: HotkeyEditor::default(),
+            scanner: Scanner::default(),
+            search_panel: SearchPanel::default(),
+            last_crash: None,
+            self_test: None,
+            restart_deferred: HashSet::new(),
+            restart_error: None,
             allowed_to_close: false,
             show_confirmation_dialog: false,
             show_confirmation_dialog_disabled: false,
+            log_filter: None,
+            appearance: Appearance::default(),
         }
     }
 }
@@ -89,9 +148,19 @@ macro __plugins {
         let mut plugins = Plugins::new();
 
         $(
-            plugins.push(((Box::new($plugin::load($cc).unwrap_or_else(|e| {
-                panic!("Failed to load `{}`: {e}", stringify!($plugin))
-            }))), true));
+            // Only to get its key and name, since it isn't loaded yet
+            let unloaded = <$plugin>::default();
+
+            if !safe_mode::is_disabled(&unloaded.key(), &unloaded.name()) {
+                let _active = safe_mode::enter(unloaded.key());
+
+                let mut plugin: PluginTy = Box::new($plugin::load($cc).unwrap_or_else(|e| {
+                    panic!("Failed to load `{}`: {e}", stringify!($plugin))
+                }));
+
+                ipc::apply_pending_settings($cc.storage, &mut plugin);
+                plugins.push((plugin, true));
+            }
         )*
 
         plugins
@@ -104,19 +173,33 @@ impl StarApp {
     pub fn new(cc: &CreationContext<'_>) -> Self {
         // TODO: Find and add custom early/late plugins.
 
+        // Before any plugins, so nothing's patched if this is the wrong exe
+        let version = SE_VERSION.get_or_init(version::detect);
+
+        info!("SE version: {version}");
+
+        assert!(
+            version.supported() != Some(false),
+            "This version of SE isn't supported! This may be because starb needs updating or \
+             because the user is using the wrong SE version. SE version: {version}",
+        );
+
+        if version.supported().is_none() {
+            warn!("Couldn't tell whether this version of SE is supported. Continuing anyway...");
+        }
+
         let mut early_plugins = __plugins! {
             cc,
             NoMaxSystemsFound,
             NoMaxSearchRadius,
             NoSearchLocking,
-            // NonNegativeSearchRadius,
         };
 
         info!("Waiting for SE's main window to open...");
 
-        // This is necessary for some reason. DO. NOT. CHANGE. THIS. Basically, Steam
-        // API is FUCKED for SE. So, we wait until it stops using it to use it
-        // ourselves.
+        // This is necessary for some reason. DO. NOT. CHANGE. THIS. This used to be
+        // for the Steam API, but `Late` plugins also need SE's main window to have
+        // opened.
         loop {
             let mut found_se = false;
             let mut times = 0i32;
@@ -134,6 +217,7 @@ impl StarApp {
 
             if found_se {
                 trace!("Found SE window; We can begin!");
+                SE_STARTED.store(true, Ordering::Relaxed);
                 break;
             }
 
@@ -144,16 +228,10 @@ impl StarApp {
             thread::sleep(Duration::from_millis(100u64));
         }
 
-        let bid = __check_build_id();
-
-        assert!(
-            // Some = bid does not match
-            bid.is_none(),
-            "Build ID does not match! This may be because starb needs updating or because the \
-             user is using the wrong SE version. Build ID: {bid:?}",
-        );
-
-        let mut late_plugins = __plugins! {};
+        let mut late_plugins = __plugins! {
+            cc,
+            NonNegativeSearchRadius,
+        };
 
         info!("Early plugins:");
 
@@ -167,156 +245,336 @@ impl StarApp {
         plugins.append(&mut early_plugins);
         plugins.append(&mut late_plugins);
 
-        // TODO: if_chain?
-        if let Some(storage) = cc.storage {
-            if let Some(app) = eframe::get_value::<Self>(storage, APP_KEY) {
-                return app;
+        drop(plugins);
+
+        if let Err(e) = events::spawn(&cc.egui_ctx) {
+            error!("Failed to start events: {e}");
+        }
+
+        if let Err(e) = tick::spawn() {
+            error!("Failed to start ticking plugins: {e}");
+        }
+
+        // Not being able to control starb from outside isn't worth crashing over
+        if let Err(e) = ipc::spawn() {
+            error!("Failed to start the control API: {e}");
+        }
+
+        hotkeys::load(cc.storage);
+        search::load(cc.storage);
+
+        if let Err(e) = search::spawn() {
+            error!("Failed to start keeping track of searches: {e}");
+        }
+
+        if let Err(e) = hotkeys::spawn() {
+            error!("Failed to start hotkeys: {e}");
+        }
+
+        let mut app = cc
+            .storage
+            .and_then(|storage| eframe::get_value::<Self>(storage, APP_KEY))
+            .unwrap_or_default();
+
+        if let Some(filter) = app.log_filter.as_deref() {
+            if let Err(e) = logging::set_filter(filter) {
+                error!("Log filter in settings is invalid: {e}");
             }
         }
 
-        Self::default()
+        app.log_viewer = LogViewer::new(app.log_filter.as_deref());
+        app.tabs = __tabs();
+        app.appearance
+            .apply(&cc.egui_ctx, cc.integration_info.native_pixels_per_point);
+        app.last_crash = crash::pending();
+        // Every plugin's had a chance to patch by now, so make sure they did
+        app.self_test = Some(self_test::run());
+
+        app
     }
 
-    /// Call this to prompt the user to restart soon. YOU CANNOT UNDO THIS.
-    pub fn requires_restart(&mut self, name: &impl ToString, reason: &impl ToString) {
-        self.requires_restart
-            .insert((name.to_string(), reason.to_string()));
+    fn __tab_ui(&mut self, tab_ui: &TabUi, ctx: &Context, frame: &mut Frame, ui: &mut Ui) {
+        match *tab_ui {
+            TabUi::Builtin(f) => f(self, ctx, frame, ui),
+            TabUi::Plugin { ref key, ref id } => {
+                let mut plugins = PLUGINS.get().expect("Unreachable").lock();
+
+                if let Some(plugin) = plugins.iter_mut().find(|plugin| plugin.0.key() == *key) {
+                    let _active = safe_mode::enter(key.clone());
+
+                    plugin.0.add_tab(id, self, ctx, frame, ui);
+                }
+            },
+        }
     }
-}
 
-impl App for StarApp {
-    fn update(&mut self, ctx: &Context, frame: &mut Frame) {
-        ctx.set_pixels_per_point(1.5f32);
+    fn __plugins_tab(&mut self, ctx: &Context, frame: &mut Frame, ui: &mut Ui) {
+        for (name, reason) in safe_mode::ui(ui) {
+            request_restart(&name, &reason);
+        }
 
-        TopBottomPanel::top("menu_bar").show(ctx, |ui| {
-            ui.horizontal_centered(|ui| {
-                // Oops! All spaghetti!
-                //
-                // TODO: Let's like, not? This is stupid
-                if ui
-                    .toggle_value(&mut self.tab_internal.0, "Plugins")
-                    .clicked()
-                {
-                    // Spaghetti, but disallows disabling ALL tabs
-                    if !self.tab_internal.0 {
-                        self.tab_internal.0 = true;
-                    }
+        for plugin in PLUGINS.get().expect("Unreachable").lock().iter_mut() {
+            if plugin.1 {
+                ui.heading(plugin.0.name());
+                ui.separator();
 
-                    self.tab = Tab::StarbPlugins;
+                let _active = safe_mode::enter(plugin.0.key());
+                let settings = plugin.0.settings();
 
-                    self.tab_internal.1 = false;
-                    self.tab_internal.2 = false;
-                    self.tab_internal.3 = false;
-                }
-                else if ui
-                    .toggle_value(&mut self.tab_internal.1, "Filters")
-                    .clicked()
-                {
-                    // Spaghetti, but disallows disabling ALL tabs
-                    if !self.tab_internal.1 {
-                        self.tab_internal.1 = true;
-                    }
+                plugin.0.add_plugin(self, ctx, frame, ui);
 
-                    self.tab = Tab::Filters;
+                // Let the control API know when something's changed from here
+                let new_settings = plugin.0.settings();
 
-                    self.tab_internal.0 = false;
-                    self.tab_internal.2 = false;
-                    self.tab_internal.3 = false;
+                if new_settings != settings {
+                    events::publish(&Event::PluginChanged {
+                        key: plugin.0.key(),
+                        settings: new_settings,
+                    });
                 }
-                else if ui
-                    .toggle_value(&mut self.tab_internal.2, "Context")
-                    .clicked()
-                {
-                    // Spaghetti, but disallows disabling ALL tabs
-                    if !self.tab_internal.2 {
-                        self.tab_internal.2 = true;
-                    }
 
-                    self.tab = Tab::Context;
+                ui.separator();
+            }
+        }
+    }
+
+    fn __filters_tab(_app: &mut Self, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+        ui.vertical_centered_justified(|ui| ui.label("TODO. It is an open beta, you know?"));
+    }
+
+    fn __context_tab(&mut self, ctx: &Context, frame: &mut Frame, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .button("Run self-test")
+                .on_hover_text("Check every patch and hook is actually in place")
+                .clicked()
+            {
+                self.self_test = Some(self_test::run());
+            }
 
-                    self.tab_internal.0 = false;
-                    self.tab_internal.1 = false;
-                    self.tab_internal.3 = false;
+            if let Some(report) = self.self_test.as_ref() {
+                let (passed, total) = report.count();
+                let color = if report.passed() {
+                    Color32::GREEN
                 }
-                else if ui
-                    .toggle_value(&mut self.tab_internal.3, "Custom Plugins")
+                else {
+                    Color32::RED
+                };
+
+                ui.label(RichText::new(format!("{passed}/{total} OK")).color(color));
+
+                if ui
+                    .button("Copy diagnostics")
+                    .on_hover_text("Include this in bug reports")
                     .clicked()
                 {
-                    // Spaghetti, but disallows disabling ALL tabs
-                    if !self.tab_internal.3 {
-                        self.tab_internal.3 = true;
-                    }
+                    ui.output_mut(|output| output.copied_text = report.to_string());
+                }
+            }
+        });
+
+        ui.separator();
+
+        // Before the plugins are locked, see `search::spawn`
+        self.search_panel.ui(ui);
+
+        ui.separator();
 
-                    self.tab = Tab::CustomPlugins;
+        for plugin in PLUGINS.get().expect("Unreachable").lock().iter_mut() {
+            let _active = safe_mode::enter(plugin.0.key());
+
+            plugin.0.add_context(self, ctx, frame, ui);
 
-                    self.tab_internal.0 = false;
-                    self.tab_internal.1 = false;
-                    self.tab_internal.2 = false;
+            if let Some(report) = self
+                .self_test
+                .as_ref()
+                .and_then(|report| report.plugin(&plugin.0.key()))
+            {
+                report.ui(ui);
+            }
+        }
+    }
+
+    /// Custom (user-made) plugins
+    fn __custom_plugins_tab(_app: &mut Self, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+        ui.vertical_centered_justified(|ui| {
+            ui.label("Coming soon...")
+                .on_hover_text("Ok, not really; but maybe one day!");
+        });
+    }
+
+    fn __hotkeys_tab(&mut self, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+        self.hotkey_editor.ui(ui);
+    }
+
+    fn __scanner_tab(&mut self, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+        self.scanner.ui(ui);
+    }
+
+    fn __settings_tab(&mut self, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+        ui.heading("Appearance");
+        ui.separator();
+
+        self.appearance.ui(ui);
+    }
+
+    fn __log_tab(&mut self, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+        if let Some(filter) = self.log_viewer.ui(ui) {
+            self.log_filter = Some(filter);
+        }
+    }
+}
+
+/// Call this to prompt the user to restart soon, on behalf of `name`. See
+/// [`withdraw_restart`] for taking it back.
+pub fn request_restart(name: &impl ToString, reason: &impl ToString) {
+    let (name, reason) = (name.to_string(), reason.to_string());
+
+    if REQUIRES_RESTART
+        .lock()
+        .insert((name.clone(), reason.clone()))
+    {
+        events::publish(&Event::RestartRequested { name, reason });
+    }
+}
+
+/// Take back the restart `name` requested for a [`Setting`] called `label`,
+/// like when it's changed back to what's in effect. Those requests' reasons
+/// all start with the setting's label.
+///
+/// [`Setting`]: crate::setting::Setting
+pub fn withdraw_restart(name: &impl ToString, label: &str) {
+    let (name, label) = (name.to_string(), format!("{label} "));
+
+    REQUIRES_RESTART
+        .lock()
+        .retain(|requested| requested.0 != name || !requested.1.starts_with(&label));
+}
+
+/// Whether SE's main window has opened yet. `Early` plugins can't change
+/// anything after this without a restart.
+pub fn se_started() -> bool {
+    SE_STARTED.load(Ordering::Relaxed)
+}
+
+/// SE's version. `None` if it hasn't been checked yet.
+pub fn se_version() -> Option<&'static SeVersion> {
+    SE_VERSION.get()
+}
+
+/// Every restart requested so far, as (name, reason).
+pub fn pending_restarts() -> Vec<(String, String)> {
+    REQUIRES_RESTART.lock().iter().cloned().collect()
+}
+
+impl App for StarApp {
+    fn update(&mut self, ctx: &Context, frame: &mut Frame) {
+        // The integration resets the scale when the system's changes, like when moving
+        // to another monitor
+        let native_scale = frame.info().native_pixels_per_point;
+
+        if native_scale != self.appearance.native_scale() {
+            self.appearance.apply_scale(ctx, native_scale);
+        }
+
+        safe_mode::tick();
+
+        TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            ui.horizontal_centered(|ui| {
+                let mut selected = None;
+
+                for tab in self.tabs.iter() {
+                    if ui
+                        .selectable_label(tab.id() == self.tab, tab.label())
+                        .clicked()
+                    {
+                        selected = Some(tab.id().to_owned());
+                    }
+                }
+
+                if let Some(selected) = selected {
+                    self.tab = selected;
                 }
             })
         });
 
-        if !self.requires_restart.is_empty() {
+        let requires_restart = pending_restarts();
+        let mut restart = false;
+
+        if requires_restart
+            .iter()
+            .any(|requested| !self.restart_deferred.contains(requested))
+        {
             TopBottomPanel::bottom("requires_restart").show(ctx, |ui| {
-                ui.horizontal_centered(|ui| {
-                    ui.label(
-                        RichText::new(
-                            "A plugin(s) has requested that you restart your game. Please do so \
-                             as soon as possible. Hover over me for more information!",
-                        )
+                ui.label(
+                    RichText::new("SE needs to be restarted for some changes to apply:")
                         .color(Color32::YELLOW),
-                    )
-                    .on_hover_text(format!("{:#?}", self.requires_restart));
+                );
+
+                for requested in &requires_restart {
+                    ui.label(format!("• {}: {}", requested.0, requested.1));
+                }
+
+                ui.horizontal(|ui| {
+                    restart = ui
+                        .button("Restart SE")
+                        .on_hover_text(
+                            "Saves everything, then closes SE and starts it again. Steam copies \
+                             are started via Steam, others with the same command line.",
+                        )
+                        .clicked();
 
                     if ui
-                        .button(RichText::new("CRASH THE GAME.").color(Color32::RED))
+                        .button("Later")
+                        .on_hover_text("Hides this until something else needs a restart")
                         .clicked()
                     {
-                        error!("User requested to crash the game. Goodbye!");
+                        self.restart_deferred = requires_restart.iter().cloned().collect();
+                    }
 
-                        // Oops!
-                        panic!("Oops! User requested to crash the game!");
+                    if let Some(e) = self.restart_error.as_ref() {
+                        ui.label(RichText::new(e).color(Color32::RED));
                     }
-                })
+                });
             });
         }
 
-        CentralPanel::default().show(ctx, |ui| {
-            ScrollArea::vertical().show(ui, |ui| {
-                // Plugins tab
-                if matches!(self.tab, Tab::StarbPlugins) {
-                    for plugin in PLUGINS.get().expect("Unreachable").lock().iter_mut() {
-                        if plugin.1 {
-                            ui.heading(plugin.0.name());
-                            ui.separator();
+        if restart {
+            let from_steam = se_version().is_some_and(|version| version.build_id.is_some());
+            let result = Relaunch::detect(from_steam).and_then(|relaunch| {
+                restart::restart(&mut SystemLauncher, &relaunch, || {
+                    if let Some(storage) = frame.storage_mut() {
+                        self.save(storage);
+                        storage.flush();
+                    }
 
-                            plugin.0.add_plugin(self, ctx, frame, ui);
+                    // Restarting on purpose isn't a failed start
+                    safe_mode::mark_stable();
+                })
+            });
 
-                            ui.separator();
-                        }
-                    }
-                }
-                // Filters tab
-                else if matches!(self.tab, Tab::Filters) {
-                    ui.vertical_centered_justified(|ui| {
-                        ui.label("TODO. It is an open beta, you know?")
-                    });
-                }
-                // Context tab
-                else if matches!(self.tab, Tab::Context) {
-                    for plugin in PLUGINS.get().expect("Unreachable").lock().iter_mut() {
-                        plugin.0.add_context(self, ctx, frame, ui);
-                    }
+            if let Err(e) = result {
+                error!("Failed to restart SE: {e}");
+                self.restart_error = Some(format!("Failed to restart SE: {e}"));
+            }
+        }
+
+        if let Some((tab, tab_ui)) = self.tabs.get_or_first(&self.tab).cloned() {
+            CentralPanel::default().show(ctx, |ui| {
+                if tab.scroll() {
+                    ScrollArea::vertical().show(ui, |ui| self.__tab_ui(&tab_ui, ctx, frame, ui));
                 }
-                // Custom (user-made) plugins tab
-                else if matches!(self.tab, Tab::CustomPlugins) {
-                    ui.vertical_centered_justified(|ui| {
-                        ui.label("Coming soon...")
-                            .on_hover_text("Ok, not really; but maybe one day!");
-                    });
+                else {
+                    self.__tab_ui(&tab_ui, ctx, frame, ui);
                 }
             });
-        });
+        }
+
+        if let Some(last_crash) = self.last_crash.as_mut() {
+            if !last_crash.ui(ctx) {
+                self.last_crash = None;
+            }
+        }
 
         // <https://github.com/emilk/egui/blob/master/examples/confirm_exit/src/main.rs>
         // with minor edits
@@ -351,9 +609,23 @@ impl App for StarApp {
     }
 
     fn save(&mut self, storage: &mut dyn Storage) {
-        for plugin in PLUGINS.get().expect("Unreachable").lock().iter_mut() {
+        let mut plugins = PLUGINS.get().expect("Unreachable").lock();
+
+        for plugin in plugins.iter_mut() {
+            let _active = safe_mode::enter(plugin.0.key());
+
             plugin.0.save(self, storage);
         }
+
+        crash::snapshot(&plugins);
+        drop(plugins);
+
+        ipc::save(storage);
+        hotkeys::save(storage);
+        search::save(storage);
+
+        // Our own settings, like the selected tab and appearance
+        eframe::set_value(storage, APP_KEY, self);
     }
 
     // <https://github.com/emilk/egui/blob/master/examples/confirm_exit/src/main.rs>
@@ -363,6 +635,10 @@ impl App for StarApp {
         if !self.allowed_to_close {
             self.show_confirmation_dialog_disabled = false;
         }
+        else {
+            // Closing normally isn't a failed start
+            safe_mode::mark_stable();
+        }
         self.allowed_to_close
     }
 
@@ -395,22 +671,57 @@ unsafe extern "system" fn __check_if_window_is_opened(hwnd: isize, found: LPARAM
     i32::from(true)
 }
 
-fn __check_build_id() -> Option<i32> {
-    unsafe {
-        assert!(!SteamAPI_RestartAppIfNecessary(314650u32), "Unreachable");
-        assert!(SteamAPI_Init(), "SteamAPI_Init failed");
-    }
-
-    /// Build ID starb is created for.
-    const BUILD_ID: i32 = 11154210i32;
-
-    let build_id = unsafe { SteamAPI_ISteamApps_GetAppBuildId(SteamAPI_SteamApps_v008()) };
-
-    let matches = build_id == BUILD_ID;
+fn __default_tab() -> String {
+    "plugins".to_owned()
+}
 
-    unsafe { SteamAPI_Shutdown() };
+/// starb's own tabs, then every plugin's.
+fn __tabs() -> Tabs {
+    let mut tabs = Tabs::default();
+
+    tabs.register(
+        Tab::new("plugins", "Plugins").icon("🔌").order(0i32),
+        TabUi::Builtin(StarApp::__plugins_tab),
+    );
+    tabs.register(
+        Tab::new("filters", "Filters").icon("🔍").order(10i32),
+        TabUi::Builtin(StarApp::__filters_tab),
+    );
+    tabs.register(
+        Tab::new("context", "Context").icon("ℹ").order(20i32),
+        TabUi::Builtin(StarApp::__context_tab),
+    );
+    tabs.register(
+        Tab::new("custom_plugins", "Custom Plugins")
+            .icon("🛠")
+            .order(30i32),
+        TabUi::Builtin(StarApp::__custom_plugins_tab),
+    );
+    tabs.register(
+        Tab::new("hotkeys", "Hotkeys").icon("⌨").order(40i32),
+        TabUi::Builtin(StarApp::__hotkeys_tab),
+    );
+    tabs.register(
+        Tab::new("scanner", "Scanner").icon("🔎").order(50i32),
+        TabUi::Builtin(StarApp::__scanner_tab),
+    );
+    tabs.register(
+        Tab::new("settings", "Settings").icon("⚙").order(80i32),
+        TabUi::Builtin(StarApp::__settings_tab),
+    );
+    tabs.register(
+        // Has its own scroll area
+        Tab::new("log", "Log").icon("📜").order(90i32).no_scroll(),
+        TabUi::Builtin(StarApp::__log_tab),
+    );
+
+    for plugin in PLUGINS.get().expect("Unreachable").lock().iter() {
+        let _active = safe_mode::enter(plugin.0.key());
+
+        tabs.register_plugin(&plugin.0.key(), plugin.0.tabs());
+    }
 
-    (!matches).then_some(build_id)
+    tabs
 }
 
 fn __print_plugins(plugins: &Plugins) {
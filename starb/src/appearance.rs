@@ -0,0 +1,271 @@
+//! How starb's window looks. This is applied once when starb starts and again
+//! whenever it's changed, not every frame, so it doesn't fight with egui.
+
+use egui::Color32;
+use egui::Context;
+use egui::FontData;
+use egui::FontDefinitions;
+use egui::FontFamily;
+use egui::RichText;
+use egui::Slider;
+use egui::Style;
+use egui::TextEdit;
+use egui::Ui;
+use egui::Visuals;
+use eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use tracing::error;
+use tracing::info;
+
+/// egui's default size of body text, which every other text style is relative
+/// to.
+pub const DEFAULT_FONT_SIZE: f32 = 12.5f32;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Appearance {
+    /// Points per pixel. `None` follows the system's DPI.
+    pub scale: Option<f32>,
+    pub theme: Theme,
+    /// Color of selections and links, as RGB. `None` is the theme's own.
+    pub accent: Option<[u8; 3usize]>,
+    /// Size of body text, everything else is scaled to match.
+    pub font_size: f32,
+    /// TTF or OTF file to use instead of egui's font. egui's is still used for
+    /// anything this doesn't have, like emojis.
+    pub font: Option<PathBuf>,
+    /// System's scale when this was last applied.
+    #[serde(skip)]
+    native_scale: Option<f32>,
+    /// What's in the font text box, which is only applied once it's loaded.
+    #[serde(skip)]
+    font_input: Option<String>,
+    #[serde(skip)]
+    font_error: Option<String>,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            scale: None,
+            theme: Theme::default(),
+            accent: None,
+            font_size: DEFAULT_FONT_SIZE,
+            font: None,
+            native_scale: None,
+            font_input: None,
+            font_error: None,
+        }
+    }
+}
+
+impl Appearance {
+    /// Apply everything. `native_scale` is the system's scale, from
+    /// [`eframe::IntegrationInfo`].
+    pub fn apply(&mut self, ctx: &Context, native_scale: Option<f32>) {
+        self.apply_scale(ctx, native_scale);
+        self.__apply_style(ctx);
+        self.__apply_fonts(ctx);
+    }
+
+    /// Apply only the scale. This must be called again whenever the system's
+    /// scale changes, since the integration overwrites it then.
+    pub fn apply_scale(&mut self, ctx: &Context, native_scale: Option<f32>) {
+        self.native_scale = native_scale;
+
+        if let Some(scale) = self.scale.or(native_scale) {
+            ctx.set_pixels_per_point(scale);
+        }
+    }
+
+    /// System's scale when this was last applied.
+    #[must_use]
+    pub const fn native_scale(&self) -> Option<f32> {
+        self.native_scale
+    }
+
+    /// Show the appearance section of the settings tab. Changes are applied
+    /// immediately.
+    pub fn ui(&mut self, ui: &mut Ui) {
+        let ctx = ui.ctx().clone();
+
+        ui.horizontal(|ui| {
+            let mut follow_system = self.scale.is_none();
+
+            if ui
+                .checkbox(&mut follow_system, "Follow system scale")
+                .changed()
+            {
+                self.scale = (!follow_system).then(|| ctx.pixels_per_point());
+                self.apply_scale(&ctx, self.native_scale);
+            }
+
+            if let Some(scale) = self.scale.as_mut() {
+                let slider = ui.add(Slider::new(scale, 0.5f32..=3.0f32).text("Scale"));
+
+                // Scaling while dragging moves the slider out from under the cursor
+                if slider.drag_released() || (slider.changed() && !slider.dragged()) {
+                    self.apply_scale(&ctx, self.native_scale);
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let mut changed = false;
+
+            changed |= ui
+                .selectable_value(&mut self.theme, Theme::Dark, "🌙 Dark")
+                .changed();
+            changed |= ui
+                .selectable_value(&mut self.theme, Theme::Light, "☀ Light")
+                .changed();
+
+            ui.separator();
+
+            let mut custom_accent = self.accent.is_some();
+
+            if ui.checkbox(&mut custom_accent, "Custom accent").changed() {
+                let default = ctx.style().visuals.selection.bg_fill;
+
+                self.accent = custom_accent.then(|| [default.r(), default.g(), default.b()]);
+                changed = true;
+            }
+
+            if let Some(accent) = self.accent.as_mut() {
+                changed |= ui.color_edit_button_srgb(accent).changed();
+            }
+
+            if changed {
+                self.__apply_style(&ctx);
+            }
+        });
+
+        if ui
+            .add(Slider::new(&mut self.font_size, 8.0f32..=32.0f32).text("Font size"))
+            .changed()
+        {
+            self.__apply_style(&ctx);
+        }
+
+        let (mut load_font, mut default_font) = (false, false);
+
+        ui.horizontal(|ui| {
+            let font_input = self.font_input.get_or_insert_with(|| {
+                self.font
+                    .as_ref()
+                    .map(|font| font.display().to_string())
+                    .unwrap_or_default()
+            });
+
+            ui.add(TextEdit::singleline(font_input).hint_text("Path to a .ttf or .otf"));
+
+            load_font = ui.button("Load font").clicked();
+            default_font = ui.button("Default font").clicked();
+        });
+
+        if load_font {
+            let font_input = self.font_input.as_deref().unwrap_or_default().trim();
+
+            self.font = (!font_input.is_empty()).then(|| font_input.into());
+            self.__apply_fonts(&ctx);
+        }
+        else if default_font {
+            self.font = None;
+            self.font_input = None;
+            self.__apply_fonts(&ctx);
+        }
+
+        if let Some(e) = self.font_error.as_ref() {
+            ui.label(RichText::new(e).color(Color32::RED));
+        }
+
+        if ui.button("Reset appearance").clicked() {
+            let native_scale = self.native_scale;
+
+            *self = Self::default();
+            self.apply(&ctx, native_scale);
+        }
+    }
+
+    fn __apply_style(&self, ctx: &Context) {
+        let mut visuals = match self.theme {
+            Theme::Dark => Visuals::dark(),
+            Theme::Light => Visuals::light(),
+        };
+
+        if let Some([r, g, b]) = self.accent {
+            let accent = Color32::from_rgb(r, g, b);
+
+            visuals.selection.bg_fill = accent;
+            visuals.hyperlink_color = accent;
+        }
+
+        let ratio = self.font_size / DEFAULT_FONT_SIZE;
+        let mut style = (*ctx.style()).clone();
+
+        style.visuals = visuals;
+        style.text_styles = Style::default()
+            .text_styles
+            .into_iter()
+            .map(|(text_style, mut font_id)| {
+                font_id.size *= ratio;
+                (text_style, font_id)
+            })
+            .collect();
+
+        ctx.set_style(style);
+    }
+
+    fn __apply_fonts(&mut self, ctx: &Context) {
+        self.font_error = None;
+
+        match self.__fonts() {
+            Ok(fonts) => ctx.set_fonts(fonts),
+            Err(e) => {
+                error!("Failed to load font: {e}");
+
+                self.font_error = Some(format!("Failed to load font: {e}"));
+                ctx.set_fonts(FontDefinitions::default());
+            },
+        }
+    }
+
+    fn __fonts(&self) -> Result<FontDefinitions> {
+        let mut fonts = FontDefinitions::default();
+
+        let Some(path) = self.font.as_ref()
+        else {
+            return Ok(fonts);
+        };
+
+        let bytes = fs::read(path)?;
+
+        // egui panics later on fonts it can't parse, so check now
+        ab_glyph::FontRef::try_from_slice(&bytes)?;
+
+        info!("Using font {}", path.display());
+
+        fonts
+            .font_data
+            .insert("starb_custom".to_owned(), FontData::from_owned(bytes));
+
+        // Not monospace too, since it likely isn't
+        fonts
+            .families
+            .entry(FontFamily::Proportional)
+            .or_default()
+            .insert(0usize, "starb_custom".to_owned());
+
+        Ok(fonts)
+    }
+}
@@ -0,0 +1,342 @@
+//! Crash reports. With `panic = "abort"`, SE just disappears on a panic, so
+//! starb writes everything needed to report it to `starb_crashes` next to SE's
+//! exe. The next time starb starts, it offers to show the latest one.
+
+use crate::app::se_version;
+use crate::app::Plugins;
+use crate::app::PLUGINS;
+use crate::logging;
+use crate::safe_mode;
+use crate::utils::sys_folder;
+use egui::Context;
+use egui::ScrollArea;
+use egui::TextEdit;
+use egui::Window;
+use eyre::Result;
+use parking_lot::Mutex;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tracing::warn;
+
+/// Where to report crashes.
+pub const ISSUES_URL: &str = "https://github.com/Centri3/speng-starb/issues/new";
+/// How many lines of the log to include.
+const LOG_LINES: usize = 200usize;
+/// Name of the file containing the name of the report that hasn't been seen
+/// yet.
+const PENDING: &str = "PENDING";
+
+/// Plugins as of the last save, for when [`PLUGINS`] is locked while
+/// panicking. This happens if a plugin panics in the GUI, for example.
+static SNAPSHOT: Mutex<String> = Mutex::new(String::new());
+
+/// Write a crash report for `report`, returning where it was written.
+pub fn write_report(report: &str) -> Result<PathBuf> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut text = String::new();
+
+    writeln!(text, "starb crash report")?;
+    writeln!(text, "starb version: {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(
+        text,
+        "SE version: {}",
+        se_version().map_or_else(|| "Not checked yet".to_owned(), ToString::to_string)
+    )?;
+    writeln!(text, "Time: {now} (seconds since 1970)")?;
+    writeln!(
+        text,
+        "Active plugin: {}",
+        safe_mode::active().as_deref().unwrap_or("None")
+    )?;
+
+    match safe_mode::active_threads() {
+        Some(active) if active.is_empty() => writeln!(text, "Active plugins by thread: None")?,
+        Some(active) => {
+            writeln!(text, "Active plugins by thread:")?;
+
+            for (thread, key) in active {
+                writeln!(text, "    {thread}: {key}")?;
+            }
+        },
+        None => writeln!(text, "Active plugins by thread: (Busy, skipped)")?,
+    }
+
+    writeln!(text, "Safe mode: {}", safe_mode::enabled())?;
+
+    writeln!(text, "\n== Report ==\n\n{report}")?;
+
+    writeln!(text, "\n== Plugins ==\n")?;
+
+    match PLUGINS
+        .get()
+        .map(|plugins| plugins.try_lock_for(Duration::from_millis(100u64)))
+    {
+        Some(Some(plugins)) => text.push_str(&__describe_plugins(&plugins)),
+        Some(None) => {
+            writeln!(text, "(Plugins were busy, this is as of the last save)\n")?;
+
+            // Panicking while snapshotting would deadlock here
+            match SNAPSHOT.try_lock_for(Duration::from_millis(100u64)) {
+                Some(snapshot) => text.push_str(&snapshot),
+                None => writeln!(text, "(The last save was busy too, skipped)")?,
+            }
+        },
+        None => writeln!(text, "(No plugins were loaded yet)")?,
+    }
+
+    writeln!(text, "\n== Log (last {LOG_LINES} lines) ==\n")?;
+
+    match logging::try_tail(LOG_LINES) {
+        Some(lines) => {
+            for line in lines {
+                writeln!(text, "{line}")?;
+            }
+        },
+        None => writeln!(text, "(The log was busy, skipped)")?,
+    }
+
+    __save(&sys_folder()?, &format!("starb-crash-{now}.txt"), &text)
+}
+
+/// Remember the plugins' current state, in case they're locked when
+/// panicking.
+pub fn snapshot(plugins: &Plugins) {
+    *SNAPSHOT.lock() = __describe_plugins(plugins);
+}
+
+/// The latest crash report, if it hasn't been dismissed yet.
+#[must_use]
+pub fn pending() -> Option<CrashWindow> {
+    __pending(&sys_folder().ok()?)
+}
+
+/// Save a report to `starb_crashes` in `sys_folder` as `name`, as the one that
+/// hasn't been seen yet.
+fn __save(sys_folder: &Path, name: &str, text: &str) -> Result<PathBuf> {
+    let folder = sys_folder.join("starb_crashes");
+
+    fs::create_dir_all(&folder)?;
+    fs::write(folder.join(name), text)?;
+    fs::write(folder.join(PENDING), name)?;
+
+    Ok(folder.join(name))
+}
+
+fn __pending(sys_folder: &Path) -> Option<CrashWindow> {
+    let folder = sys_folder.join("starb_crashes");
+    let name = fs::read_to_string(folder.join(PENDING)).ok()?;
+    let path = folder.join(name.trim());
+
+    Some(CrashWindow {
+        text: fs::read_to_string(&path).ok()?,
+        path,
+        show_report: false,
+    })
+}
+
+fn __describe_plugins(plugins: &Plugins) -> String {
+    let mut text = String::new();
+
+    for &(ref plugin, starb) in plugins {
+        let _ = writeln!(
+            text,
+            "{} ({}, {}): {}",
+            plugin.name(),
+            plugin.key(),
+            if starb { "starb" } else { "custom" },
+            plugin.settings()
+        );
+
+        for patch in plugin.patches() {
+            let _ = writeln!(text, "    {patch}");
+        }
+
+        for hook in plugin.hooks() {
+            let _ = writeln!(text, "    {hook}");
+        }
+    }
+
+    text
+}
+
+/// Asks the user what to do with the last crash report.
+pub struct CrashWindow {
+    path: PathBuf,
+    text: String,
+    show_report: bool,
+}
+
+impl CrashWindow {
+    /// Show this. Returns `false` once it's been dismissed.
+    pub fn ui(&mut self, ctx: &Context) -> bool {
+        let mut open = true;
+
+        Window::new("starb crashed last time")
+            .collapsible(false)
+            .default_width(600.0f32)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Sorry! A crash report was saved to {}. Please include it when reporting this.",
+                    self.path.display()
+                ));
+
+                ui.horizontal(|ui| {
+                    ui.toggle_value(&mut self.show_report, "Show");
+
+                    if ui.button("Copy").clicked() {
+                        ui.output_mut(|o| o.copied_text = self.text.clone());
+                    }
+
+                    ui.hyperlink_to("Report", ISSUES_URL);
+
+                    if ui.button("Dismiss").clicked() {
+                        open = false;
+                    }
+                });
+
+                if self.show_report {
+                    ScrollArea::vertical().max_height(400.0f32).show(ui, |ui| {
+                        ui.add(
+                            TextEdit::multiline(&mut self.text.as_str())
+                                .code_editor()
+                                .desired_width(f32::INFINITY),
+                        );
+                    });
+                }
+            });
+
+        if !open {
+            self.__dismiss();
+        }
+
+        open
+    }
+
+    /// Don't show this again. Not a big deal if this fails, it'll just be shown
+    /// again.
+    fn __dismiss(&self) {
+        if let Some(folder) = self.path.parent() {
+            if let Err(e) = fs::remove_file(folder.join(PENDING)) {
+                warn!("Failed to dismiss crash report: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::Hook;
+    use crate::patch::Patch;
+    use crate::plugin::Plugin;
+    use crate::plugin::PluginPass;
+    use eframe::CreationContext;
+    use eyre::bail;
+    use eyre::eyre;
+    use serde_json::json;
+    use serde_json::Value;
+    use std::env;
+    use std::process;
+
+    struct Fake;
+
+    impl Plugin for Fake {
+        fn load(_cc: &CreationContext<'_>) -> Result<Self> {
+            bail!("Only made by the tests");
+        }
+
+        fn pass(&self) -> PluginPass {
+            PluginPass::Late
+        }
+
+        fn name(&self) -> String {
+            "Fake".to_owned()
+        }
+
+        fn key(&self) -> String {
+            "fake".to_owned()
+        }
+
+        fn settings(&self) -> Value {
+            json!({ "enabled": true })
+        }
+
+        fn patches(&self) -> Vec<Patch> {
+            vec![Patch {
+                name: "Jump",
+                rva: 0x10isize,
+                vanilla: vec![0x76u8],
+                patched: vec![0xEBu8],
+                applied: true,
+            }]
+        }
+
+        fn hooks(&self) -> Vec<Hook> {
+            vec![Hook {
+                name: "Thing",
+                rva: 0x20isize,
+                enabled: false,
+            }]
+        }
+    }
+
+    #[test]
+    fn describe_plugins() {
+        let plugins: Plugins = vec![(Box::new(Fake), true), (Box::new(Fake), false)];
+
+        assert_eq!(
+            __describe_plugins(&plugins),
+            "Fake (fake, starb): {\"enabled\":true}
+    Jump at 0x10: patched ([EB])
+    Thing hook at 0x20: disabled
+Fake (fake, custom): {\"enabled\":true}
+    Jump at 0x10: patched ([EB])
+    Thing hook at 0x20: disabled
+"
+        );
+    }
+
+    #[test]
+    fn pending_report() -> Result<()> {
+        let sys_folder = env::temp_dir().join(format!("starb_crash_{}", process::id()));
+
+        if sys_folder.exists() {
+            fs::remove_dir_all(&sys_folder)?;
+        }
+
+        assert!(
+            __pending(&sys_folder).is_none(),
+            "Found a report in nothing"
+        );
+
+        __save(&sys_folder, "starb-crash-1.txt", "First")?;
+
+        let path = __save(&sys_folder, "starb-crash-2.txt", "Second")?;
+        let window = __pending(&sys_folder).ok_or_else(|| eyre!("No pending report"))?;
+
+        // Only the latest is shown
+        assert_eq!(window.path, path);
+        assert_eq!(window.text, "Second");
+
+        window.__dismiss();
+
+        assert!(
+            __pending(&sys_folder).is_none(),
+            "Still pending once dismissed"
+        );
+        assert!(path.exists(), "Removed the report itself");
+
+        fs::remove_dir_all(&sys_folder)?;
+
+        Ok(())
+    }
+}
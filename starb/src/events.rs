@@ -0,0 +1,409 @@
+//! Event bus, so plugins (and the control API) can react to things as they
+//! happen, rather than only when starb's window is repainted.
+//!
+//! Events are published by whatever notices them: settings changes, restart
+//! requests, and the watcher, which polls addresses for changes. Everyone
+//! subscribed gets every event, in the order they were published. Plugins get
+//! them in [`Plugin::on_event`], on starb's own thread.
+//!
+//! [`Plugin::on_event`]: crate::plugin::Plugin::on_event
+
+use crate::app::PLUGINS;
+use crate::safe_mode;
+use crate::scanner::Memory;
+use crate::scanner::ProcessMemory;
+use crate::scanner::Value;
+use crate::scanner::ValueType;
+use crate::scanner::Watch;
+use crate::utils::base;
+use crate::utils::PointerChain;
+use egui::Context;
+use eyre::Result;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use tracing::trace;
+
+/// How often watched addresses are checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(100u64);
+/// Where SE keeps the search radius, in parsecs.
+const SEARCH_RADIUS_RVA: isize = 0x10457B0isize;
+
+static BUS: Bus = Bus::new();
+static WATCHER: Mutex<Watcher> = Mutex::new(Watcher::new());
+
+/// Something that happened. Over the control API, these are `event`
+/// notifications with `{ "event": <name>, "data": { .. } }`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    /// Only published once where SE keeps its search progress has been set,
+    /// see [`search`](crate::search).
+    SearchStarted,
+    /// Same as `SearchStarted`.
+    SearchFinished {
+        seconds: f64,
+        checked: Option<u64>,
+        found: Option<u64>,
+    },
+    /// In parsecs.
+    SearchRadiusChanged {
+        old: f64,
+        new: f64,
+    },
+    RestartRequested {
+        name: String,
+        reason: String,
+    },
+    /// A plugin's settings changed, from the GUI, a hotkey or the control
+    /// API. This includes being toggled.
+    PluginChanged {
+        key: String,
+        settings: serde_json::Value,
+    },
+    /// A whole profile of settings was imported, after each plugin's
+    /// `PluginChanged`.
+    ProfileImported {
+        keys: Vec<String>,
+    },
+    /// Something registered with [`watch`] changed.
+    ValueChanged {
+        label: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+/// Sends every event to every subscriber. There's one of these for all of
+/// starb, see [`publish`] and [`subscribe`].
+pub struct Bus {
+    subscribers: Mutex<Vec<Sender<Event>>>,
+}
+
+impl Bus {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(vec![]),
+        }
+    }
+
+    /// Every event published from now on. Dropping this unsubscribes.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.subscribers.lock().push(sender);
+
+        receiver
+    }
+
+    pub fn publish(&self, event: &Event) {
+        trace!(?event, "Publishing");
+
+        // Sent while locked, so everyone sees the same order
+        self.subscribers
+            .lock()
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns a watched value's change into an event.
+type ToEvent = Box<dyn Fn(Value, Value) -> Event + Send>;
+
+/// Addresses that publish an event when what's there changes.
+pub struct Watcher {
+    watched: Vec<(Watch, Option<Value>, ToEvent)>,
+}
+
+impl Watcher {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { watched: vec![] }
+    }
+
+    /// Publish `to_event(old, new)` whenever `watch` changes.
+    pub fn watch(&mut self, watch: Watch, to_event: ToEvent) {
+        self.watched.push((watch, None, to_event));
+    }
+
+    /// Check everything once, relative to `base`. Anything that couldn't be
+    /// read is checked from scratch next time, rather than counted as a
+    /// change.
+    pub fn poll(&mut self, memory: &dyn Memory, base: usize, bus: &Bus) {
+        for watched in &mut self.watched {
+            let new = watched.0.read(memory, base).ok();
+
+            if let (Some(old), Some(new)) = (watched.1.as_ref(), new.as_ref()) {
+                // Not `!=`, so NaN isn't always a change
+                if old.to_bytes() != new.to_bytes() {
+                    bus.publish(&(watched.2)(old.clone(), new.clone()));
+                }
+            }
+
+            watched.1 = new;
+        }
+    }
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Publish `event` to everyone subscribed.
+pub fn publish(event: &Event) {
+    BUS.publish(event);
+}
+
+/// Every event published from now on. See [`Bus::subscribe`].
+pub fn subscribe() -> Receiver<Event> {
+    BUS.subscribe()
+}
+
+/// Publish [`Event::ValueChanged`] whenever `watch` changes. Its label is used
+/// as the event's.
+pub fn watch(watch: Watch) {
+    let label = watch.label.clone();
+
+    WATCHER.lock().watch(
+        watch,
+        Box::new(move |old, new| Event::ValueChanged {
+            label: label.clone(),
+            old,
+            new,
+        }),
+    );
+}
+
+/// Start the watcher, and delivering events to plugins. `ctx` is repainted
+/// after every event, since something shown has likely changed.
+pub fn spawn(ctx: &Context) -> Result<()> {
+    WATCHER.lock().watch(
+        Watch::new(PointerChain::new(SEARCH_RADIUS_RVA, []), ValueType::Double),
+        Box::new(|old, new| match (old, new) {
+            (Value::Double(old), Value::Double(new)) => Event::SearchRadiusChanged { old, new },
+            _ => unreachable!("Watched as a double"),
+        }),
+    );
+
+    let events = subscribe();
+
+    thread::Builder::new()
+        .name("starb-events".to_owned())
+        .spawn(move || {
+            for event in events {
+                __deliver(&event);
+            }
+        })?;
+
+    let events = subscribe();
+    let ctx = ctx.clone();
+
+    thread::Builder::new()
+        .name("starb-repaint".to_owned())
+        .spawn(move || {
+            for _ in events {
+                ctx.request_repaint();
+            }
+        })?;
+
+    thread::Builder::new()
+        .name("starb-watcher".to_owned())
+        .spawn(|| loop {
+            WATCHER.lock().poll(&ProcessMemory, base() as usize, &BUS);
+
+            thread::sleep(POLL_INTERVAL);
+        })?;
+
+    Ok(())
+}
+
+fn __deliver(event: &Event) {
+    let Some(plugins) = PLUGINS.get()
+    else {
+        return;
+    };
+
+    for plugin in plugins.lock().iter_mut() {
+        let _active = safe_mode::enter(plugin.0.key());
+
+        plugin.0.on_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Region;
+    use eyre::bail;
+    use std::cell::RefCell;
+
+    /// A single page of memory at 0x1000.
+    struct Page(RefCell<Vec<u8>>);
+
+    impl Page {
+        fn write(&self, address: usize, bytes: &[u8]) {
+            self.0.borrow_mut()[address - 0x1000usize..][..bytes.len()].copy_from_slice(bytes);
+        }
+    }
+
+    impl Memory for Page {
+        fn regions(&self) -> Result<Vec<Region>> {
+            Ok(vec![Region {
+                base: 0x1000usize,
+                len: 0x1000usize,
+            }])
+        }
+
+        fn read(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+            let Some(bytes) = address.checked_sub(0x1000usize).and_then(|offset| {
+                self.0
+                    .borrow()
+                    .get(offset..offset + buf.len())
+                    .map(<[u8]>::to_vec)
+            })
+            else {
+                bail!("Unmapped");
+            };
+
+            buf.copy_from_slice(&bytes);
+
+            Ok(())
+        }
+    }
+
+    fn __changed(label: &str, old: i32, new: i32) -> Event {
+        Event::ValueChanged {
+            label: label.to_owned(),
+            old: Value::Int(old),
+            new: Value::Int(new),
+        }
+    }
+
+    #[test]
+    fn ordered() {
+        let bus = Bus::new();
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+
+        thread::scope(|scope| {
+            for thread in 0i32..4i32 {
+                let bus = &bus;
+
+                scope.spawn(move || {
+                    for i in 0i32..100i32 {
+                        bus.publish(&__changed("", thread, i));
+                    }
+                });
+            }
+        });
+
+        let first = first.try_iter().collect::<Vec<_>>();
+
+        assert_eq!(first.len(), 400usize);
+        assert_eq!(second.try_iter().collect::<Vec<_>>(), first);
+
+        // Each thread's events are still in the order it published them
+        for thread in 0i32..4i32 {
+            let from_thread = first
+                .iter()
+                .filter_map(|event| match *event {
+                    Event::ValueChanged {
+                        old: Value::Int(old),
+                        new: Value::Int(new),
+                        ..
+                    } if old == thread => Some(new),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            assert_eq!(from_thread, (0i32..100i32).collect::<Vec<_>>());
+        }
+
+        // Gone subscribers are dropped
+        drop(second);
+        bus.publish(&Event::SearchStarted);
+
+        assert_eq!(bus.subscribers.lock().len(), 1usize);
+    }
+
+    #[test]
+    fn watching() -> Result<()> {
+        let bus = Bus::new();
+        let events = bus.subscribe();
+        let memory = Page(RefCell::new(vec![0u8; 0x1000usize]));
+        let mut watcher = Watcher::new();
+
+        for (label, chain) in [("direct", "0x10"), ("pointer", "0x20 -> 0x4")] {
+            let mut watch = Watch::new(chain.parse()?, ValueType::Int);
+            watch.label = label.to_owned();
+
+            let label = label.to_owned();
+            watcher.watch(
+                watch,
+                Box::new(move |old, new| Event::ValueChanged {
+                    label: label.clone(),
+                    old,
+                    new,
+                }),
+            );
+        }
+
+        memory.write(0x1010usize, &5i32.to_le_bytes());
+        // Nothing to compare to the first time
+        watcher.poll(&memory, 0x1000usize, &bus);
+        watcher.poll(&memory, 0x1000usize, &bus);
+
+        assert_eq!(events.try_iter().count(), 0usize);
+
+        memory.write(0x1010usize, &6i32.to_le_bytes());
+        watcher.poll(&memory, 0x1000usize, &bus);
+
+        // The pointer's null until now
+        memory.write(0x1020usize, &0x1100usize.to_le_bytes());
+        memory.write(0x1104usize, &1i32.to_le_bytes());
+        watcher.poll(&memory, 0x1000usize, &bus);
+        memory.write(0x1104usize, &2i32.to_le_bytes());
+        memory.write(0x1010usize, &7i32.to_le_bytes());
+        watcher.poll(&memory, 0x1000usize, &bus);
+
+        assert_eq!(events.try_iter().collect::<Vec<_>>(), [
+            __changed("direct", 5i32, 6i32),
+            __changed("direct", 6i32, 7i32),
+            __changed("pointer", 1i32, 2i32),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn serialized() -> Result<()> {
+        assert_eq!(
+            serde_json::to_value(Event::SearchRadiusChanged {
+                old: 100.0f64,
+                new: 500.0f64
+            })?,
+            serde_json::json!({
+                "event": "search_radius_changed",
+                "data": { "old": 100.0f64, "new": 500.0f64 },
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(__changed("a", 1i32, 2i32))?["data"],
+            serde_json::json!({ "label": "a", "old": 1i32, "new": 2i32 })
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,516 @@
+//! Typed access to SE's memory. Instead of raw offsets like
+//! `base().byte_offset(0x1046F18isize).cast::<usize>()`, describe what's there
+//! once with [`GameStruct`]:
+//!
+//! ```ignore
+//! #[derive(GameStruct)]
+//! struct SearchText {
+//!     #[game(offset = 0x0)]
+//!     text: *const u16,
+//!     #[game(offset = 0x10)]
+//!     len: usize,
+//! }
+//!
+//! let search_text = SearchTextRef::from_rva(0x1046F08isize);
+//! let len = search_text.len().read()?;
+//! let first = search_text.text().get()?.read()?;
+//!
+//! // Or somewhere on the heap
+//! let search_text = PointerChain::new(0x1046F08isize, [0x8isize]).get::<SearchTextRef>()?;
+//! ```
+//!
+//! The derive adds `SearchTextRef`, which has an accessor for each field.
+//! Fields can be numbers, pointers (`*const T` or `*mut T`), arrays, or other
+//! game structs. Everything goes through `ReadProcessMemory` and
+//! `WriteProcessMemory`, so it fails instead of crashing on unmapped memory,
+//! and null pointers and out of bounds indices are errors too. Only code has
+//! its protection changed to write to it, since SE's other threads use
+//! everything else while it's being accessed. The offsets are still up to
+//! whoever writes the struct, though.
+
+use crate::utils;
+use crate::utils::base;
+use eyre::bail;
+use eyre::Result;
+pub use starb_macros::GameStruct;
+use std::array;
+use std::marker::PhantomData;
+use std::mem::align_of;
+use std::mem::size_of;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::slice;
+
+/// Used by [`GameStruct`]'s generated code.
+#[doc(hidden)]
+pub mod __private {
+    pub use eyre::Result;
+
+    #[must_use]
+    pub const fn max(a: usize, b: usize) -> usize {
+        if a > b {
+            a
+        }
+        else {
+            b
+        }
+    }
+
+    /// `size` rounded up to a multiple of `align`, like a C compiler pads the
+    /// end of a struct so arrays of it stay aligned.
+    #[must_use]
+    pub const fn round_up(size: usize, align: usize) -> usize {
+        match size % align {
+            0usize => size,
+            rest => size + (align - rest),
+        }
+    }
+}
+
+/// Somewhere in SE's memory.
+pub trait Located: Copy {
+    fn at(address: usize) -> Self;
+
+    fn address(self) -> usize;
+
+    /// At `rva` from SE's [`base`].
+    #[must_use]
+    fn from_rva(rva: isize) -> Self {
+        Self::at((base() as usize).wrapping_add_signed(rva))
+    }
+}
+
+/// Anything that can be a field of a [`GameStruct`].
+pub trait Field: Sized {
+    /// How it's accessed where it is.
+    type Ref: Located;
+
+    /// Size in SE's memory, in bytes.
+    const SIZE: usize;
+
+    /// Copy it out of SE's memory.
+    fn read(address: usize) -> Result<Self>;
+}
+
+/// Numbers, which can be read and written as they are.
+pub trait Primitive: Copy {}
+
+/// A [`Primitive`] in SE's memory.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Value<T> {
+    address: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Primitive> Value<T> {
+    pub fn read(self) -> Result<T> {
+        __read(self.address)
+    }
+
+    pub fn write(self, value: T) -> Result<()> {
+        __write(self.address, value)
+    }
+}
+
+/// A pointer in SE's memory, to an `E`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Pointer<E> {
+    address: usize,
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E: Field> Pointer<E> {
+    /// What this points to.
+    pub fn get(self) -> Result<E::Ref> {
+        match __read::<usize>(self.address)? {
+            0usize => bail!("Null pointer at {:#X}", self.address),
+            target => Ok(E::Ref::at(target)),
+        }
+    }
+
+    /// Point this somewhere else.
+    pub fn set(self, target: E::Ref) -> Result<()> {
+        __write(self.address, target.address())
+    }
+
+    pub fn is_null(self) -> Result<bool> {
+        Ok(__read::<usize>(self.address)? == 0usize)
+    }
+}
+
+/// An array of `N` `E`s in SE's memory.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Array<E, const N: usize> {
+    address: usize,
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E: Field, const N: usize> Array<E, N> {
+    pub const LEN: usize = N;
+
+    /// Element `i`, if it's in bounds.
+    pub fn get(self, i: usize) -> Result<E::Ref> {
+        if i >= N {
+            bail!("Index {i} is out of bounds of an array of {N}");
+        }
+
+        Ok(E::Ref::at(self.address.wrapping_add(i * E::SIZE)))
+    }
+
+    /// Every element.
+    pub fn iter(self) -> impl Iterator<Item = E::Ref> {
+        (0usize..N).map(move |i| E::Ref::at(self.address.wrapping_add(i * E::SIZE)))
+    }
+}
+
+// Deriving these would require `T: Clone` and so on, which isn't needed
+macro_rules! __located {
+    ($($ty:ident<$param:ident $(, const $n:ident: usize)?>),*) => {
+        $(
+            impl<$param $(, const $n: usize)?> Clone for $ty<$param $(, $n)?> {
+                fn clone(&self) -> Self {
+                    *self
+                }
+            }
+
+            impl<$param $(, const $n: usize)?> Copy for $ty<$param $(, $n)?> {}
+
+            impl<$param $(, const $n: usize)?> Located for $ty<$param $(, $n)?> {
+                fn at(address: usize) -> Self {
+                    Self {
+                        address,
+                        _marker: PhantomData,
+                    }
+                }
+
+                fn address(self) -> usize {
+                    self.address
+                }
+            }
+        )*
+    };
+}
+
+__located!(Value<T>, Pointer<E>, Array<E, const N: usize>);
+
+macro_rules! __primitive {
+    ($($ty:ty),*) => {
+        $(
+            impl Primitive for $ty {}
+
+            impl Field for $ty {
+                type Ref = Value<Self>;
+
+                const SIZE: usize = size_of::<Self>();
+
+                fn read(address: usize) -> Result<Self> {
+                    __read(address)
+                }
+            }
+        )*
+    };
+}
+
+__primitive!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+
+impl<E: Field> Field for *const E {
+    type Ref = Pointer<E>;
+
+    const SIZE: usize = size_of::<usize>();
+
+    fn read(address: usize) -> Result<Self> {
+        Ok(__read::<usize>(address)? as Self)
+    }
+}
+
+impl<E: Field> Field for *mut E {
+    type Ref = Pointer<E>;
+
+    const SIZE: usize = size_of::<usize>();
+
+    fn read(address: usize) -> Result<Self> {
+        Ok(__read::<usize>(address)? as Self)
+    }
+}
+
+impl<E: Field, const N: usize> Field for [E; N] {
+    type Ref = Array<E, N>;
+
+    const SIZE: usize = E::SIZE * N;
+
+    fn read(address: usize) -> Result<Self> {
+        let mut error = None;
+        let elements = array::from_fn::<_, N, _>(|i| {
+            E::read(address.wrapping_add(i * E::SIZE))
+                .map_err(|e| error = Some(e))
+                .ok()
+        });
+
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        Ok(elements.map(|element| element.expect("Unreachable")))
+    }
+}
+
+/// Whether a `T` can be at `address` at all.
+fn __check<T>(address: usize) -> Result<()> {
+    if address == 0usize {
+        bail!("Null pointer");
+    }
+
+    if address & (align_of::<T>() - 1usize) != 0usize {
+        bail!(
+            "{address:#X} isn't aligned for `{}`",
+            std::any::type_name::<T>()
+        );
+    }
+
+    Ok(())
+}
+
+fn __read<T: Copy>(address: usize) -> Result<T> {
+    __check::<T>(address)?;
+
+    let mut value = MaybeUninit::<T>::zeroed();
+
+    // SAFETY: `T` is only ever a number, so any bytes are fine
+    __read_bytes(address, unsafe {
+        slice::from_raw_parts_mut(value.as_mut_ptr().cast::<u8>(), size_of::<T>())
+    })?;
+
+    // SAFETY: Same as above
+    Ok(unsafe { value.assume_init() })
+}
+
+fn __write<T: Copy>(address: usize, value: T) -> Result<()> {
+    __check::<T>(address)?;
+
+    let region = region::query(address as *const u8)?;
+
+    // Patching code. Its protection has to change for that, but nothing else
+    // writes to it, so putting it back afterwards can't get in anyone's way
+    if region.is_executable() || !region.is_writable() {
+        // SAFETY: It's mapped, `write` checks that much. What it is is up to whoever
+        // described it
+        return unsafe { utils::write(address as *mut T, value) };
+    }
+
+    // SAFETY: Same as `__read`
+    __write_bytes(address, unsafe {
+        slice::from_raw_parts(ptr::addr_of!(value).cast::<u8>(), size_of::<T>())
+    })
+}
+
+#[cfg(any(windows, not(test)))]
+fn __read_bytes(address: usize, buf: &mut [u8]) -> Result<()> {
+    use crate::scanner::Memory;
+    use crate::scanner::ProcessMemory;
+
+    ProcessMemory.read(address, buf)
+}
+
+#[cfg(any(windows, not(test)))]
+fn __write_bytes(address: usize, bytes: &[u8]) -> Result<()> {
+    use std::ffi::c_void;
+    use windows_sys::Win32::System::Diagnostics::Debug::WriteProcessMemory;
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    let mut written = 0usize;
+
+    // This fails instead of crashing if it's been freed since
+    if unsafe {
+        WriteProcessMemory(
+            GetCurrentProcess(),
+            address as *const c_void,
+            bytes.as_ptr().cast(),
+            bytes.len(),
+            ptr::addr_of_mut!(written),
+        )
+    } == 0i32
+        || written != bytes.len()
+    {
+        bail!("Failed to write {} bytes at {address:#X}", bytes.len());
+    }
+
+    Ok(())
+}
+
+// There's no `ReadProcessMemory` or `WriteProcessMemory` to test with
+// elsewhere, so check it's mapped with `region` instead
+#[cfg(all(test, not(windows)))]
+fn __read_bytes(address: usize, buf: &mut [u8]) -> Result<()> {
+    __mapped(address, buf.len())?;
+
+    unsafe { ptr::copy_nonoverlapping(address as *const u8, buf.as_mut_ptr(), buf.len()) };
+
+    Ok(())
+}
+
+#[cfg(all(test, not(windows)))]
+fn __write_bytes(address: usize, bytes: &[u8]) -> Result<()> {
+    __mapped(address, bytes.len())?;
+
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, bytes.len()) };
+
+    Ok(())
+}
+
+#[cfg(all(test, not(windows)))]
+fn __mapped(address: usize, len: usize) -> Result<()> {
+    // Nothing's returned for what isn't mapped at all, so check there's no gap
+    let mut mapped = address;
+
+    for region in region::query_range(address as *const u8, len)? {
+        let region = region?;
+
+        if !region.is_readable() || region.as_range().start > mapped {
+            bail!("Failed to access {len} bytes at {address:#X}");
+        }
+
+        mapped = region.as_range().end;
+    }
+
+    if mapped < address + len {
+        bail!("Failed to access {len} bytes at {address:#X}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use region::Protection;
+
+    #[derive(Debug, GameStruct, PartialEq)]
+    #[game(size = 0x10)]
+    struct Vector {
+        #[game(offset = 0x0)]
+        x: f32,
+        #[game(offset = 0x8)]
+        y: f64,
+    }
+
+    /// Without `#[game(size)]`, so it's padded to 0x10 like in C.
+    #[derive(Debug, GameStruct, PartialEq)]
+    struct Padded {
+        #[game(offset = 0x0)]
+        big: u64,
+        #[game(offset = 0x8)]
+        small: u8,
+    }
+
+    #[derive(Debug, GameStruct, PartialEq)]
+    struct Browser {
+        #[game(offset = 0x0)]
+        count: u32,
+        #[game(offset = 0x8)]
+        position: Vector,
+        #[game(offset = 0x18)]
+        ids: [u16; 4usize],
+        /// Somewhere else
+        #[game(offset = 0x20)]
+        target: *const Vector,
+        #[game(offset = 0x28)]
+        vectors: [Vector; 2usize],
+    }
+
+    #[test]
+    fn derived() -> Result<()> {
+        assert_eq!(<Vector as Field>::SIZE, 0x10usize);
+        assert_eq!(<Browser as Field>::SIZE, 0x48usize);
+
+        let mut memory = region::alloc(0x1000usize, Protection::READ_WRITE)?;
+        let base = memory.as_mut_ptr::<u8>() as usize;
+        let browser = BrowserRef::at(base);
+
+        browser.count().write(5u32)?;
+        browser.position().x().write(1.5f32)?;
+        browser.position().y().write(-2.0f64)?;
+        browser.ids().get(3usize)?.write(7u16)?;
+        browser.vectors().get(1usize)?.y().write(3.0f64)?;
+
+        assert!(browser.target().is_null()?);
+        assert!(browser.target().get().is_err());
+        assert!(browser.ids().get(4usize).is_err());
+
+        browser.target().set(browser.vectors().get(1usize)?)?;
+
+        assert_eq!(browser.target().get()?.y().read()?, 3.0f64);
+        assert_eq!(browser.ids().iter().count(), 4usize);
+        assert_eq!(browser.read()?, Browser {
+            count: 5u32,
+            position: Vector {
+                x: 1.5f32,
+                y: -2.0f64,
+            },
+            ids: [0u16, 0u16, 0u16, 7u16],
+            target: (base + 0x38usize) as *const Vector,
+            vectors: [
+                Vector {
+                    x: 0.0f32,
+                    y: 0.0f64,
+                },
+                Vector {
+                    x: 0.0f32,
+                    y: 3.0f64,
+                },
+            ],
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn checks() -> Result<()> {
+        let mut memory = region::alloc(0x1000usize, Protection::READ_WRITE)?;
+        let base = memory.as_mut_ptr::<u8>() as usize;
+
+        assert!(Value::<u32>::at(0usize).read().is_err());
+        assert!(Value::<u32>::at(base + 1usize).read().is_err());
+        assert!(Value::<u32>::at(base + 1usize).write(1u32).is_err());
+        assert_eq!(Value::<u32>::at(base + 4usize).read()?, 0u32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn padded() -> Result<()> {
+        assert_eq!(
+            <Padded as Field>::SIZE,
+            0x10usize,
+            "Should be padded to its alignment"
+        );
+        assert_eq!(
+            <[Padded; 2usize] as Field>::SIZE,
+            0x20usize,
+            "Arrays should be of padded elements"
+        );
+        assert_eq!(Array::<Padded, 2usize>::LEN, 2usize, "Wrong length");
+
+        let mut memory = region::alloc(0x1000usize, Protection::READ_WRITE)?;
+        let base = memory.as_mut_ptr::<u8>() as usize;
+        let padded = Array::<Padded, 2usize>::at(base);
+
+        padded.get(1usize)?.big().write(5u64)?;
+        padded.get(1usize)?.small().write(6u8)?;
+
+        assert_eq!(
+            Value::<u64>::at(base + 0x10usize).read()?,
+            5u64,
+            "The second element should be after the padding"
+        );
+        assert_eq!(
+            <[Padded; 2usize] as Field>::read(base)?[1usize],
+            Padded {
+                big: 5u64,
+                small: 6u8,
+            },
+            "Wrong second element"
+        );
+
+        Ok(())
+    }
+}
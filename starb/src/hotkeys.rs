@@ -0,0 +1,399 @@
+//! Hotkeys, so plugins can be toggled without leaving SE. Any on/off setting
+//! of a plugin, or any of its [`Plugin::actions`], can be bound to a chord like
+//! `Ctrl+Shift+F5`.
+//!
+//! Keys are polled with `GetAsyncKeyState`, and only while one of SE's own
+//! windows has focus, so typing in starb's window (or another program) never
+//! triggers anything. Chords themselves are in [`starb_hotkeys`].
+//!
+//! [`Plugin::actions`]: crate::plugin::Plugin::actions
+
+use crate::app::PLUGINS;
+use crate::events;
+use crate::events::Event;
+use crate::safe_mode;
+use eframe::Storage;
+use egui::Color32;
+use egui::ComboBox;
+use egui::RichText;
+use egui::TextEdit;
+use egui::Ui;
+use eyre::bail;
+use eyre::eyre;
+use eyre::Result;
+use parking_lot::Mutex;
+use serde_json::Value;
+use starb_hotkeys::Binding;
+use starb_hotkeys::Target;
+use starb_hotkeys::VK_CONTROL;
+use starb_hotkeys::VK_MENU;
+use starb_hotkeys::VK_SHIFT;
+use std::collections::HashSet;
+use std::ptr::addr_of_mut;
+use std::thread;
+use std::time::Duration;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+use windows_sys::Win32::System::Threading::GetCurrentProcessId;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::GetAsyncKeyState;
+use windows_sys::Win32::UI::WindowsAndMessaging::GetForegroundWindow;
+use windows_sys::Win32::UI::WindowsAndMessaging::GetWindowTextW;
+use windows_sys::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
+
+/// Storage key of the bindings, as a JSON string.
+pub const BINDINGS_KEY: &str = "starb_hotkeys";
+
+/// How often keys are checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(15u64);
+
+static BINDINGS: Mutex<Vec<Binding>> = Mutex::new(vec![]);
+
+/// Replace every binding. Fails if any of them conflict.
+pub fn set(bindings: Vec<Binding>) -> Result<()> {
+    if let Some(&(i, j)) = starb_hotkeys::conflicts(&bindings).first() {
+        bail!(
+            "`{}` is bound to both {} of `{}` and {} of `{}`",
+            bindings[i].chord,
+            bindings[i].target,
+            bindings[i].plugin,
+            bindings[j].target,
+            bindings[j].plugin,
+        );
+    }
+
+    *BINDINGS.lock() = bindings;
+
+    Ok(())
+}
+
+#[must_use]
+pub fn get() -> Vec<Binding> {
+    BINDINGS.lock().clone()
+}
+
+/// Load the bindings from `storage`.
+pub fn load(storage: Option<&dyn Storage>) {
+    let Some(bindings) =
+        storage.and_then(|storage| eframe::get_value::<String>(storage, BINDINGS_KEY))
+    else {
+        return;
+    };
+
+    let bindings = serde_json::from_str(&bindings)
+        .map_err(Into::into)
+        .and_then(set);
+
+    if let Err(e) = bindings {
+        error!("Hotkeys in settings are invalid: {e}");
+    }
+}
+
+pub fn save(storage: &mut dyn Storage) {
+    match serde_json::to_string(&*BINDINGS.lock()) {
+        Ok(bindings) => eframe::set_value(storage, BINDINGS_KEY, &bindings),
+        Err(e) => error!("Failed to save hotkeys: {e}"),
+    }
+}
+
+/// Start checking for hotkeys on their own thread.
+pub fn spawn() -> Result<()> {
+    thread::Builder::new()
+        .name("starb-hotkeys".to_owned())
+        .spawn(__poll)?;
+
+    Ok(())
+}
+
+fn __poll() {
+    let mut before = HashSet::new();
+
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        let bindings = get();
+
+        if bindings.is_empty() {
+            continue;
+        }
+
+        // Only the keys that matter, there's no reason to check all 256
+        let now = bindings
+            .iter()
+            .map(|binding| binding.chord.key)
+            .chain([VK_CONTROL, VK_SHIFT, VK_MENU])
+            .filter(|&key| unsafe { GetAsyncKeyState(i32::from(key)) } < 0i16)
+            .collect::<HashSet<_>>();
+
+        if __se_has_focus() {
+            for binding in &bindings {
+                if binding
+                    .chord
+                    .pressed(|key| before.contains(&key), |key| now.contains(&key))
+                {
+                    info!("{} pressed", binding.chord);
+
+                    if let Err(e) = __fire(binding) {
+                        warn!("Hotkey `{}` failed: {e}", binding.chord);
+                    }
+                }
+            }
+        }
+
+        // Updated even without focus, so keys held while switching to SE don't count
+        before = now;
+    }
+}
+
+fn __fire(binding: &Binding) -> Result<()> {
+    let mut plugins = PLUGINS.get().expect("Unreachable").lock();
+    let plugin = plugins
+        .iter_mut()
+        .find(|plugin| plugin.0.key() == binding.plugin)
+        .ok_or_else(|| eyre!("No plugin with key `{}`", binding.plugin))?;
+
+    let _active = safe_mode::enter(plugin.0.key());
+
+    match binding.target {
+        Target::Toggle(ref name) => {
+            let mut settings = plugin.0.settings();
+
+            let Some(&mut Value::Bool(ref mut value)) = settings.get_mut(name)
+            else {
+                bail!("`{}` has no on/off setting `{name}`", binding.plugin);
+            };
+
+            *value = !*value;
+
+            plugin.0.set_settings(settings)?;
+        },
+        Target::Action(ref name) => plugin.0.run_action(name)?,
+    }
+
+    events::publish(&Event::PluginChanged {
+        key: plugin.0.key(),
+        settings: plugin.0.settings(),
+    });
+
+    Ok(())
+}
+
+/// Whether the foreground window is SE's, and not starb's.
+fn __se_has_focus() -> bool {
+    let hwnd = unsafe { GetForegroundWindow() };
+
+    if hwnd == 0isize {
+        return false;
+    }
+
+    let mut pid = 0u32;
+    unsafe { GetWindowThreadProcessId(hwnd, addr_of_mut!(pid)) };
+
+    if pid != unsafe { GetCurrentProcessId() } {
+        return false;
+    }
+
+    let mut title = [0u16; 256usize];
+    let title_len = unsafe { GetWindowTextW(hwnd, title.as_mut_ptr(), title.len() as i32) };
+    let title = String::from_utf16_lossy(&title[..title_len.max(0i32) as usize]);
+
+    !title.starts_with("Star Browser Utilities")
+}
+
+/// State of the hotkeys tab. Changes are only applied once they're valid.
+#[derive(Default)]
+pub struct HotkeyEditor {
+    rows: Option<Vec<Row>>,
+    error: Option<String>,
+}
+
+struct Row {
+    chord: String,
+    plugin: String,
+    target: Option<Target>,
+}
+
+/// A plugin, and what of it can be bound.
+struct Bindable {
+    key: String,
+    name: String,
+    targets: Vec<Target>,
+}
+
+impl HotkeyEditor {
+    pub fn ui(&mut self, ui: &mut Ui) {
+        let rows = self.rows.get_or_insert_with(|| {
+            get()
+                .into_iter()
+                .map(|binding| Row {
+                    chord: binding.chord.to_string(),
+                    plugin: binding.plugin,
+                    target: Some(binding.target),
+                })
+                .collect()
+        });
+
+        let plugins = PLUGINS
+            .get()
+            .expect("Unreachable")
+            .lock()
+            .iter()
+            .map(|plugin| {
+                let toggles = match plugin.0.settings() {
+                    Value::Object(settings) => settings
+                        .into_iter()
+                        .filter(|setting| setting.1.is_boolean())
+                        .map(|setting| Target::Toggle(setting.0))
+                        .collect(),
+                    _ => vec![],
+                };
+                let actions = plugin
+                    .0
+                    .actions()
+                    .into_iter()
+                    .map(|(name, _)| Target::Action(name));
+
+                Bindable {
+                    key: plugin.0.key(),
+                    name: plugin.0.name(),
+                    targets: toggles.into_iter().chain(actions).collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        ui.label("Hotkeys only work while SE's window is focused, e.g. `Ctrl+Shift+F5`.");
+        ui.separator();
+
+        let mut remove = None;
+
+        for (i, row) in rows.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(
+                    TextEdit::singleline(&mut row.chord)
+                        .hint_text("Ctrl+F5")
+                        .desired_width(120f32),
+                );
+
+                let plugin_name = plugins
+                    .iter()
+                    .find(|plugin| plugin.key == row.plugin)
+                    .map_or_else(|| row.plugin.clone(), |plugin| plugin.name.clone());
+
+                ComboBox::from_id_source(("hotkey_plugin", i))
+                    .selected_text(plugin_name)
+                    .show_ui(ui, |ui| {
+                        for plugin in &plugins {
+                            let selected = row.plugin == plugin.key;
+
+                            if ui.selectable_label(selected, &plugin.name).clicked() {
+                                row.plugin = plugin.key.clone();
+                                row.target = None;
+                            }
+                        }
+                    });
+
+                ComboBox::from_id_source(("hotkey_target", i))
+                    .selected_text(
+                        row.target
+                            .as_ref()
+                            .map_or_else(String::new, ToString::to_string),
+                    )
+                    .show_ui(ui, |ui| {
+                        let targets = plugins
+                            .iter()
+                            .find(|plugin| plugin.key == row.plugin)
+                            .map(|plugin| plugin.targets.as_slice())
+                            .unwrap_or_default();
+
+                        for target in targets {
+                            let selected = row.target.as_ref() == Some(target);
+
+                            if ui.selectable_label(selected, target.to_string()).clicked() {
+                                row.target = Some(target.clone());
+                            }
+                        }
+                    });
+
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+
+        if let Some(i) = remove {
+            rows.remove(i);
+        }
+
+        let mut revert = false;
+
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                rows.push(Row {
+                    chord: String::new(),
+                    plugin: plugins
+                        .first()
+                        .map(|plugin| plugin.key.clone())
+                        .unwrap_or_default(),
+                    target: None,
+                });
+            }
+
+            if ui.button("Apply").clicked() {
+                self.error = __bindings(rows).and_then(set).err().map(|e| e.to_string());
+            }
+
+            revert = ui.button("Revert").clicked();
+        });
+
+        if revert {
+            self.rows = None;
+            self.error = None;
+        }
+
+        if let Some(e) = self.error.as_ref() {
+            ui.label(RichText::new(e).color(Color32::RED));
+        }
+    }
+}
+
+fn __bindings(rows: &[Row]) -> Result<Vec<Binding>> {
+    rows.iter()
+        .map(|row| {
+            Ok(Binding {
+                chord: row.chord.parse()?,
+                plugin: row.plugin.clone(),
+                target: row
+                    .target
+                    .clone()
+                    .ok_or_else(|| eyre!("`{}` isn't bound to anything", row.chord))?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicting() -> Result<()> {
+        let binding = |chord: &str| -> Result<Binding> {
+            Ok(Binding {
+                chord: chord.parse()?,
+                plugin: "no_search_locking".to_owned(),
+                target: Target::Toggle("enabled".to_owned()),
+            })
+        };
+
+        assert!(
+            set(vec![binding("Ctrl+F5")?, binding("control+f5")?]).is_err(),
+            "Conflicting bindings shouldn't be set"
+        );
+        assert!(
+            set(vec![binding("F5")?, binding("Ctrl+F5")?]).is_ok(),
+            "Bindings that don't conflict should be set"
+        );
+
+        Ok(())
+    }
+}
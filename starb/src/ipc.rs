@@ -0,0 +1,200 @@
+//! starb's side of the control API (see [`starb_ipc`]), over [`PLUGINS`].
+//!
+//! While starb isn't running, `starb-ctl` can't use any of this. So, starb
+//! also keeps a copy of [`plugin_list`] in its storage, and picks up settings
+//! `starb-ctl` changed in the meantime when plugins are loaded.
+
+use crate::app::pending_restarts;
+use crate::app::PluginTy;
+use crate::app::PLUGINS;
+use crate::events;
+use crate::events::Event;
+use crate::safe_mode;
+use crate::search;
+use crate::self_test;
+use crate::utils;
+use eframe::Storage;
+use eyre::Result;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use starb_ipc::Handler;
+use starb_ipc::Server;
+use std::env;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use tracing::info;
+use tracing::warn;
+
+/// Storage key of the last [`plugin_list`], as a JSON string.
+pub const SETTINGS_KEY: &str = "starb_ctl_settings";
+/// Storage key of settings changed while starb wasn't running, as a JSON
+/// string. This is an object of plugin key -> settings.
+pub const PENDING_SETTINGS_KEY: &str = "starb_ctl_pending_settings";
+
+struct Plugins;
+
+impl Handler for Plugins {
+    fn plugins(&self) -> Value {
+        plugin_list()
+    }
+
+    fn settings(&self, key: &str) -> Option<Value> {
+        __with_plugin(key, |plugin| plugin.0.settings())
+    }
+
+    fn set(&self, key: &str, changes: &Map<String, Value>) -> Option<Result<Value>> {
+        __with_plugin(key, |plugin| __set(plugin, changes))
+    }
+
+    fn imported(&self, keys: Vec<String>) {
+        events::publish(&Event::ProfileImported { keys });
+    }
+
+    fn context(&self, key: Option<&str>) -> Value {
+        Value::Object(
+            PLUGINS
+                .get()
+                .expect("Unreachable")
+                .lock()
+                .iter()
+                .filter(|plugin| !key.is_some_and(|key| plugin.0.key() != key))
+                .map(|plugin| (plugin.0.key(), json!(plugin.0.context())))
+                .collect(),
+        )
+    }
+
+    fn restarts(&self) -> Value {
+        pending_restarts()
+            .into_iter()
+            .map(|(name, reason)| json!({ "name": name, "reason": reason }))
+            .collect()
+    }
+
+    fn self_test(&self) -> Value {
+        self_test::run().to_json()
+    }
+
+    fn search_history(&self) -> Value {
+        json!(search::history())
+    }
+}
+
+/// Bind the control API and serve it on its own thread. Its token is written
+/// next to SE's exe, for `starb-ctl`.
+pub fn spawn() -> Result<()> {
+    let addr = env::var("STARB_IPC_ADDR").unwrap_or_else(|_| starb_ipc::DEFAULT_ADDR.to_owned());
+    let listener = TcpListener::bind(&addr)?;
+    let token = starb_ipc::write_token(&utils::sys_folder()?)?;
+    let server = Arc::new(Server::new(Plugins, token));
+
+    info!("Control API listening on {addr}");
+
+    let serving = Arc::clone(&server);
+
+    thread::Builder::new()
+        .name("starb-ipc".to_owned())
+        .spawn(move || Server::serve(&serving, &listener))?;
+
+    let events = events::subscribe();
+
+    thread::Builder::new()
+        .name("starb-ipc-events".to_owned())
+        .spawn(move || {
+            for event in events {
+                server.notify("event", &event);
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Every plugin, with its settings. This is what `plugins.list` returns.
+pub fn plugin_list() -> Value {
+    PLUGINS
+        .get()
+        .expect("Unreachable")
+        .lock()
+        .iter()
+        .map(|plugin| {
+            json!({
+                "key": plugin.0.key(),
+                "name": plugin.0.name(),
+                "section": plugin.0.section(),
+                "priority": plugin.0.priority(),
+                "builtin": plugin.1,
+                "settings": plugin.0.settings(),
+            })
+        })
+        .collect()
+}
+
+/// Apply settings `starb-ctl` changed while starb wasn't running to a plugin
+/// that was just loaded.
+pub fn apply_pending_settings(storage: Option<&dyn Storage>, plugin: &mut PluginTy) {
+    let Some(pending) = storage
+        .and_then(|storage| eframe::get_value::<String>(storage, PENDING_SETTINGS_KEY))
+        .and_then(|pending| serde_json::from_str::<Map<String, Value>>(&pending).ok())
+    else {
+        return;
+    };
+
+    let Some(changes) = pending.get(&plugin.key()).and_then(Value::as_object)
+    else {
+        return;
+    };
+
+    let mut settings = match plugin.settings() {
+        Value::Object(settings) => settings,
+        _ => Map::new(),
+    };
+
+    settings.extend(changes.clone());
+
+    match plugin.set_settings(Value::Object(settings)) {
+        Ok(()) => info!(
+            "Applied settings changed by starb-ctl to `{}`",
+            plugin.name()
+        ),
+        Err(e) => warn!(
+            "Failed to apply settings changed by starb-ctl to `{}`: {e}",
+            plugin.name()
+        ),
+    }
+}
+
+/// Keep a copy of every plugin's settings for `starb-ctl`, and forget settings
+/// that were pending since they've been applied by now.
+pub fn save(storage: &mut dyn Storage) {
+    eframe::set_value(storage, SETTINGS_KEY, &plugin_list().to_string());
+    eframe::set_value(storage, PENDING_SETTINGS_KEY, &"{}".to_owned());
+}
+
+/// Change some of `plugin`'s settings, and let everyone know.
+fn __set(plugin: &mut (PluginTy, bool), changes: &Map<String, Value>) -> Result<Value> {
+    let mut settings = match plugin.0.settings() {
+        Value::Object(settings) => settings,
+        _ => Map::new(),
+    };
+
+    settings.extend(changes.clone());
+    plugin.0.set_settings(Value::Object(settings))?;
+
+    let settings = plugin.0.settings();
+
+    events::publish(&Event::PluginChanged {
+        key: plugin.0.key(),
+        settings: settings.clone(),
+    });
+
+    Ok(settings)
+}
+
+fn __with_plugin<T>(key: &str, f: impl FnOnce(&mut (PluginTy, bool)) -> T) -> Option<T> {
+    let mut plugins = PLUGINS.get().expect("Unreachable").lock();
+    let plugin = plugins.iter_mut().find(|plugin| plugin.0.key() == key)?;
+    let _active = safe_mode::enter(plugin.0.key());
+
+    Some(f(plugin))
+}
@@ -2,37 +2,145 @@
 #![feature(pointer_byte_offsets)]
 #![feature(vec_into_raw_parts)]
 
+// So `GameStruct` works in starb's own tests
+#[cfg(test)]
+extern crate self as speng_starb;
+
 pub mod app;
+pub mod appearance;
+pub mod crash;
+pub mod events;
+pub mod game;
+pub mod hotkeys;
+pub mod ipc;
+pub mod logging;
+pub mod patch;
 pub mod plugin;
 mod plugins;
+pub mod restart;
+pub mod safe_mode;
+pub mod scanner;
+pub mod search;
+pub mod self_test;
+pub mod setting;
+pub mod storage;
+pub mod tabs;
+pub mod tick;
 pub mod utils;
+pub mod version;
 
 use app::StarApp;
 use color_eyre::config::HookBuilder;
+use color_eyre::config::Theme;
 use eframe::NativeOptions;
 use std::env::current_exe;
-use std::fs::File;
+use std::ffi::c_char;
 use std::panic::set_hook;
-use std::sync::Arc;
+use std::ptr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::thread;
 use std::time::Duration;
 use tracing::error;
 use tracing::info;
-use tracing::Level;
+use windows_sys::s;
+use windows_sys::Win32::Foundation::GetLastError;
+use windows_sys::Win32::Foundation::ERROR_ALREADY_EXISTS;
 use windows_sys::Win32::Foundation::HMODULE;
 use windows_sys::Win32::System::SystemServices::DLL_PROCESS_ATTACH;
+use windows_sys::Win32::System::Threading::CreateMutexW;
+use windows_sys::Win32::System::Threading::GetCurrentProcessId;
+use windows_sys::Win32::UI::WindowsAndMessaging::MessageBoxA;
+use windows_sys::Win32::UI::WindowsAndMessaging::MB_ICONERROR;
+
+/// Version of how the proxy and starb talk to each other. Bump this whenever
+/// the exports below change, and keep it in sync with the proxy's.
+pub const ABI_VERSION: u32 = 1u32;
+
+/// What [`starb_start`] returns. Keep this in sync with the proxy's.
+#[repr(u32)]
+pub enum StartResult {
+    Started = 0u32,
+    /// The proxy's [`ABI_VERSION`] is different, so starb didn't start.
+    AbiMismatch = 1u32,
+    /// starb's already running in this process, possibly from another copy of
+    /// this DLL.
+    AlreadyStarted = 2u32,
+}
+
+/// Whether the proxy has called [`starb_start`], successfully or not.
+static START_CALLED: AtomicBool = AtomicBool::new(false);
 
 // WARNING: ACTUALLY OK CODE BELOW
 
 #[no_mangle]
 unsafe extern "system" fn DllMain(_: HMODULE, reason: u32, _: usize) -> bool {
     if reason == DLL_PROCESS_ATTACH {
-        thread::spawn(|| unsafe { __start_starb() });
+        // Proxies from before `starb_start` existed expect this to start starb on
+        // its own. Let the user know instead of doing nothing
+        thread::spawn(|| {
+            thread::sleep(Duration::from_secs(10u64));
+
+            if !START_CALLED.load(Ordering::Relaxed) {
+                unsafe {
+                    MessageBoxA(
+                        0isize,
+                        s!(
+                            "starb was loaded, but not started. The proxy (version.dll) is likely \
+                             from an older version of starb, please copy both from the same \
+                             version."
+                        ),
+                        s!("starb"),
+                        MB_ICONERROR,
+                    )
+                };
+            }
+        });
     }
 
     true
 }
 
+/// For the proxy to check it's talking to the starb it expects.
+#[no_mangle]
+extern "system" fn starb_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// starb's version, for the proxy to show when something doesn't match.
+#[no_mangle]
+extern "system" fn starb_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Start starb, if `abi_version` is ours and it isn't running already. This
+/// used to be done in `DllMain`, but then a mismatched proxy couldn't stop it.
+#[no_mangle]
+extern "system" fn starb_start(abi_version: u32) -> StartResult {
+    START_CALLED.store(true, Ordering::Relaxed);
+
+    if abi_version != ABI_VERSION {
+        return StartResult::AbiMismatch;
+    }
+
+    // Named, so this works across copies of this DLL too. This is never closed,
+    // since it's only to be released when SE closes
+    let name = format!("Local\\speng_starb_{}", unsafe { GetCurrentProcessId() })
+        .encode_utf16()
+        .chain([0u16])
+        .collect::<Vec<_>>();
+    let guard = unsafe { CreateMutexW(ptr::null(), 0i32, name.as_ptr()) };
+
+    // If creating it failed, it's better to start anyway
+    if guard != 0isize && unsafe { GetLastError() } == ERROR_ALREADY_EXISTS {
+        return StartResult::AlreadyStarted;
+    }
+
+    thread::spawn(|| unsafe { __start_starb() });
+
+    StartResult::Started
+}
+
 unsafe fn __start_starb() {
     // Just incase SE somehow changed current_dir before we got here
     let log = current_exe()
@@ -48,26 +156,29 @@ unsafe fn __start_starb() {
         return;
     }
 
-    tracing_subscriber::fmt()
-        .with_writer(Arc::new(
-            File::create(log).expect("This can't be seen. No point"),
-        ))
-        .with_max_level(Level::DEBUG)
-        .with_ansi(true)
-        .init();
+    logging::init(&log).expect("This can't be seen. No point");
+    safe_mode::begin();
 
+    // Blank theme, since logs are plain text
     let (ph, eh) = HookBuilder::default()
         .display_env_section(false)
-        .panic_section("Please report this at: https://github.com/Centri3/speng-starb/issues/new")
+        .theme(Theme::new())
+        .panic_section(format!("Please report this at: {}", crash::ISSUES_URL))
         .into_hooks();
 
     eh.install().expect("This can't be seen. No point");
 
     set_hook(Box::new(move |pi| {
-        error!(
-            "unexpected panic, handing off to color-eyre:\n\n{}",
-            ph.panic_report(pi)
-        );
+        let report = ph.panic_report(pi).to_string();
+
+        error!("unexpected panic, handing off to color-eyre:\n\n{report}");
+
+        safe_mode::record_crash(&pi.to_string());
+
+        match crash::write_report(&report) {
+            Ok(path) => error!("Wrote crash report to {}", path.display()),
+            Err(e) => error!("Failed to write crash report: {e}"),
+        }
     }));
 
     info!("Hii!! uwu");
@@ -76,8 +187,11 @@ unsafe fn __start_starb() {
     thread::sleep(Duration::from_secs(1u64));
 
     eframe::run_native(
-        &format!("Star Browser Utilities v{}", env!("CARGO_PKG_VERSION")),
-        NativeOptions::default(),
+        &storage::app_name(),
+        NativeOptions {
+            persistence_path: storage::prepare(),
+            ..Default::default()
+        },
         Box::new(|cc| Box::new(StarApp::new(cc))),
     )
     .expect("Failed to start eframe");
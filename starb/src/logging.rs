@@ -0,0 +1,446 @@
+//! Logging. Everything goes to `starb.log` next to SE's exe, and to memory so
+//! the log tab can show it.
+//!
+//! What's logged can be changed with the `STARB_LOG` environment variable or in
+//! the log tab, using [`EnvFilter`]'s syntax. `STARB_LOG` takes precedence.
+//! The last few sessions' logs are kept around as `starb.1.log`,
+//! `starb.2.log`, etc., so a crash's log isn't lost once SE is restarted.
+
+use egui::Color32;
+use egui::ComboBox;
+use egui::RichText;
+use egui::ScrollArea;
+use egui::TextEdit;
+use egui::TextStyle;
+use egui::Ui;
+use eyre::Result;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::field::Field;
+use tracing::field::Visit;
+use tracing::Event;
+use tracing::Level;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::Layer;
+use tracing_subscriber::Registry;
+
+/// Filter used when neither `STARB_LOG` nor the settings say otherwise.
+pub const DEFAULT_FILTER: &str = "debug";
+/// How many sessions' logs to keep, including the current one. Can be
+/// overridden with `STARB_LOG_KEEP`.
+const DEFAULT_SESSIONS: usize = 5usize;
+/// How many lines to keep in memory for the log tab.
+const MAX_LINES: usize = 10000usize;
+
+static FILTER: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
+static START: OnceCell<Instant> = OnceCell::new();
+static LINES: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+/// How many lines were ever added to [`LINES`], including ones that were
+/// dropped since. Only changed while [`LINES`] is locked.
+static LOGGED: AtomicUsize = AtomicUsize::new(0usize);
+
+/// A single event, as shown by the log tab.
+#[derive(Clone, Debug)]
+pub struct LogLine {
+    /// Time since starb started.
+    pub uptime: Duration,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:>9.3}s] {:>5} {}: {}",
+            self.uptime.as_secs_f64(),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// Rotate old logs, then start logging to `log` and memory.
+pub fn init(log: &Path) -> io::Result<()> {
+    START.get_or_init(Instant::now);
+
+    let sessions = env::var("STARB_LOG_KEEP")
+        .ok()
+        .and_then(|keep| keep.parse().ok())
+        .unwrap_or(DEFAULT_SESSIONS);
+
+    __rotate(log, sessions)?;
+
+    let filter = env::var("STARB_LOG")
+        .ok()
+        .and_then(|filter| EnvFilter::try_new(filter).ok())
+        .unwrap_or_else(|| EnvFilter::new(DEFAULT_FILTER));
+    let (filter, handle) = reload::Layer::new(filter);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(Arc::new(File::create(log)?))
+                .with_ansi(false),
+        )
+        .with(MemoryLayer)
+        .init();
+
+    FILTER.get_or_init(|| handle);
+
+    Ok(())
+}
+
+/// Change what's logged, unless `STARB_LOG` is set.
+pub fn set_filter(filter: &str) -> Result<()> {
+    if overridden() {
+        return Ok(());
+    }
+
+    let filter = EnvFilter::try_new(filter)?;
+
+    if let Some(handle) = FILTER.get() {
+        handle.reload(filter)?;
+    }
+
+    Ok(())
+}
+
+/// Whether `STARB_LOG` is set, and the settings can't change what's logged.
+#[must_use]
+pub fn overridden() -> bool {
+    env::var_os("STARB_LOG").is_some()
+}
+
+/// Last `n` lines logged, oldest first. `None` if that's busy, since this is
+/// used when panicking, possibly while logging.
+#[must_use]
+pub fn try_tail(n: usize) -> Option<Vec<LogLine>> {
+    let lines = LINES.try_lock_for(Duration::from_millis(100u64))?;
+
+    Some(
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(n))
+            .cloned()
+            .collect(),
+    )
+}
+
+/// `starb.log` -> `starb.1.log` -> ... The oldest is removed.
+fn __rotate(log: &Path, sessions: usize) -> io::Result<()> {
+    for i in (1usize..sessions).rev() {
+        let from = __session_path(log, i - 1usize);
+
+        if from.try_exists()? {
+            fs::rename(from, __session_path(log, i))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn __session_path(log: &Path, i: usize) -> PathBuf {
+    if i == 0usize {
+        return log.to_path_buf();
+    }
+
+    let stem = log.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = log.extension().unwrap_or_default().to_string_lossy();
+
+    log.with_file_name(format!("{stem}.{i}.{extension}"))
+}
+
+struct MemoryLayer;
+
+impl<S: Subscriber> Layer<S> for MemoryLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        let line = LogLine {
+            uptime: START.get().map(Instant::elapsed).unwrap_or_default(),
+            level: *metadata.level(),
+            target: metadata.target().to_owned(),
+            message: visitor.message + &visitor.fields,
+        };
+
+        let mut lines = LINES.lock();
+
+        if lines.len() >= MAX_LINES {
+            lines.pop_front();
+        }
+
+        lines.push_back(line);
+        LOGGED.fetch_add(1usize, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        }
+        else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// State of the log tab.
+pub struct LogViewer {
+    level: Level,
+    search: String,
+    filter: String,
+    filter_error: Option<String>,
+    /// Lines that match `level` and `search`, by how many were logged before
+    /// them. Only new lines are checked every frame.
+    matches: VecDeque<usize>,
+    /// `level`, `search` and [`LOGGED`] when `matches` was last updated.
+    matched: (Level, String, usize),
+}
+
+impl Default for LogViewer {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl LogViewer {
+    /// `filter` is the filter from the settings, if there is one.
+    #[must_use]
+    pub fn new(filter: Option<&str>) -> Self {
+        Self {
+            level: Level::TRACE,
+            search: String::new(),
+            filter: filter.unwrap_or(DEFAULT_FILTER).to_owned(),
+            filter_error: None,
+            matches: VecDeque::new(),
+            matched: (Level::TRACE, String::new(), 0usize),
+        }
+    }
+
+    /// Show the log tab. Returns the new filter if it was changed.
+    pub fn ui(&mut self, ui: &mut Ui) -> Option<String> {
+        let mut new_filter = None;
+
+        ui.horizontal(|ui| {
+            ComboBox::from_label("Level")
+                .selected_text(self.level.as_str())
+                .show_ui(ui, |ui| {
+                    for level in [
+                        Level::ERROR,
+                        Level::WARN,
+                        Level::INFO,
+                        Level::DEBUG,
+                        Level::TRACE,
+                    ] {
+                        ui.selectable_value(&mut self.level, level, level.as_str());
+                    }
+                });
+
+            ui.add(TextEdit::singleline(&mut self.search).hint_text("Search"));
+        });
+
+        ui.horizontal(|ui| {
+            if overridden() {
+                ui.label("What's logged is set by STARB_LOG.");
+                return;
+            }
+
+            ui.add(TextEdit::singleline(&mut self.filter).hint_text(DEFAULT_FILTER))
+                .on_hover_text(
+                    "What to log, e.g. `info,speng_starb=trace`. This only affects new lines.",
+                );
+
+            if ui.button("Apply").clicked() {
+                match set_filter(&self.filter) {
+                    Ok(()) => {
+                        self.filter_error = None;
+                        new_filter = Some(self.filter.clone());
+                    },
+                    Err(e) => self.filter_error = Some(e.to_string()),
+                }
+            }
+
+            if let Some(e) = self.filter_error.as_ref() {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+        });
+
+        ui.separator();
+
+        {
+            let lines = LINES.lock();
+
+            self.__update_matches(&lines, LOGGED.load(Ordering::Relaxed));
+        }
+
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+
+        ScrollArea::both()
+            .auto_shrink([false; 2usize])
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, self.matches.len(), |ui, rows| {
+                // Only what's visible, and without holding the lock while drawing
+                let visible = {
+                    let lines = LINES.lock();
+                    let first = LOGGED.load(Ordering::Relaxed) - lines.len();
+
+                    self.matches
+                        .range(rows)
+                        .filter_map(|&i| lines.get(i.checked_sub(first)?))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                };
+
+                for line in visible {
+                    let color = match line.level {
+                        Level::ERROR => Color32::RED,
+                        Level::WARN => Color32::YELLOW,
+                        Level::INFO => ui.visuals().text_color(),
+                        _ => Color32::GRAY,
+                    };
+
+                    ui.label(RichText::new(line.to_string()).monospace().color(color));
+                }
+            });
+
+        new_filter
+    }
+
+    /// Check lines logged since the last frame against `level` and `search`,
+    /// or all of them if those changed. `lines` is [`LINES`], and `logged` is
+    /// [`LOGGED`].
+    fn __update_matches(&mut self, lines: &VecDeque<LogLine>, logged: usize) {
+        let search = self.search.to_lowercase();
+        let first = logged - lines.len();
+
+        if self.matched.0 != self.level || self.matched.1 != search {
+            self.matches.clear();
+            self.matched = (self.level, search.clone(), first);
+        }
+
+        // Dropped to make room for new lines
+        while self.matches.front().is_some_and(|&i| i < first) {
+            self.matches.pop_front();
+        }
+
+        let new = logged - self.matched.2.max(first);
+
+        for (i, line) in lines.iter().enumerate().skip(lines.len() - new) {
+            if line.level <= self.level
+                && (search.is_empty() || line.to_string().to_lowercase().contains(&search))
+            {
+                self.matches.push_back(first + i);
+            }
+        }
+
+        self.matched.2 = logged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn __line(level: Level, message: &str) -> LogLine {
+        LogLine {
+            uptime: Duration::ZERO,
+            level,
+            target: "test".to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    #[test]
+    fn rotate() -> Result<()> {
+        let dir = env::temp_dir().join(format!("starb_logging_{}", process::id()));
+
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+
+        fs::create_dir_all(&dir)?;
+
+        let log = dir.join("starb.log");
+
+        assert_eq!(__session_path(&log, 0usize), log);
+        assert_eq!(__session_path(&log, 2usize), dir.join("starb.2.log"));
+
+        // Keeping 3 sessions, the current one and 2 old ones
+        for session in 0u32..4u32 {
+            __rotate(&log, 3usize)?;
+            fs::write(&log, session.to_string())?;
+        }
+
+        assert_eq!(fs::read_to_string(&log)?, "3");
+        assert_eq!(fs::read_to_string(dir.join("starb.1.log"))?, "2");
+        assert_eq!(fs::read_to_string(dir.join("starb.2.log"))?, "1");
+        assert!(!dir.join("starb.3.log").exists(), "Kept too many sessions");
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn matches() {
+        let mut viewer = LogViewer::default();
+        let mut lines = VecDeque::new();
+
+        lines.push_back(__line(Level::INFO, "Loaded plugin"));
+        lines.push_back(__line(Level::TRACE, "Patched"));
+        viewer.__update_matches(&lines, 2usize);
+
+        assert_eq!(viewer.matches, [0usize, 1usize]);
+
+        // Only new lines are checked, and old ones that were dropped are too
+        lines.pop_front();
+        lines.push_back(__line(Level::WARN, "Failed to load plugin"));
+        viewer.__update_matches(&lines, 3usize);
+
+        assert_eq!(viewer.matches, [1usize, 2usize]);
+
+        // Everything is, once the search changes
+        viewer.search = "PLUGIN".to_owned();
+        viewer.__update_matches(&lines, 3usize);
+
+        assert_eq!(viewer.matches, [2usize]);
+
+        viewer.level = Level::ERROR;
+        viewer.__update_matches(&lines, 3usize);
+
+        assert!(viewer.matches.is_empty(), "Matched a warning");
+    }
+}
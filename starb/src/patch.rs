@@ -0,0 +1,104 @@
+use crate::utils::base;
+use crate::utils::read_bytes;
+use crate::utils::write_bytes;
+use eyre::Result;
+use std::fmt;
+
+/// A single place in SE a plugin changes.
+#[derive(Clone, Debug)]
+pub struct Patch {
+    /// What this patch is for.
+    pub name: &'static str,
+    /// Offset from [`base`].
+    pub rva: isize,
+    /// What's here in an unmodified SE.
+    pub vanilla: Vec<u8>,
+    /// What's here once patched.
+    pub patched: Vec<u8>,
+    /// Whether `patched` should be in place, rather than `vanilla`.
+    pub applied: bool,
+}
+
+impl Patch {
+    /// What should currently be at this patch's address.
+    #[must_use]
+    pub fn expected(&self) -> &[u8] {
+        if self.applied {
+            &self.patched
+        }
+        else {
+            &self.vanilla
+        }
+    }
+
+    /// Write [`Patch::expected`] to SE.
+    pub fn apply(&self) -> Result<()> {
+        // SAFETY: Patches are only ever created by plugins, for addresses in SE they
+        // know about.
+        unsafe { write_bytes(base().byte_offset(self.rva).cast(), self.expected()) }
+    }
+
+    /// What's actually at this patch's address.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        // SAFETY: Same as `apply`
+        unsafe { read_bytes(base().byte_offset(self.rva).cast(), self.expected().len()) }
+    }
+
+    /// Whether [`Patch::expected`] is actually in place.
+    pub fn verify(&self) -> Result<bool> {
+        Ok(self.read()? == self.expected())
+    }
+}
+
+/// A function in SE a plugin detours.
+#[derive(Clone, Debug)]
+pub struct Hook {
+    pub name: &'static str,
+    /// Offset from [`base`].
+    pub rva: isize,
+    /// Whether the detour should currently be enabled.
+    pub enabled: bool,
+}
+
+impl Hook {
+    /// First bytes of the function.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        // SAFETY: Hooks are only ever created by plugins, for functions in SE they know
+        // about. Any function is at least 2 bytes, more so with a detour in place
+        unsafe { read_bytes(base().byte_offset(self.rva).cast(), 2usize) }
+    }
+
+    /// Whether the function starts with a jump exactly when the detour should
+    /// be enabled. Detours start with either `jmp rel32` or `jmp [rip+x]`.
+    pub fn verify(&self) -> Result<bool> {
+        let bytes = self.read()?;
+        let jumps = matches!(bytes[..], [0xE9u8, _] | [0xFFu8, 0x25u8]);
+
+        Ok(jumps == self.enabled)
+    }
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hook at {:#X}: {}",
+            self.name,
+            self.rva,
+            if self.enabled { "enabled" } else { "disabled" },
+        )
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {:#X}: {} ({:02X?})",
+            self.name,
+            self.rva,
+            if self.applied { "patched" } else { "vanilla" },
+            self.expected(),
+        )
+    }
+}
@@ -1,10 +1,17 @@
 use crate::app::StarApp;
+use crate::events::Event;
+use crate::patch::Hook;
+use crate::patch::Patch;
+use crate::tabs::Tab;
 use eframe::CreationContext;
 use eframe::Frame;
 use eframe::Storage;
 use egui::Context;
 use egui::Ui;
+use eyre::bail;
 use eyre::Result;
+use serde_json::Value;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum PluginPass {
@@ -26,6 +33,10 @@ pub trait Plugin {
     /// plugins.
     fn name(&self) -> String;
 
+    /// Key this plugin's settings are stored under. This is also how the
+    /// plugin is referred to from outside of starb, like the control API.
+    fn key(&self) -> String;
+
     /// Tab to add this plugin to. Is noop for custom plugins, since they're
     /// added to their own tab based on their name.
     fn section(&self) -> Option<String> {
@@ -44,9 +55,25 @@ pub trait Plugin {
     }
 
     /// Same as `update`, but called when the app adds context in the context
-    /// tab. Use this to show the current state, I guess?
-    fn add_context(
+    /// tab. Use this to show the current state, I guess? Shows
+    /// [`Plugin::context`] by default.
+    fn add_context(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+        for line in self.context() {
+            ui.label(line);
+        }
+    }
+
+    /// Whole tabs this plugin adds to the menu bar, for when a section in the
+    /// plugins tab isn't enough. Shown with [`Plugin::add_tab`].
+    fn tabs(&self) -> Vec<Tab> {
+        vec![]
+    }
+
+    /// Same as `update`, but called when one of this plugin's tabs is selected.
+    /// `id` is the ID it was given in [`Plugin::tabs`].
+    fn add_tab(
         &mut self,
+        _id: &str,
         _app: &mut StarApp,
         _ctx: &Context,
         _frame: &mut Frame,
@@ -54,6 +81,65 @@ pub trait Plugin {
     ) {
     }
 
+    /// Current state of this plugin as text. This is shown in the context tab,
+    /// and by the control API.
+    fn context(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Current settings of this plugin, as a JSON object.
+    fn settings(&self) -> Value {
+        Value::Null
+    }
+
+    /// Change this plugin's settings, and apply them if possible. `settings` is
+    /// the whole object returned by [`Plugin::settings`], with some fields
+    /// changed.
+    fn set_settings(&mut self, _settings: Value) -> Result<()> {
+        bail!("`{}` has no settings", self.name())
+    }
+
+    /// Things this plugin can do on demand, as (name, description). These can
+    /// be bound to hotkeys.
+    fn actions(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+
+    /// Do the action called `name` from [`Plugin::actions`].
+    fn run_action(&mut self, name: &str) -> Result<()> {
+        bail!("`{}` has no action `{name}`", self.name())
+    }
+
+    /// Every place in SE this plugin changes, and whether each is currently
+    /// patched. These are included in crash reports, and checked by the
+    /// self-test.
+    fn patches(&self) -> Vec<Patch> {
+        vec![]
+    }
+
+    /// Every function in SE this plugin detours. Same as [`Plugin::patches`].
+    fn hooks(&self) -> Vec<Hook> {
+        vec![]
+    }
+
+    /// How often to call [`Plugin::tick`], or `None` to not. This is checked
+    /// before every tick, so it can change, like when the plugin's disabled.
+    fn tick_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Do whatever this plugin has to keep doing. This is on starb's own
+    /// thread, so it's called whether or not starb's window is open. Use this
+    /// instead of `update` or `add_plugin` for anything that isn't GUI.
+    fn tick(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called for every [`Event`], in the order they happened. This is on
+    /// starb's own thread, not the GUI's, so it's called even while starb's
+    /// window isn't being repainted.
+    fn on_event(&mut self, _event: &Event) {}
+
     /// Called when [`StarApp`]'s `update` method is called.
     fn update(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame) {}
 
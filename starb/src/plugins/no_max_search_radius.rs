@@ -1,27 +1,198 @@
+//! The Star browser won't search further than 100 pc. SE compares the search
+//! radius against a constant right before the jump at [`JUMP_RVA`], and clamps
+//! it if it's above that. That constant is MSVC's pooled 100.0, which anything
+//! else comparing against 100.0 uses too, so it's left alone. Instead, a cap is
+//! set by pointing the comparison at starb's own, allocated close enough to
+//! SE's code for the comparison to reach. No cap at all is the jump being made
+//! unconditional instead, like before.
+
 use crate::app::StarApp;
+use crate::game::Located;
+use crate::game::Value as GameValue;
+use crate::patch::Patch;
 use crate::plugin::Plugin;
 use crate::plugin::PluginPass;
+use crate::setting::ApplyPolicy;
+use crate::setting::Setting;
 use crate::utils::base;
-use crate::utils::write;
+use crate::utils::image_size;
+use crate::utils::read_bytes;
+use crate::utils::rva;
 use eframe::CreationContext;
 use eframe::Frame;
 use eframe::Storage;
+use egui::ComboBox;
 use egui::Context;
+use egui::DragValue;
 use egui::Ui;
+use eyre::bail;
+use eyre::ensure;
+use eyre::eyre;
 use eyre::Result;
+use once_cell::sync::OnceCell;
+use region::Allocation;
+use region::Protection;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::json;
+use serde_json::Value;
+use std::fmt;
+use std::mem::size_of;
+use tracing::error;
+use tracing::info;
 use tracing::instrument;
+use tracing::warn;
 
 const PLUGIN_KEY: &str = "no_max_search_radius";
+/// `jbe` after comparing the search radius against the cap.
+const JUMP_RVA: isize = 0x3EFBA0isize;
+/// SE's own cap, in parsecs.
+const VANILLA_CAP: f64 = 100.0f64;
+/// What Windows allocates in multiples of.
+const ALLOCATION_GRANULARITY: usize = 0x10000usize;
+/// How many places after SE's image to try to allocate starb's cap at.
+const ALLOCATION_ATTEMPTS: usize = 0x1000usize;
+
+/// starb's cap, once it's been allocated. It's never freed, since SE could
+/// compare against it at any time.
+static CAP: CapAllocation = CapAllocation(OnceCell::new());
+
+/// Only so [`CAP`] can be a static.
+struct CapAllocation(OnceCell<Allocation>);
+
+// SAFETY: Only its address is used once it's allocated, which is fine from any
+// thread
+unsafe impl Sync for CapAllocation {}
+
+/// The Star browser's max search radius.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, PartialOrd, Serialize)]
+enum Cap {
+    /// In parsecs.
+    Limited(f64),
+    Unlimited,
+}
+
+impl fmt::Display for Cap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Limited(pc) => write!(f, "{pc} pc"),
+            Self::Unlimited => write!(f, "UNLIMITED"),
+        }
+    }
+}
+
+/// What the cap is shown in. It's always stored in parsecs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+enum Unit {
+    #[default]
+    Parsecs,
+    LightYears,
+    Kiloparsecs,
+}
+
+impl Unit {
+    const ALL: [Self; 3usize] = [Self::Parsecs, Self::LightYears, Self::Kiloparsecs];
+
+    const fn per_parsec(self) -> f64 {
+        match self {
+            Self::Parsecs => 1.0f64,
+            Self::LightYears => 3.261_563_777f64,
+            Self::Kiloparsecs => 0.001f64,
+        }
+    }
 
+    const fn symbol(self) -> &'static str {
+        match self {
+            Self::Parsecs => "pc",
+            Self::LightYears => "ly",
+            Self::Kiloparsecs => "kpc",
+        }
+    }
+}
+
+/// How this is stored.
 #[derive(Deserialize, Serialize)]
-pub struct NoMaxSearchRadius(bool);
+struct Saved {
+    cap: Cap,
+    unit: Unit,
+}
+
+/// How this was stored before the cap could be set, when it was only on or
+/// off.
+#[derive(Deserialize)]
+struct SavedEnabled(bool);
+
+/// The comparison against the cap, as RVAs of what it can compare against.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Comparison {
+    /// SE's constant, which is shared, so it's never written to.
+    constant: isize,
+    /// starb's own, which is compared against instead when it's not 100 pc.
+    cap: isize,
+}
+
+impl Comparison {
+    /// Where the comparison's displacement is, right before the jump.
+    const DISP_RVA: isize = JUMP_RVA - 4isize;
+
+    /// `None` if either is too far away from the comparison to be reached.
+    fn new(constant: isize, cap: isize) -> Option<Self> {
+        let comparison = Self { constant, cap };
+
+        (Self::__disp(constant).is_some() && Self::__disp(cap).is_some()).then_some(comparison)
+    }
+
+    /// The displacement that makes the comparison read `rva`.
+    fn __disp(rva: isize) -> Option<[u8; 4usize]> {
+        // Relative to the end of the instruction, which is the jump
+        i32::try_from(rva - JUMP_RVA).ok().map(i32::to_le_bytes)
+    }
+}
+
+pub struct NoMaxSearchRadius {
+    cap: Setting<Cap>,
+    unit: Unit,
+    /// `None` if it couldn't be found, or starb's cap couldn't be allocated.
+    comparison: Option<Comparison>,
+}
 
-#[allow(clippy::derivable_impls)]
 impl Default for NoMaxSearchRadius {
     fn default() -> Self {
-        Self(false)
+        Self {
+            cap: Setting::new(
+                "Max search radius",
+                Cap::Limited(VANILLA_CAP),
+                ApplyPolicy::Verified,
+            ),
+            unit: Unit::default(),
+            comparison: None,
+        }
+    }
+}
+
+impl NoMaxSearchRadius {
+    /// Write the jump, starb's cap and the comparison for the requested cap.
+    fn apply(&mut self) -> Result<()> {
+        let comparison = self.comparison;
+
+        self.cap.apply(|&cap| {
+            if matches!(cap, Cap::Limited(pc) if !__is_vanilla(pc)) && comparison.is_none() {
+                bail!("SE's max search radius wasn't found, so it can only be 100 pc or unlimited");
+            }
+
+            __patches(cap, comparison).iter().try_for_each(Patch::apply)
+        })?;
+
+        // Read it back, so the GUI doesn't claim it worked when it didn't
+        if !self.cap.verify(|&cap| {
+            __patches(cap, comparison)
+                .iter()
+                .try_fold(true, |verified, patch| Ok(verified && patch.verify()?))
+        })? {
+            bail!("Patches weren't in place after applying them");
+        }
+
+        Ok(())
     }
 }
 
@@ -31,20 +202,57 @@ impl Plugin for NoMaxSearchRadius {
     where
         Self: Sized,
     {
-        let no_max_search_radius =
-            eframe::get_value::<Self>(cc.storage.expect("Probably unreachable?"), PLUGIN_KEY)
-                .unwrap_or_default();
+        let mut no_max_search_radius = Self::default();
+        let storage = cc.storage.expect("Probably unreachable?");
 
-        // TODO: Don't do this here. Quick hotfix
-        unsafe {
-            if no_max_search_radius.0 {
-                write::<u8>(base().byte_offset(0x3EFBA0isize).cast(), 0xEBu8)
+        if let Some(saved) = eframe::get_value::<Saved>(storage, PLUGIN_KEY) {
+            no_max_search_radius.cap = no_max_search_radius.cap.with_requested(saved.cap);
+            no_max_search_radius.unit = saved.unit;
+        }
+        else if matches!(
+            eframe::get_value(storage, PLUGIN_KEY),
+            Some(SavedEnabled(true))
+        ) {
+            no_max_search_radius.cap = no_max_search_radius.cap.with_requested(Cap::Unlimited);
+        }
+
+        let code = __comparison_code()?;
+        // Anything else means this isn't the comparison we think it is
+        let constant = if let Some(rva) = __constant_rva(&code) {
+            let cap = GameValue::<f64>::from_rva(rva).read()?;
+
+            if __is_vanilla(cap) {
+                Some(rva)
             }
             else {
-                write::<u8>(base().byte_offset(0x3EFBA0isize).cast(), 0x76u8)
+                warn!("Max search radius at {rva:#X} is {cap}, not 100! Leaving it alone.");
+
+                None
             }
         }
-        .expect("Failed to update ):");
+        else {
+            warn!("Couldn't find the max search radius from {code:02X?}");
+
+            None
+        };
+
+        if let Some(constant) = constant {
+            info!("Found the max search radius at {constant:#X}");
+
+            no_max_search_radius.comparison = match __alloc_cap() {
+                Ok(cap) => Comparison::new(constant, cap),
+                Err(e) => {
+                    warn!("Failed to allocate starb's max search radius: {e}");
+
+                    None
+                },
+            };
+        }
+
+        // TODO: Don't do this here. Quick hotfix
+        if let Err(e) = no_max_search_radius.apply() {
+            error!("Failed to apply the max search radius: {e}");
+        }
 
         Ok(no_max_search_radius)
     }
@@ -57,37 +265,330 @@ impl Plugin for NoMaxSearchRadius {
         "No Max Search Radius".to_owned()
     }
 
+    fn key(&self) -> String {
+        PLUGIN_KEY.to_owned()
+    }
+
     fn priority(&self) -> Option<usize> {
         Some(1usize)
     }
 
     #[instrument(skip(self, _app, _ctx, _frame, ui))]
     fn add_plugin(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
-        if ui
-            .checkbox(&mut self.0, "Enabled")
-            .on_hover_text("Uncap the Star browser's search radius")
-            .clicked()
-        {
-            unsafe {
-                if self.0 {
-                    write::<u8>(base().byte_offset(0x3EFBA0isize).cast(), 0xEBu8)
+        let name = self.name();
+        let unit = self.unit;
+        let found = self.comparison.is_some();
+
+        if self.cap.ui(ui, &name, |ui, cap| {
+            ui.horizontal(|ui| {
+                let mut unlimited = *cap == Cap::Unlimited;
+                let mut response = ui
+                    .checkbox(&mut unlimited, "Unlimited")
+                    .on_hover_text("Uncap the Star browser's search radius");
+
+                if response.changed() {
+                    *cap = if unlimited {
+                        Cap::Unlimited
+                    }
+                    else {
+                        Cap::Limited(VANILLA_CAP)
+                    };
                 }
-                else {
-                    write::<u8>(base().byte_offset(0x3EFBA0isize).cast(), 0x76u8)
+
+                if let Cap::Limited(ref mut pc) = *cap {
+                    let mut shown = *pc * unit.per_parsec();
+
+                    let drag = ui
+                        .add_enabled(
+                            found,
+                            DragValue::new(&mut shown)
+                                .speed(1.0f64)
+                                .clamp_range(0.001f64..=f64::MAX)
+                                .suffix(format!(" {}", unit.symbol())),
+                        )
+                        .on_hover_text("Max search radius.\n\nDefault: 100 pc");
+
+                    if drag.changed() {
+                        *pc = shown / unit.per_parsec();
+                    }
+
+                    response |= drag;
                 }
+
+                response
+            })
+            .inner
+        }) {
+            if let Err(e) = self.apply() {
+                error!("Failed to update `{name}`: {e}");
             }
-            .expect("Failed to update ):");
         }
+
+        ComboBox::from_id_source("no_max_search_radius_unit")
+            .selected_text(self.unit.symbol())
+            .show_ui(ui, |ui| {
+                for unit in Unit::ALL {
+                    ui.selectable_value(&mut self.unit, unit, unit.symbol());
+                }
+            });
+
+        if !found {
+            ui.label("SE's max search radius wasn't found, so it can only be 100 pc or unlimited.");
+        }
+    }
+
+    fn context(&self) -> Vec<String> {
+        let mut context = vec![match __in_effect() {
+            Ok(cap) => format!("Max search radius is currently {cap}"),
+            Err(e) => format!("Max search radius couldn't be read: {e}"),
+        }];
+
+        if let Some(comparison) = self.comparison {
+            context.push(format!(
+                "Max search radius is SE's at {:#X}, or starb's at {:#X} when it isn't 100 pc",
+                comparison.constant, comparison.cap
+            ));
+        }
+        else {
+            context.push("Max search radius wasn't found, only unlimited works".to_owned());
+        }
+
+        context
+    }
+
+    fn patches(&self) -> Vec<Patch> {
+        __patches(*self.cap.applied_or_default(), self.comparison)
+    }
+
+    fn settings(&self) -> Value {
+        let (unlimited, pc) = match *self.cap.requested() {
+            Cap::Limited(pc) => (false, pc),
+            Cap::Unlimited => (true, VANILLA_CAP),
+        };
+
+        json!({ "unlimited": unlimited, "max_search_radius": pc })
     }
 
-    fn add_context(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
-        ui.label(format!(
-            "Max search radius is currently {}",
-            if self.0 { "100.0f64" } else { "UNLIMITED" }
-        ));
+    fn set_settings(&mut self, settings: Value) -> Result<()> {
+        // Only on or off, from before the cap could be set
+        let unlimited = match settings.get("enabled") {
+            Some(enabled) => serde_json::from_value::<bool>(enabled.clone())?,
+            None => serde_json::from_value::<bool>(settings["unlimited"].clone())?,
+        };
+        let cap = if unlimited {
+            Cap::Unlimited
+        }
+        else {
+            let pc = serde_json::from_value::<f64>(settings["max_search_radius"].clone())?;
+
+            ensure!(
+                pc.is_finite() && pc > 0.0f64,
+                "Max search radius must be above 0, in parsecs"
+            );
+
+            Cap::Limited(pc)
+        };
+
+        let name = self.name();
+
+        if self.cap.request(&name, cap) {
+            self.apply()?;
+        }
+
+        Ok(())
     }
 
     fn save(&mut self, _app: &mut StarApp, storage: &mut dyn Storage) {
-        eframe::set_value(storage, PLUGIN_KEY, &self);
+        let saved = Saved {
+            cap: *self.cap.requested(),
+            unit: self.unit,
+        };
+
+        eframe::set_value(storage, PLUGIN_KEY, &saved);
+    }
+}
+
+/// What SE's actually using, rather than what was last applied.
+fn __in_effect() -> Result<Cap> {
+    if __patches(Cap::Unlimited, None)[0usize].verify()? {
+        return Ok(Cap::Unlimited);
+    }
+
+    // Whichever the comparison's reading right now
+    let rva = __constant_rva(&__comparison_code()?)
+        .ok_or_else(|| eyre!("The max search radius comparison isn't there anymore"))?;
+
+    Ok(Cap::Limited(GameValue::<f64>::from_rva(rva).read()?))
+}
+
+/// The 9 bytes before the jump, which should be the comparison.
+fn __comparison_code() -> Result<Vec<u8>> {
+    // SAFETY: Right before the jump, which is in SE's code
+    unsafe { read_bytes(base().byte_offset(JUMP_RVA - 9isize).cast(), 9usize) }
+}
+
+/// Allocate starb's cap, set to SE's, after SE's image. That's close enough
+/// for the comparison to reach, since a displacement can only be 2 GiB either
+/// way. It's kept in [`CAP`], so it's only allocated once. Returns its RVA.
+fn __alloc_cap() -> Result<isize> {
+    let base = base() as usize;
+    let allocation = CAP.0.get_or_try_init(|| {
+        let end = (base + image_size() + ALLOCATION_GRANULARITY - 1usize)
+            & !(ALLOCATION_GRANULARITY - 1usize);
+
+        for address in (0usize..ALLOCATION_ATTEMPTS).map(|i| end + i * ALLOCATION_GRANULARITY) {
+            // Something's already there. Windows wouldn't allocate over it anyway, but
+            // others would
+            if region::query(address as *const u8).is_ok() {
+                continue;
+            }
+
+            let Ok(allocation) = region::alloc_at(
+                address as *const u8,
+                size_of::<f64>(),
+                Protection::READ_WRITE,
+            )
+            else {
+                continue;
+            };
+
+            // SAFETY: Just allocated, and nothing else knows about it yet
+            unsafe { allocation.as_ptr::<f64>().cast_mut().write(VANILLA_CAP) };
+
+            return Ok(allocation);
+        }
+
+        bail!("Nowhere near SE's code was free")
+    })?;
+
+    Ok(rva(allocation.as_ptr::<f64>() as usize, base))
+}
+
+/// Where the constant compared against is, from the 9 bytes before the jump.
+/// That's `comisd` or `ucomisd` with a RIP-relative operand, with or without a
+/// REX prefix.
+fn __constant_rva(code: &[u8]) -> Option<isize> {
+    let disp = match *code {
+        [_, 0x66u8, 0x0Fu8, 0x2Eu8 | 0x2Fu8, modrm, ref disp @ ..]
+        | [0x66u8, 0x40u8..=0x4Fu8, 0x0Fu8, 0x2Eu8 | 0x2Fu8, modrm, ref disp @ ..]
+            if modrm & 0xC7u8 == 0x05u8 =>
+        {
+            i32::from_le_bytes(disp.try_into().ok()?)
+        },
+        _ => return None,
+    };
+
+    // Relative to the end of the instruction, which is the jump
+    Some(JUMP_RVA + disp as isize)
+}
+
+/// Whether `pc` is exactly SE's own cap.
+fn __is_vanilla(pc: f64) -> bool {
+    pc.to_bits() == VANILLA_CAP.to_bits()
+}
+
+/// starb's cap before the comparison, so it's never pointed at something that
+/// isn't ready yet.
+fn __patches(cap: Cap, comparison: Option<Comparison>) -> Vec<Patch> {
+    let mut patches = vec![Patch {
+        name: "Max search radius jump",
+        rva: JUMP_RVA,
+        // jbe -> jmp
+        vanilla: vec![0x76u8],
+        patched: vec![0xEBu8],
+        applied: cap == Cap::Unlimited,
+    }];
+
+    let Some(comparison) = comparison
+    else {
+        return patches;
+    };
+    // Checked by `Comparison::new`
+    let (Some(vanilla), Some(patched)) = (
+        Comparison::__disp(comparison.constant),
+        Comparison::__disp(comparison.cap),
+    )
+    else {
+        return patches;
+    };
+    let pc = match cap {
+        Cap::Limited(pc) => pc,
+        Cap::Unlimited => VANILLA_CAP,
+    };
+
+    patches.push(Patch {
+        name: "Max search radius",
+        rva: comparison.cap,
+        vanilla: VANILLA_CAP.to_le_bytes().to_vec(),
+        patched: pc.to_le_bytes().to_vec(),
+        applied: !__is_vanilla(pc),
+    });
+    patches.push(Patch {
+        name: "Max search radius comparison",
+        rva: Comparison::DISP_RVA,
+        vanilla: vanilla.to_vec(),
+        patched: patched.to_vec(),
+        applied: !__is_vanilla(pc),
+    });
+
+    patches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant() {
+        // `comisd xmm0, [rip+0x10]`
+        assert_eq!(
+            __constant_rva(&[0x90u8, 0x66u8, 0x0Fu8, 0x2Fu8, 0x05u8, 0x10u8, 0u8, 0u8, 0u8]),
+            Some(JUMP_RVA + 0x10isize)
+        );
+        // `ucomisd xmm9, [rip-0x20]`
+        assert_eq!(
+            __constant_rva(&[
+                0x66u8, 0x44u8, 0x0Fu8, 0x2Eu8, 0x0Du8, 0xE0u8, 0xFFu8, 0xFFu8, 0xFFu8
+            ]),
+            Some(JUMP_RVA - 0x20isize)
+        );
+        // `comisd xmm0, xmm1`, so it's not in memory
+        assert_eq!(
+            __constant_rva(&[
+                0x90u8, 0x90u8, 0x90u8, 0x90u8, 0x90u8, 0x66u8, 0x0Fu8, 0x2Fu8, 0xC1u8
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn patches() {
+        let comparison = Comparison::new(JUMP_RVA + 0x100isize, JUMP_RVA - 0x1000isize);
+        let limited = __patches(Cap::Limited(500.0f64), comparison);
+        let [ref jump, ref cap, ref disp] = *limited
+        else {
+            panic!("Should be three patches");
+        };
+
+        assert!(!jump.applied);
+        assert_eq!(cap.rva, JUMP_RVA - 0x1000isize);
+        assert_eq!(cap.expected(), 500.0f64.to_le_bytes());
+        assert_eq!(disp.rva, JUMP_RVA - 4isize);
+        assert_eq!(disp.vanilla, 0x100i32.to_le_bytes());
+        assert_eq!(disp.expected(), (-0x1000i32).to_le_bytes());
+
+        // SE's constant is left alone either way
+        let patches = __patches(Cap::Unlimited, comparison);
+
+        assert!(patches[0usize].applied);
+        assert!(patches[1usize..].iter().all(|patch| !patch.applied));
+        assert!(patches
+            .iter()
+            .all(|patch| patch.rva != JUMP_RVA + 0x100isize));
+        assert_eq!(__patches(Cap::Unlimited, None).len(), 1usize);
+        // Out of the comparison's reach
+        assert_eq!(Comparison::new(JUMP_RVA, JUMP_RVA + 0x100000000isize), None);
+        // Unlimited is above any cap, for `Setting`
+        assert!(Cap::Limited(f64::MAX) < Cap::Unlimited);
     }
 }
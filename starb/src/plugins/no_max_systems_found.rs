@@ -1,31 +1,148 @@
+//! SE has its max systems found as an immediate in two instructions. Changing
+//! it needs SE to be restarted, since it's only known that SE compares against
+//! it, not whether it also sizes something with it (like a buffer it
+//! allocates). Raising it live could then overflow that.
+//!
+//! What each instruction looks like is logged and shown in the Context tab
+//! (see [`Usage::detect`]), for working that out.
+
 use crate::app::StarApp;
+use crate::patch::Patch;
 use crate::plugin::Plugin;
 use crate::plugin::PluginPass;
+use crate::setting::ApplyPolicy;
+use crate::setting::Setting;
 use crate::utils::base;
-use crate::utils::read;
-use crate::utils::write;
+use crate::utils::read_bytes;
 use eframe::CreationContext;
 use eframe::Frame;
 use eframe::Storage;
 use egui::Context;
 use egui::Slider;
 use egui::Ui;
+use eyre::ensure;
 use eyre::Result;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::json;
+use serde_json::Value;
+use std::fmt;
+use std::mem::size_of;
 use tracing::info;
 use tracing::instrument;
 use tracing::warn;
 
 const PLUGIN_KEY: &str = "no_max_systems_found";
+/// Every immediate of the limit, as (name, RVA).
+const SITES: [(&str, isize); 2usize] = [
+    ("Max systems found (1)", 0x3F1531isize),
+    ("Max systems found (2)", 0x3F1549isize),
+];
+
+/// What the instruction one of [`SITES`] is in looks like.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Usage {
+    /// `cmp`.
+    Bound,
+    /// `mov`.
+    Size,
+    /// Anything else, or couldn't be read.
+    Unknown,
+}
+
+impl Usage {
+    /// Which instruction the immediate after `code` is in. `code` is the 4
+    /// bytes before it, which is enough for `opcode modrm [sib] [disp8]`.
+    fn detect(code: &[u8]) -> Self {
+        let from_opcode = |opcode: u8, modrm: u8| match (opcode, (modrm >> 3u8) & 7u8) {
+            (0x81u8, 7u8) => Self::Bound,
+            (0xC7u8, 0u8) => Self::Size,
+            _ => Self::Unknown,
+        };
+        let mode = |modrm: u8| modrm >> 6u8;
+        let rm = |modrm: u8| modrm & 7u8;
+
+        // Longest first, since they're the least likely to match by accident
+        let forms = [
+            match *code {
+                [opcode, modrm, _, _] if mode(modrm) == 1u8 && rm(modrm) == 4u8 => {
+                    from_opcode(opcode, modrm)
+                },
+                _ => Self::Unknown,
+            },
+            match *code {
+                [.., opcode, modrm, _] if mode(modrm) == 1u8 && rm(modrm) != 4u8 => {
+                    from_opcode(opcode, modrm)
+                },
+                _ => Self::Unknown,
+            },
+            match *code {
+                [.., opcode, modrm] if mode(modrm) == 3u8 => from_opcode(opcode, modrm),
+                _ => Self::Unknown,
+            },
+            match *code {
+                // `cmp eax, imm32`
+                [.., 0x3Du8] => Self::Bound,
+                // `mov r32, imm32`
+                [.., 0xB8u8..=0xBFu8] => Self::Size,
+                _ => Self::Unknown,
+            },
+        ];
+
+        forms
+            .into_iter()
+            .find(|usage| *usage != Self::Unknown)
+            .unwrap_or(Self::Unknown)
+    }
+}
 
-/// .0 = requested, .1 = current
-#[derive(Clone, Deserialize, Serialize)]
-pub struct NoMaxSystemsFound(u32, u32);
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Bound => write!(f, "a `cmp`"),
+            Self::Size => write!(f, "a `mov`"),
+            Self::Unknown => write!(f, "something else"),
+        }
+    }
+}
+
+/// How this is stored. .0 = requested, .1 = what was in effect, which is only
+/// kept so older versions of starb can still read this.
+#[derive(Deserialize, Serialize)]
+struct Saved(u32, u32);
+
+pub struct NoMaxSystemsFound {
+    max: Setting<u32>,
+    /// What each of [`SITES`] looks like.
+    usages: Vec<Usage>,
+}
 
 impl Default for NoMaxSystemsFound {
     fn default() -> Self {
-        Self(10000u32, 10000u32)
+        Self {
+            max: Setting::new("Max systems found", 10000u32, ApplyPolicy::NextRestart),
+            usages: vec![Usage::Unknown; SITES.len()],
+        }
+    }
+}
+
+impl NoMaxSystemsFound {
+    /// Write the requested max systems found. This does nothing once SE has
+    /// started.
+    fn apply(&mut self) -> Result<()> {
+        if !self.max.can_apply() {
+            return Ok(());
+        }
+
+        // SAFETY: The check in `load` should be enough, UNLESS both HAPPEN to be the
+        // same SOMEHOW. I cannot stress enough how rare this would be (unless they're
+        // both 0xCC...?).
+        self.max
+            .apply(|&max| __patches(max).iter().try_for_each(Patch::apply))?;
+
+        info!("Changed max systems found to {}.", self.max.requested());
+
+        Ok(())
     }
 }
 
@@ -35,39 +152,52 @@ impl Plugin for NoMaxSystemsFound {
     where
         Self: Sized,
     {
-        let no_max_systems_found =
-            eframe::get_value::<Self>(cc.storage.expect("Probably unreachable?"), PLUGIN_KEY)
-                .unwrap_or_default();
+        let mut no_max_systems_found = Self::default();
 
-        if no_max_systems_found.0 > 1000000u32 {
+        if let Some(saved) =
+            eframe::get_value::<Saved>(cc.storage.expect("Probably unreachable?"), PLUGIN_KEY)
+        {
+            no_max_systems_found.max = no_max_systems_found.max.with_requested(saved.0);
+        }
+
+        if *no_max_systems_found.max.requested() > 1000000u32 {
             warn!("NO MAX SYSTEMS FOUND IS ABOVE 1000000. UH OH!");
         }
 
-        let fir = unsafe { base().byte_offset(0x3F1531isize).cast::<u32>() };
-        let sec = unsafe { base().byte_offset(0x3F1549isize).cast::<u32>() };
+        // SAFETY: Both are in SE's code
+        let [fir, sec] =
+            SITES.map(|(_, rva)| unsafe { __read_immediate(base().byte_offset(rva).cast()) });
+        let (fir, sec) = (fir?, sec?);
 
-        unsafe {
-            assert_eq!(
-                fir.read(),
-                sec.read(),
-                "These are not equal! THIS IS IMPOSSIBLE. WRONG SE VERSION!"
-            );
-        }
+        assert_eq!(
+            fir, sec,
+            "These are not equal! THIS IS IMPOSSIBLE. WRONG SE VERSION!"
+        );
 
-        // SAFETY: THIS IS UNSAFE.
-        if unsafe { read(fir)? != 10000u32 } || unsafe { read(sec)? != 10000u32 } {
+        if fir != 10000u32 || sec != 10000u32 {
             warn!("Either of fir or sec are not 10000! This exe is likely modifed, but that's ok.");
         }
 
-        // SAFETY: The above check should be enough, UNLESS both HAPPEN to be the same
-        // SOMEHOW. I cannot stress enough how rare this would be (unless they're both
-        // 0xCC...?).
-        unsafe {
-            write(fir, no_max_systems_found.0)?;
-            write(sec, no_max_systems_found.0)?;
+        no_max_systems_found.usages = SITES
+            .iter()
+            .map(|&(name, rva)| {
+                // SAFETY: Right before the immediate, which is in SE's code
+                match unsafe { read_bytes(base().byte_offset(rva - 4isize).cast(), 4usize) } {
+                    Ok(code) => Usage::detect(&code),
+                    Err(e) => {
+                        warn!("Failed to read the instruction of {name}: {e}");
+
+                        Usage::Unknown
+                    },
+                }
+            })
+            .collect();
+
+        for (&(name, _), usage) in SITES.iter().zip(&no_max_systems_found.usages) {
+            info!("{name} is in {usage}");
         }
 
-        info!("Changed max systems found to {}.", no_max_systems_found.0);
+        no_max_systems_found.apply()?;
 
         Ok(no_max_systems_found)
     }
@@ -80,39 +210,147 @@ impl Plugin for NoMaxSystemsFound {
         "No Max Systems Found".to_owned()
     }
 
+    fn key(&self) -> String {
+        PLUGIN_KEY.to_owned()
+    }
+
     fn priority(&self) -> Option<usize> {
         Some(0usize)
     }
 
-    fn add_plugin(&mut self, app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
-        if ui
-            .add(Slider::new(&mut self.0, 0u32..=1000000u32).logarithmic(true))
-            .on_hover_text(
-                "What to set the max number of systems the Star browser will search.\n\nDefault: \
-                 10000",
-            )
-            .changed()
-        {
-            app.requires_restart(
-                &self.name(),
-                &"Max systems found cannot be modified after SE has already started.",
-            );
-        }
+    fn add_plugin(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+        let name = self.name();
+
+        // Never applied here, since SE has always started by the time the GUI's shown
+        self.max.ui(ui, &name, |ui, max| {
+            ui.add(Slider::new(max, 0u32..=1000000u32).logarithmic(true))
+                .on_hover_text(
+                    "What to set the max number of systems the Star browser will \
+                     search.\n\nDefault: 10000",
+                )
+        });
 
-        if self.0 == 1000000u32 {
+        ui.label("Changing this needs SE to be restarted.");
+
+        if *self.max.requested() == 1000000u32 {
             ui.label("Values above 1000000 are very unstable. They cannot to be set.");
         }
-        else if self.0 > 100000u32 {
+        else if *self.max.requested() > 100000u32 {
             ui.label("Values above 100000 are both unnecessary and difficult to run.");
         }
     }
 
-    fn add_context(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
-        ui.label(format!("Requested max systems found is {}", self.0));
-        ui.label(format!("Max systems found is currently {}", self.1));
+    fn context(&self) -> Vec<String> {
+        let mut context = vec![self.max.to_string()];
+
+        context.extend(
+            SITES
+                .iter()
+                .zip(&self.usages)
+                .map(|(&(name, _), usage)| format!("{name} is in {usage}")),
+        );
+
+        context
+    }
+
+    fn patches(&self) -> Vec<Patch> {
+        __patches(*self.max.applied_or_default())
+    }
+
+    fn settings(&self) -> Value {
+        json!({ "max_systems_found": self.max.requested() })
+    }
+
+    fn set_settings(&mut self, settings: Value) -> Result<()> {
+        let requested = serde_json::from_value::<u32>(settings["max_systems_found"].clone())?;
+
+        ensure!(
+            requested <= 1000000u32,
+            "Max systems found cannot be above 1000000"
+        );
+
+        // Changed before SE could use it (e.g., by starb-ctl), so no restart needed
+        if requested != *self.max.requested() && self.max.request(&self.name(), requested) {
+            self.apply()?;
+        }
+
+        Ok(())
     }
 
     fn save(&mut self, _app: &mut StarApp, storage: &mut dyn Storage) {
-        eframe::set_value(storage, PLUGIN_KEY, &self);
+        let saved = Saved(*self.max.requested(), *self.max.applied_or_default());
+
+        eframe::set_value(storage, PLUGIN_KEY, &saved);
+    }
+}
+
+/// Read one of [`SITES`]. They aren't aligned, so this can't go through
+/// [`crate::game::Value`].
+///
+/// # Safety
+///
+/// * `p` must point to 4 bytes of mapped memory.
+unsafe fn __read_immediate(p: *const u8) -> Result<u32> {
+    let bytes = unsafe { read_bytes(p, size_of::<u32>())? };
+
+    Ok(u32::from_le_bytes(bytes.as_slice().try_into()?))
+}
+
+fn __patches(max: u32) -> Vec<Patch> {
+    SITES
+        .into_iter()
+        .map(|(name, rva)| Patch {
+            name,
+            rva,
+            vanilla: 10000u32.to_le_bytes().to_vec(),
+            patched: max.to_le_bytes().to_vec(),
+            applied: max != 10000u32,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use region::Protection;
+
+    #[test]
+    fn usages() {
+        for (code, usage) in [
+            // `cmp ecx, imm32`
+            ([0x90u8, 0x90u8, 0x81u8, 0xF9u8], Usage::Bound),
+            // `cmp eax, imm32`
+            ([0x90u8, 0x90u8, 0x90u8, 0x3Du8], Usage::Bound),
+            // `cmp dword ptr [rbp+0x10], imm32`
+            ([0x90u8, 0x81u8, 0x7Du8, 0x10u8], Usage::Bound),
+            // `cmp dword ptr [rsp+0x3D], imm32`, which ends like `cmp eax`
+            ([0x81u8, 0x7Cu8, 0x24u8, 0x3Du8], Usage::Bound),
+            // `mov r8d, imm32`
+            ([0x90u8, 0x90u8, 0x41u8, 0xB8u8], Usage::Size),
+            // `mov dword ptr [rsp+0x20], imm32`
+            ([0xC7u8, 0x44u8, 0x24u8, 0x20u8], Usage::Size),
+            // `add ecx, imm32`
+            ([0x90u8, 0x90u8, 0x81u8, 0xC1u8], Usage::Unknown),
+            ([0x90u8, 0x90u8, 0x90u8, 0x90u8], Usage::Unknown),
+        ] {
+            assert_eq!(Usage::detect(&code), usage, "{code:02X?}");
+        }
+    }
+
+    #[test]
+    fn unaligned() -> Result<()> {
+        let mut memory = region::alloc(0x1000usize, Protection::READ_WRITE)?;
+        // Like the real ones, which are 1 past a multiple of 4
+        let immediate = unsafe { memory.as_mut_ptr::<u8>().add(0x531usize) };
+
+        unsafe { immediate.cast::<u32>().write_unaligned(10000u32) };
+
+        assert_eq!(
+            unsafe { __read_immediate(immediate)? },
+            10000u32,
+            "Unaligned immediates should be read as is"
+        );
+
+        Ok(())
     }
 }
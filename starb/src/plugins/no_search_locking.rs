@@ -1,8 +1,9 @@
 use crate::app::StarApp;
+use crate::patch::Patch;
 use crate::plugin::Plugin;
 use crate::plugin::PluginPass;
-use crate::utils::base;
-use crate::utils::write;
+use crate::setting::ApplyPolicy;
+use crate::setting::Setting;
 use eframe::CreationContext;
 use eframe::Frame;
 use eframe::Storage;
@@ -11,17 +12,42 @@ use egui::Ui;
 use eyre::Result;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::json;
+use serde_json::Value;
+use tracing::error;
 use tracing::instrument;
 
 const PLUGIN_KEY: &str = "no_search_locking";
 
+/// How this is stored.
 #[derive(Deserialize, Serialize)]
-pub struct NoSearchLocking(bool);
+struct Saved(bool);
+
+pub struct NoSearchLocking(Setting<bool>);
 
-#[allow(clippy::derivable_impls)]
 impl Default for NoSearchLocking {
     fn default() -> Self {
-        Self(false)
+        Self(Setting::new("Enabled", false, ApplyPolicy::Verified))
+    }
+}
+
+impl NoSearchLocking {
+    /// Write either the fixed or vanilla instructions, depending on whether
+    /// this is enabled.
+    fn apply(&mut self) -> Result<()> {
+        self.0
+            .apply(|&enabled| __patches(enabled).iter().try_for_each(Patch::apply))?;
+
+        // Read it back, so the GUI doesn't claim it worked when it didn't
+        if !self.0.verify(|&enabled| {
+            __patches(enabled)
+                .iter()
+                .try_fold(true, |verified, patch| Ok(verified && patch.verify()?))
+        })? {
+            eyre::bail!("Patches weren't in place after applying them");
+        }
+
+        Ok(())
     }
 }
 
@@ -31,36 +57,18 @@ impl Plugin for NoSearchLocking {
     where
         Self: Sized,
     {
-        let no_search_locking =
-            eframe::get_value::<Self>(cc.storage.expect("Probably unreachable?"), PLUGIN_KEY)
-                .unwrap_or_default();
-
-        // TODO: Don't do this here. Quick hotfix
-        unsafe {
-            if no_search_locking.0 {
-                // Prevent flashing on GUI
-                write::<[u8; 3usize]>(base().byte_offset(0x3EC50Aisize).cast(), [
-                    0x8Bu8, 0xC2u8, 0x90u8,
-                ])?;
-                // nop 6, the actual fix
-                write::<[u8; 6usize]>(base().byte_offset(0x3EFB2Cisize).cast(), [
-                    0x66u8, 0x0Fu8, 0x1Fu8, 0x44u8, 0x00u8, 0x00u8,
-                ])?;
-                write::<[u8; 6usize]>(base().byte_offset(0x3EFD4Eisize).cast(), [
-                    0x66u8, 0x0Fu8, 0x1Fu8, 0x44u8, 0x00u8, 0x00u8,
-                ])?;
-            }
-            else {
-                write::<[u8; 3usize]>(base().byte_offset(0x3EC50Aisize).cast(), [
-                    0x0Fu8, 0x44u8, 0xC2u8,
-                ])?;
-                write::<[u8; 6usize]>(base().byte_offset(0x3EFB2Cisize).cast(), [
-                    0x0Fu8, 0x85u8, 0x82u8, 0x00u8, 0x00u8, 0x00u8,
-                ])?;
-                write::<[u8; 6usize]>(base().byte_offset(0x3EFD4Eisize).cast(), [
-                    0x0Fu8, 0x85u8, 0xBAu8, 0x01u8, 0x00u8, 0x00u8,
-                ])?;
-            }
+        let mut no_search_locking = Self::default();
+
+        if let Some(saved) =
+            eframe::get_value::<Saved>(cc.storage.expect("Probably unreachable?"), PLUGIN_KEY)
+        {
+            no_search_locking.0 = no_search_locking.0.with_requested(saved.0);
+        }
+
+        // TODO: Don't do this here. Quick hotfix. Failing to load aborts SE, which isn't
+        // worth it over this, the self-test shows it
+        if let Err(e) = no_search_locking.apply() {
+            error!("Failed to apply `{}`: {e}", no_search_locking.name());
         }
 
         Ok(no_search_locking)
@@ -74,56 +82,80 @@ impl Plugin for NoSearchLocking {
         "No Search Locking".to_owned()
     }
 
+    fn key(&self) -> String {
+        PLUGIN_KEY.to_owned()
+    }
+
     fn priority(&self) -> Option<usize> {
         Some(2usize)
     }
 
     #[instrument(skip(self, _app, _ctx, _frame, ui))]
     fn add_plugin(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
-        if ui
-            .checkbox(&mut self.0, "Enabled")
-            .on_hover_text(
+        let name = self.name();
+
+        if self.0.ui(ui, &name, |ui, enabled| {
+            ui.checkbox(enabled, "Enabled").on_hover_text(
                 "Prevent the Star browser from occasionally locking after a search \
                  concludes.\n\nThis is a MAJOR bugfix; It's highly recommended to keep this on.",
             )
-            .clicked()
+        }) {
+            if let Err(e) = self.apply() {
+                error!("Failed to update `{name}`: {e}");
+            }
+        }
+    }
+
+    fn patches(&self) -> Vec<Patch> {
+        __patches(*self.0.applied_or_default())
+    }
+
+    fn settings(&self) -> Value {
+        json!({ "enabled": self.0.requested() })
+    }
+
+    fn set_settings(&mut self, settings: Value) -> Result<()> {
+        let name = self.name();
+
+        if self
+            .0
+            .request(&name, serde_json::from_value(settings["enabled"].clone())?)
         {
-            // Weird try block
-            || -> Result<()> {
-                unsafe {
-                    if self.0 {
-                        // Prevent flashing on GUI
-                        write::<[u8; 3usize]>(base().byte_offset(0x3EC50Aisize).cast(), [
-                            0x8Bu8, 0xC2u8, 0x90u8,
-                        ])?;
-                        // nop 6, the actual fix
-                        write::<[u8; 6usize]>(base().byte_offset(0x3EFB2Cisize).cast(), [
-                            0x66u8, 0x0Fu8, 0x1Fu8, 0x44u8, 0x00u8, 0x00u8,
-                        ])?;
-                        write::<[u8; 6usize]>(base().byte_offset(0x3EFD4Eisize).cast(), [
-                            0x66u8, 0x0Fu8, 0x1Fu8, 0x44u8, 0x00u8, 0x00u8,
-                        ])?;
-                    }
-                    else {
-                        write::<[u8; 3usize]>(base().byte_offset(0x3EC50Aisize).cast(), [
-                            0x0Fu8, 0x44u8, 0xC2u8,
-                        ])?;
-                        write::<[u8; 6usize]>(base().byte_offset(0x3EFB2Cisize).cast(), [
-                            0x0Fu8, 0x85u8, 0x82u8, 0x00u8, 0x00u8, 0x00u8,
-                        ])?;
-                        write::<[u8; 6usize]>(base().byte_offset(0x3EFD4Eisize).cast(), [
-                            0x0Fu8, 0x85u8, 0xBAu8, 0x01u8, 0x00u8, 0x00u8,
-                        ])?;
-                    }
-
-                    Ok(())
-                }
-            }()
-            .expect("Failed to update ):");
+            self.apply()?;
         }
+
+        Ok(())
     }
 
     fn save(&mut self, _app: &mut StarApp, storage: &mut dyn Storage) {
-        eframe::set_value(storage, PLUGIN_KEY, &self);
+        eframe::set_value(storage, PLUGIN_KEY, &Saved(*self.0.requested()));
     }
 }
+
+fn __patches(enabled: bool) -> Vec<Patch> {
+    vec![
+        // Prevent flashing on GUI
+        Patch {
+            name: "GUI flashing",
+            rva: 0x3EC50Aisize,
+            vanilla: vec![0x0Fu8, 0x44u8, 0xC2u8],
+            patched: vec![0x8Bu8, 0xC2u8, 0x90u8],
+            applied: enabled,
+        },
+        // nop 6, the actual fix
+        Patch {
+            name: "Search locking (1)",
+            rva: 0x3EFB2Cisize,
+            vanilla: vec![0x0Fu8, 0x85u8, 0x82u8, 0x00u8, 0x00u8, 0x00u8],
+            patched: vec![0x66u8, 0x0Fu8, 0x1Fu8, 0x44u8, 0x00u8, 0x00u8],
+            applied: enabled,
+        },
+        Patch {
+            name: "Search locking (2)",
+            rva: 0x3EFD4Eisize,
+            vanilla: vec![0x0Fu8, 0x85u8, 0xBAu8, 0x01u8, 0x00u8, 0x00u8],
+            patched: vec![0x66u8, 0x0Fu8, 0x1Fu8, 0x44u8, 0x00u8, 0x00u8],
+            applied: enabled,
+        },
+    ]
+}
@@ -1,9 +1,10 @@
-//! TODO
+//! Keeps SE's search radius from going negative, by checking it every tick.
 
 use crate::app::StarApp;
+use crate::game::Located;
+use crate::game::Value;
 use crate::plugin::Plugin;
 use crate::plugin::PluginPass;
-use crate::utils::base;
 use eframe::CreationContext;
 use eframe::Frame;
 use eframe::Storage;
@@ -12,11 +13,16 @@ use egui::Ui;
 use eyre::Result;
 use serde::Deserialize;
 use serde::Serialize;
-use std::mem::forget;
-use std::slice;
+use serde_json::json;
+use std::time::Duration;
+use tracing::info;
 use tracing::instrument;
 
 const PLUGIN_KEY: &str = "non_negative_search_radius";
+/// Where SE keeps the search radius, in parsecs.
+const SEARCH_RADIUS_RVA: isize = 0x10457B0isize;
+/// How often the search radius is checked.
+const TICK_INTERVAL: Duration = Duration::from_millis(100u64);
 
 /// .0 = enabled, .1 = behavior
 #[derive(Deserialize, Serialize)]
@@ -49,12 +55,16 @@ impl Plugin for NonNegativeSearchRadius {
         "Non Negative Search Radius".to_owned()
     }
 
+    fn key(&self) -> String {
+        PLUGIN_KEY.to_owned()
+    }
+
     fn priority(&self) -> Option<usize> {
         Some(3usize)
     }
 
-    #[instrument(skip(self, _app, ctx, _frame, ui))]
-    fn add_plugin(&mut self, _app: &mut StarApp, ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
+    #[instrument(skip(self, _app, _ctx, _frame, ui))]
+    fn add_plugin(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
         ui.checkbox(&mut self.0, "Enabled")
             .on_hover_text("Disallow negative search radii\n\nThis is a very minor bugfix.");
 
@@ -63,47 +73,43 @@ impl Plugin for NonNegativeSearchRadius {
                 .on_hover_text(
                     "Use the absolute value of the search radius instead of setting it to 0.",
                 );
+        }
+    }
 
-            let search_radius = unsafe { base().byte_offset(0x10457B0isize).cast::<f64>() };
-            let old = unsafe { search_radius.read() };
+    fn tick_interval(&self) -> Option<Duration> {
+        self.0.then_some(TICK_INTERVAL)
+    }
 
-            if old.is_sign_negative() {
-                let new = if self.1 { old.abs() } else { 0.0f64 };
+    fn tick(&mut self) -> Result<()> {
+        let search_radius = Value::<f64>::from_rva(SEARCH_RADIUS_RVA);
+        let old = search_radius.read()?;
 
-                unsafe { search_radius.write(new) };
+        if !old.is_sign_negative() {
+            return Ok(());
+        }
 
-                let str_ptr =
-                    unsafe { base().byte_offset(0x1046F08isize).cast::<*mut u16>().read() };
-                let str_len_ptr = unsafe { base().byte_offset(0x1046F18isize).cast::<usize>() };
-                let str_len = unsafe { str_len_ptr.read() };
-                let str = String::from_utf16(unsafe { slice::from_raw_parts(str_ptr, str_len) })
-                    .expect("String was not valid utf-16. Shame on you, vladimir.");
+        let new = if self.1 { old.abs() } else { 0.0f64 };
 
-                let (new_str, new_str_len, _) = new
-                    .to_string()
-                    .encode_utf16()
-                    .collect::<Vec<u16>>()
-                    .into_raw_parts();
+        // SE's text box keeps showing the negative radius. It's one of SE's
+        // strings, which can't be replaced from here without knowing how it
+        // allocates them
+        search_radius.write(new)?;
 
-                // TODO: We could probably do this better.
-                #[allow(clippy::forget_copy)]
-                forget(new_str);
+        info!("Changed search radius from {old} to {new}");
 
-                unsafe { (str_ptr as usize as *mut usize).write(new_str as usize) }
-                unsafe { str_len_ptr.write(new_str_len) };
-            }
+        Ok(())
+    }
 
-            // TODO: We could probably do this better.
-            ctx.request_repaint();
-        }
+    fn settings(&self) -> serde_json::Value {
+        json!({ "enabled": self.0, "use_absolute_value": self.1 })
     }
 
-    // fn add_context(&mut self, _app: &mut StarApp, _ctx: &Context, _frame: &mut Frame, ui: &mut Ui) {
-    //     ui.label(format!(
-    //         "Max search radius is currently {}",
-    //         if self.0 { "100.0f64" } else { "UNLIMITED" }
-    //     ));
-    // }
+    fn set_settings(&mut self, settings: serde_json::Value) -> Result<()> {
+        self.0 = serde_json::from_value(settings["enabled"].clone())?;
+        self.1 = serde_json::from_value(settings["use_absolute_value"].clone())?;
+
+        Ok(())
+    }
 
     fn save(&mut self, _app: &mut StarApp, storage: &mut dyn Storage) {
         eframe::set_value(storage, PLUGIN_KEY, &self);
@@ -0,0 +1,383 @@
+//! Restarting SE, for when a plugin's change only applies on startup. This
+//! saves everything, starts a helper that waits for SE to exit and then starts
+//! it again, and closes SE's main window so SE exits like it normally would.
+//!
+//! Starting and exiting processes is behind [`Launcher`], so the sequence can
+//! be tested without actually doing either.
+
+use crate::version::APP_ID;
+use eyre::Result;
+use std::env;
+use std::fmt;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::process;
+use std::process::Command;
+use tracing::info;
+use tracing::warn;
+
+/// How SE is started again.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Relaunch {
+    /// `steam://rungameid/314650`. SE's command line is lost, but this is how
+    /// Steam copies are normally started.
+    Steam,
+    /// SE's exe, with the command line it was started with.
+    Exe {
+        exe: PathBuf,
+        /// Everything after the exe, as it was passed.
+        args: String,
+        dir: PathBuf,
+    },
+}
+
+impl Relaunch {
+    /// Via Steam if SE is from Steam, otherwise the exe.
+    pub fn detect(from_steam: bool) -> Result<Self> {
+        if from_steam {
+            return Ok(Self::Steam);
+        }
+
+        Self::exe()
+    }
+
+    /// The exe, with this SE's command line and working directory.
+    pub fn exe() -> Result<Self> {
+        Ok(Self::Exe {
+            exe: env::current_exe()?,
+            args: __args(),
+            dir: env::current_dir()?,
+        })
+    }
+}
+
+impl fmt::Display for Relaunch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Steam => write!(f, "Via Steam (steam://rungameid/{APP_ID})"),
+            Self::Exe {
+                ref exe, ref args, ..
+            } => write!(f, "{} {args}", exe.display()),
+        }
+    }
+}
+
+/// Starts and exits processes.
+pub trait Launcher {
+    /// Start something that waits for the process `pid` to exit, then starts
+    /// SE again.
+    fn launch_after_exit(&mut self, pid: u32, relaunch: &Relaunch) -> Result<()>;
+
+    /// Ask SE to exit. It does so some time after this returns.
+    fn exit(&mut self);
+}
+
+/// Starts a hidden PowerShell to relaunch SE, since nothing of starb's is left
+/// once SE has exited.
+pub struct SystemLauncher;
+
+impl Launcher for SystemLauncher {
+    fn launch_after_exit(&mut self, pid: u32, relaunch: &Relaunch) -> Result<()> {
+        let mut command = Command::new("powershell.exe");
+        command.args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-WindowStyle",
+            "Hidden",
+            "-Command",
+            &script(pid, relaunch),
+        ]);
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            use windows_sys::Win32::System::Threading::CREATE_NO_WINDOW;
+
+            command.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        command.spawn()?;
+
+        Ok(())
+    }
+
+    fn exit(&mut self) {
+        #[cfg(windows)]
+        {
+            use windows_sys::Win32::UI::WindowsAndMessaging::PostMessageW;
+            use windows_sys::Win32::UI::WindowsAndMessaging::WM_CLOSE;
+
+            // Like the user closed it, so SE saves and cleans up after itself
+            if let Some(hwnd) = __se_window() {
+                if unsafe { PostMessageW(hwnd, WM_CLOSE, 0usize, 0isize) } != 0i32 {
+                    return;
+                }
+            }
+        }
+
+        warn!("Failed to close SE's main window, exiting instead");
+
+        process::exit(0i32);
+    }
+}
+
+/// Save with `save`, then restart SE using `launcher`. If SE can't be started
+/// again, this doesn't exit.
+pub fn restart(
+    launcher: &mut dyn Launcher,
+    relaunch: &Relaunch,
+    save: impl FnOnce(),
+) -> Result<()> {
+    info!("Restarting SE: {relaunch}");
+
+    save();
+
+    launcher.launch_after_exit(process::id(), relaunch)?;
+    launcher.exit();
+
+    Ok(())
+}
+
+/// PowerShell that waits for `pid` to exit, then starts SE.
+#[must_use]
+pub fn script(pid: u32, relaunch: &Relaunch) -> String {
+    let start = match *relaunch {
+        Relaunch::Steam => format!("Start-Process 'steam://rungameid/{APP_ID}'"),
+        Relaunch::Exe {
+            ref exe,
+            ref args,
+            ref dir,
+        } => {
+            let mut start = format!(
+                "Start-Process -FilePath {} -WorkingDirectory {}",
+                __quote(&exe.to_string_lossy()),
+                __quote(&dir.to_string_lossy()),
+            );
+
+            // PowerShell refuses an empty argument list
+            if !args.trim().is_empty() {
+                write!(start, " -ArgumentList {}", __quote(args)).expect("Unreachable");
+            }
+
+            start
+        },
+    };
+
+    format!("Wait-Process -Id {pid} -ErrorAction SilentlyContinue; {start}")
+}
+
+/// Split a Windows command line into the program and everything after it,
+/// following how `CommandLineToArgvW` finds the program.
+#[must_use]
+pub fn split_command_line(command_line: &str) -> (&str, &str) {
+    let (program, rest) = match command_line.strip_prefix('"') {
+        // The program can't contain quotes, so no escaping to worry about
+        Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+        None => command_line
+            .split_once([' ', '\t'])
+            .unwrap_or((command_line, "")),
+    };
+
+    (program, rest.trim_start())
+}
+
+/// Single-quoted PowerShell string.
+fn __quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Everything after SE's exe on its command line, as it was passed.
+#[cfg(windows)]
+fn __args() -> String {
+    use windows_sys::Win32::System::Environment::GetCommandLineW;
+
+    let command_line = unsafe { __wide_str(GetCommandLineW()) };
+
+    split_command_line(&command_line).1.to_owned()
+}
+
+/// Everything after SE's exe on its command line. Outside of Windows, it's
+/// been split already.
+#[cfg(not(windows))]
+fn __args() -> String {
+    env::args().skip(1usize).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(windows)]
+unsafe fn __wide_str(p: *const u16) -> String {
+    if p.is_null() {
+        return String::new();
+    }
+
+    let len = (0usize..)
+        .take_while(|&i| unsafe { *p.add(i) } != 0u16)
+        .count();
+
+    String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(p, len) })
+}
+
+/// SE's main window, i.e., a visible window of SE's that isn't starb's or SE's
+/// splash screen.
+#[cfg(windows)]
+fn __se_window() -> Option<windows_sys::Win32::Foundation::HWND> {
+    use std::ptr::addr_of_mut;
+    use windows_sys::Win32::UI::WindowsAndMessaging::EnumWindows;
+
+    let mut found = 0isize;
+
+    // This "fails" when the callback stops early, so the result is useless
+    unsafe { EnumWindows(Some(__find_se_window), addr_of_mut!(found) as isize) };
+
+    (found != 0isize).then_some(found)
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn __find_se_window(hwnd: isize, found: isize) -> i32 {
+    use std::ptr::addr_of_mut;
+    use windows_sys::Win32::System::Threading::GetCurrentProcessId;
+    use windows_sys::Win32::UI::WindowsAndMessaging::GetClassNameW;
+    use windows_sys::Win32::UI::WindowsAndMessaging::GetWindowTextW;
+    use windows_sys::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
+    use windows_sys::Win32::UI::WindowsAndMessaging::IsWindowVisible;
+
+    let mut pid = 0u32;
+    unsafe { GetWindowThreadProcessId(hwnd, addr_of_mut!(pid)) };
+
+    if pid != unsafe { GetCurrentProcessId() } || unsafe { IsWindowVisible(hwnd) } == 0i32 {
+        return i32::from(true);
+    }
+
+    let mut class = [0u16; 256usize];
+    let class_len = unsafe { GetClassNameW(hwnd, class.as_mut_ptr(), class.len() as i32) };
+    let class = String::from_utf16_lossy(&class[..class_len.max(0i32) as usize]);
+
+    let mut title = [0u16; 256usize];
+    let title_len = unsafe { GetWindowTextW(hwnd, title.as_mut_ptr(), title.len() as i32) };
+    let title = String::from_utf16_lossy(&title[..title_len.max(0i32) as usize]);
+
+    if class == "SE Splash"
+        || class == "Winit Thread Event Target"
+        || title.starts_with("Star Browser Utilities")
+    {
+        return i32::from(true);
+    }
+
+    // SAFETY: This is the only reference to `found`
+    unsafe { (found as *mut isize).write(hwnd) };
+
+    i32::from(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MockLauncher {
+        calls: Vec<String>,
+        fail: bool,
+    }
+
+    impl Launcher for MockLauncher {
+        fn launch_after_exit(&mut self, pid: u32, relaunch: &Relaunch) -> Result<()> {
+            self.calls.push(format!("launch {pid} {relaunch}"));
+
+            if self.fail {
+                eyre::bail!("Failed to start PowerShell");
+            }
+
+            Ok(())
+        }
+
+        fn exit(&mut self) {
+            self.calls.push("exit".to_owned());
+        }
+    }
+
+    #[test]
+    fn sequence() -> Result<()> {
+        let mut launcher = MockLauncher::default();
+        let mut saved = false;
+
+        restart(&mut launcher, &Relaunch::Steam, || saved = true)?;
+
+        assert!(saved);
+        assert_eq!(launcher.calls, [
+            format!(
+                "launch {} Via Steam (steam://rungameid/314650)",
+                process::id()
+            ),
+            "exit".to_owned(),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_launch_doesnt_exit() {
+        let mut launcher = MockLauncher {
+            fail: true,
+            ..Default::default()
+        };
+        let mut saved = false;
+
+        assert!(restart(&mut launcher, &Relaunch::Steam, || saved = true).is_err());
+        // Saving first is harmless, exiting without a new SE isn't
+        assert!(saved);
+        assert_eq!(launcher.calls.len(), 1usize);
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(
+            script(42u32, &Relaunch::Steam),
+            "Wait-Process -Id 42 -ErrorAction SilentlyContinue; Start-Process \
+             'steam://rungameid/314650'",
+        );
+
+        let relaunch = Relaunch::Exe {
+            exe: PathBuf::from(r"C:\Games\Bob's SE\system\SpaceEngine.exe"),
+            args: "-noaudio".to_owned(),
+            dir: PathBuf::from(r"C:\Games\Bob's SE\system"),
+        };
+
+        assert_eq!(
+            script(42u32, &relaunch),
+            "Wait-Process -Id 42 -ErrorAction SilentlyContinue; Start-Process -FilePath \
+             'C:\\Games\\Bob''s SE\\system\\SpaceEngine.exe' -WorkingDirectory 'C:\\Games\\Bob''s \
+             SE\\system' -ArgumentList '-noaudio'",
+        );
+
+        let Relaunch::Exe { exe, dir, .. } = relaunch
+        else {
+            unreachable!();
+        };
+        let relaunch = Relaunch::Exe {
+            exe,
+            args: " ".to_owned(),
+            dir,
+        };
+
+        assert!(!script(42u32, &relaunch).contains("-ArgumentList"));
+    }
+
+    #[test]
+    fn command_lines() {
+        assert_eq!(
+            split_command_line(r#""C:\Program Files\SE\SpaceEngine.exe"  -a "b c""#),
+            (r"C:\Program Files\SE\SpaceEngine.exe", r#"-a "b c""#),
+        );
+        assert_eq!(
+            split_command_line("SpaceEngine.exe -a"),
+            ("SpaceEngine.exe", "-a")
+        );
+        assert_eq!(
+            split_command_line("SpaceEngine.exe"),
+            ("SpaceEngine.exe", "")
+        );
+        assert_eq!(
+            split_command_line(r#""SpaceEngine.exe""#),
+            ("SpaceEngine.exe", "")
+        );
+    }
+}
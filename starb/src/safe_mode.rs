@@ -0,0 +1,431 @@
+//! Safe mode. starb counts how many times in a row SE has started without
+//! reaching a stable state, and after [`FAILED_STARTS`] of those, starts with
+//! every plugin disabled. Plugins can then be re-enabled one at a time, to
+//! find the one that's crashing.
+//!
+//! This is stored in `starb_safe_mode.json`, next to SE's exe. Deleting it
+//! leaves safe mode.
+
+use crate::utils::sys_folder;
+use egui::Color32;
+use egui::RichText;
+use egui::Ui;
+use eyre::Result;
+use hashbrown::HashMap;
+use once_cell::sync::Lazy;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::thread::ThreadId;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+/// Failed starts in a row before starting in safe mode.
+pub const FAILED_STARTS: u32 = 3u32;
+/// How long SE has to run before it's considered stable.
+const STABLE_AFTER: Duration = Duration::from_secs(60u64);
+/// How many crashes to remember.
+const MAX_CRASHES: usize = 10usize;
+
+static STATE: Lazy<Mutex<State>> = Lazy::new(Mutex::default);
+static STARTED: OnceCell<Instant> = OnceCell::new();
+static STABLE: AtomicBool = AtomicBool::new(false);
+/// Same as [`ACTIVE`], but every thread's, as thread ID -> (thread name, key),
+/// for crash reports.
+static ACTIVE_THREADS: Lazy<Mutex<HashMap<ThreadId, (String, String)>>> = Lazy::new(Mutex::default);
+/// (key, name) of each plugin that wasn't loaded, because of safe mode.
+static DISABLED: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Plugin whose code is currently running on this thread, if any. Plugins
+    /// run on the GUI's, tick's, events' and control API's threads at the same
+    /// time, so only this thread's says who's to blame for its panic.
+    static ACTIVE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+struct State {
+    /// Starts since SE last reached a stable state, including this one.
+    failed_starts: u32,
+    /// Whether a crash has been recorded since the last start.
+    crash_recorded: bool,
+    safe_mode: bool,
+    /// Keys of plugins the user has re-enabled while in safe mode.
+    reenabled: Vec<String>,
+    /// Most recent last.
+    crashes: Vec<Crash>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Crash {
+    /// Seconds since 1970.
+    pub time: u64,
+    /// Key of the plugin whose code was running, if any.
+    pub plugin: Option<String>,
+    pub message: String,
+}
+
+impl State {
+    /// Count a start, returning whether this entered safe mode.
+    fn on_start(&mut self, now: u64) -> bool {
+        // Crashes that aren't panics don't get recorded, but we can tell they happened
+        if self.failed_starts > 0u32 && !self.crash_recorded {
+            self.on_crash(
+                None,
+                "SE closed before it was stable, without starb noticing".to_owned(),
+                now,
+            );
+        }
+
+        let entered = self.failed_starts >= FAILED_STARTS && !self.safe_mode;
+
+        if entered {
+            self.safe_mode = true;
+            self.reenabled.clear();
+        }
+
+        self.failed_starts += 1u32;
+        self.crash_recorded = false;
+
+        entered
+    }
+
+    /// SE reached a stable state, so this start didn't fail after all.
+    fn on_stable(&mut self) {
+        info!("SE is stable");
+
+        self.failed_starts = 0u32;
+    }
+
+    fn on_crash(&mut self, plugin: Option<String>, message: String, now: u64) {
+        self.crashes.push(Crash {
+            time: now,
+            plugin,
+            message,
+        });
+        self.crash_recorded = true;
+
+        let excess = self.crashes.len().saturating_sub(MAX_CRASHES);
+        self.crashes.drain(..excess);
+    }
+}
+
+/// Count this start, and enter safe mode if there's been too many failed ones.
+/// Call this as early as possible.
+pub fn begin() {
+    STARTED.get_or_init(Instant::now);
+
+    let mut state = __load().unwrap_or_else(|e| {
+        // Likely doesn't exist yet
+        info!("Couldn't read safe mode state, starting fresh: {e}");
+        State::default()
+    });
+
+    if state.on_start(__now()) {
+        warn!(
+            "SE failed to start {} times in a row. Starting in safe mode!",
+            state.failed_starts - 1u32
+        );
+    }
+
+    __save(&state);
+    *STATE.lock() = state;
+}
+
+/// Record a crash. This is called when panicking.
+pub fn record_crash(message: &str) {
+    let plugin = active();
+
+    // If this is locked, we're likely panicking while saving
+    let Some(mut state) = STATE.try_lock_for(Duration::from_millis(100u64))
+    else {
+        return;
+    };
+
+    state.on_crash(plugin, message.to_owned(), __now());
+
+    __save(&state);
+}
+
+/// Mark SE as stable once it's been running for long enough. Call this often.
+pub fn tick() {
+    if STARTED
+        .get()
+        .is_some_and(|started| started.elapsed() >= STABLE_AFTER)
+    {
+        mark_stable();
+    }
+}
+
+/// Reset the failed start counter. Closing SE normally counts too.
+pub fn mark_stable() {
+    if STABLE.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    let mut state = STATE.lock();
+    state.on_stable();
+
+    __save(&state);
+}
+
+/// Whether starb started in safe mode. This doesn't wait for long, since it's
+/// also used when panicking.
+#[must_use]
+pub fn enabled() -> bool {
+    STATE
+        .try_lock_for(Duration::from_millis(100u64))
+        .is_some_and(|state| state.safe_mode)
+}
+
+/// Whether the plugin `key` shouldn't be loaded. `name` is remembered, so it
+/// can be re-enabled from the GUI.
+#[must_use]
+pub fn is_disabled(key: &str, name: &str) -> bool {
+    let state = STATE.lock();
+    let disabled = state.safe_mode && !state.reenabled.iter().any(|k| k == key);
+
+    if disabled {
+        DISABLED.lock().push((key.to_owned(), name.to_owned()));
+    }
+
+    disabled
+}
+
+/// Plugin whose code is currently running on this thread, if any.
+#[must_use]
+pub fn active() -> Option<String> {
+    // This thread may be going away, or have panicked in `enter`, but that's fine
+    ACTIVE
+        .try_with(|active| active.try_borrow().ok().and_then(|active| active.clone()))
+        .ok()
+        .flatten()
+}
+
+/// Plugins whose code is currently running on every thread, as (thread name,
+/// key). `None` if that's busy, since this is used when panicking.
+#[must_use]
+pub fn active_threads() -> Option<Vec<(String, String)>> {
+    let mut active = ACTIVE_THREADS
+        .try_lock_for(Duration::from_millis(100u64))?
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    active.sort();
+
+    Some(active)
+}
+
+/// Mark the plugin `key` as running on this thread until the returned guard
+/// is dropped, so it's blamed if anything crashes.
+#[must_use]
+pub fn enter(key: String) -> ActiveGuard {
+    let previous = ACTIVE.with(|active| active.replace(Some(key)));
+
+    __publish();
+
+    ActiveGuard(previous, PhantomData)
+}
+
+/// Only for the thread it was made on.
+pub struct ActiveGuard(Option<String>, PhantomData<*const ()>);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| *active.borrow_mut() = self.0.take());
+
+        __publish();
+    }
+}
+
+/// Let [`active_threads`] know what this thread's running.
+fn __publish() {
+    let thread = thread::current();
+    let key = active();
+    let mut active = ACTIVE_THREADS.lock();
+
+    match key {
+        Some(key) => {
+            let name = thread
+                .name()
+                .map_or_else(|| format!("{:?}", thread.id()), ToOwned::to_owned);
+
+            active.insert(thread.id(), (name, key));
+        },
+        None => {
+            active.remove(&thread.id());
+        },
+    }
+}
+
+/// Show what safe mode disabled, and let the user re-enable it. Returns
+/// (name, reason) for each plugin that needs a restart.
+pub fn ui(ui: &mut Ui) -> Vec<(String, String)> {
+    let mut restarts = vec![];
+    let mut state = STATE.lock();
+
+    if !state.safe_mode {
+        return restarts;
+    }
+
+    ui.heading(RichText::new("Safe mode").color(Color32::YELLOW));
+    ui.label(format!(
+        "SE failed to start {FAILED_STARTS} times in a row, so starb started with every plugin \
+         disabled. Re-enable them one at a time to find which is the problem."
+    ));
+
+    ui.separator();
+    ui.label("Recent crashes:");
+
+    let now = __now();
+
+    for crash in state.crashes.iter().rev() {
+        ui.label(format!(
+            "{}: {} ({})",
+            __ago(now.saturating_sub(crash.time)),
+            crash.message,
+            crash.plugin.as_deref().unwrap_or("no plugin was running"),
+        ));
+    }
+
+    ui.separator();
+
+    let mut changed = false;
+
+    for (key, name) in DISABLED.lock().iter().map(|plugin| (&plugin.0, &plugin.1)) {
+        ui.horizontal(|ui| {
+            ui.label(name);
+
+            if state.reenabled.contains(key) {
+                ui.label("Enabled after restarting");
+            }
+            else if ui.button("Re-enable").clicked() {
+                state.reenabled.push(key.clone());
+                restarts.push((name.clone(), "Re-enabled after safe mode".to_owned()));
+                changed = true;
+            }
+        });
+    }
+
+    if ui.button("Leave safe mode").clicked() {
+        state.safe_mode = false;
+        state.reenabled.clear();
+        restarts.push(("Safe mode".to_owned(), "Left safe mode".to_owned()));
+        changed = true;
+    }
+
+    if changed {
+        __save(&state);
+    }
+
+    ui.separator();
+
+    restarts
+}
+
+/// Seconds since 1970.
+fn __now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// `secs` like "5 minutes ago".
+fn __ago(secs: u64) -> String {
+    let (n, unit) = match secs {
+        0u64..=59u64 => return "Just now".to_owned(),
+        60u64..=3599u64 => (secs / 60u64, "minute"),
+        3600u64..=86399u64 => (secs / 3600u64, "hour"),
+        _ => (secs / 86400u64, "day"),
+    };
+
+    format!("{n} {unit}{} ago", if n == 1u64 { "" } else { "s" })
+}
+
+fn __path() -> Result<PathBuf> {
+    Ok(sys_folder()?.join("starb_safe_mode.json"))
+}
+
+fn __load() -> Result<State> {
+    Ok(serde_json::from_str(&fs::read_to_string(__path()?)?)?)
+}
+
+fn __save(state: &State) {
+    let result =
+        __path().and_then(|path| Ok(fs::write(path, serde_json::to_string_pretty(state)?)?));
+
+    if let Err(e) = result {
+        error!("Failed to save safe mode state: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_starts() {
+        let mut state = State::default();
+
+        for _ in 0u32..FAILED_STARTS {
+            assert!(!state.on_start(0u64), "Entered safe mode too early");
+        }
+
+        // Every start but the first crashed, without starb noticing
+        assert_eq!(state.crashes.len(), (FAILED_STARTS - 1u32) as usize);
+        assert!(state.on_start(1u64), "Didn't enter safe mode");
+        assert!(state.safe_mode, "Didn't enter safe mode");
+        assert!(!state.on_start(2u64), "Entered safe mode twice");
+
+        // Being stable doesn't leave safe mode, but resets the counter
+        state.on_stable();
+        state.safe_mode = false;
+
+        assert!(!state.on_start(3u64), "Counted starts before it was stable");
+        assert_eq!(state.failed_starts, 1u32);
+    }
+
+    #[test]
+    fn crashes() {
+        let mut state = State::default();
+
+        state.on_start(0u64);
+        state.on_crash(Some("plugin".to_owned()), "Oops".to_owned(), 1u64);
+        state.on_start(2u64);
+
+        // Only the recorded crash, not another for the same start
+        assert_eq!(state.crashes.len(), 1usize);
+        assert_eq!(state.crashes[0usize].plugin.as_deref(), Some("plugin"));
+
+        for time in 0u64..20u64 {
+            state.on_crash(None, "Oops".to_owned(), time);
+        }
+
+        assert_eq!(state.crashes.len(), MAX_CRASHES);
+        assert_eq!(state.crashes.last().map(|crash| crash.time), Some(19u64));
+    }
+
+    #[test]
+    fn ago() {
+        assert_eq!(__ago(5u64), "Just now");
+        assert_eq!(__ago(60u64), "1 minute ago");
+        assert_eq!(__ago(7300u64), "2 hours ago");
+        assert_eq!(__ago(86400u64 * 3u64), "3 days ago");
+    }
+}
@@ -0,0 +1,1125 @@
+//! Value scanner, for finding addresses in SE (like the search radius at
+//! `0x10457B0`) without outside tools. This works like Cheat Engine's: a first
+//! scan finds everywhere a value is, then next scans narrow that down by how
+//! each has changed since.
+//!
+//! Scanning goes through [`Memory`], so it can be tested without SE.
+
+use crate::utils::base;
+use crate::utils::format_rva;
+use crate::utils::image_size;
+use crate::utils::rva;
+use crate::utils::PointerChain;
+use egui::Button;
+use egui::Color32;
+use egui::ComboBox;
+use egui::Grid;
+use egui::RichText;
+use egui::TextEdit;
+use egui::Ui;
+use eyre::bail;
+use eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::ffi::c_void;
+use std::fmt;
+use std::fmt::Write as _;
+use std::mem::size_of;
+use std::ops::Range;
+use std::ptr;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::info;
+use tracing::trace;
+use windows_sys::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+/// Most hits a scan keeps, so scanning for something like `0` doesn't use up
+/// all of SE's memory.
+pub const MAX_HITS: usize = 1000000usize;
+/// How much of a region is read at once.
+const CHUNK_SIZE: usize = 0x100000usize;
+/// How many hits the scanner tab shows.
+const SHOWN_HITS: usize = 100usize;
+/// How many characters of a UTF-16 watch are shown, when it wasn't from a scan.
+const WATCHED_CHARS: usize = 32usize;
+
+/// A range of memory that can be scanned.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Region {
+    pub base: usize,
+    pub len: usize,
+}
+
+/// Somewhere to scan.
+pub trait Memory {
+    /// Every region worth scanning.
+    fn regions(&self) -> Result<Vec<Region>>;
+
+    /// Read `buf.len()` bytes at `address`. This must fail, not crash, if any
+    /// of it isn't readable.
+    fn read(&self, address: usize, buf: &mut [u8]) -> Result<()>;
+}
+
+/// SE's (and so starb's) own memory. Only writable memory is scanned, since
+/// that's where anything worth finding is.
+pub struct ProcessMemory;
+
+impl Memory for ProcessMemory {
+    fn regions(&self) -> Result<Vec<Region>> {
+        let mut regions = vec![];
+
+        for region in region::query_range(ptr::null::<u8>(), isize::MAX as usize)? {
+            let region = region?;
+
+            if region.is_committed() && region.is_writable() && !region.is_guarded() {
+                regions.push(Region {
+                    base: region.as_range().start,
+                    len: region.len(),
+                });
+            }
+        }
+
+        Ok(regions)
+    }
+
+    fn read(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+        let mut read = 0usize;
+
+        // This fails instead of crashing if it's been freed since
+        if unsafe {
+            ReadProcessMemory(
+                GetCurrentProcess(),
+                address as *const c_void,
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                ptr::addr_of_mut!(read),
+            )
+        } == 0i32
+            || read != buf.len()
+        {
+            bail!("Failed to read {} bytes at {address:#X}", buf.len());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ValueType {
+    /// `i32`
+    #[default]
+    Int,
+    /// `f32`
+    Float,
+    /// `f64`
+    Double,
+    Utf16,
+}
+
+impl ValueType {
+    pub const ALL: [Self; 4usize] = [Self::Int, Self::Float, Self::Double, Self::Utf16];
+
+    /// Size of values of this type, in bytes. Strings are however long they
+    /// are.
+    #[must_use]
+    pub const fn size(self) -> Option<usize> {
+        match self {
+            Self::Int | Self::Float => Some(4usize),
+            Self::Double => Some(8usize),
+            Self::Utf16 => None,
+        }
+    }
+
+    /// What values of this type are assumed to be aligned to.
+    #[must_use]
+    pub const fn align(self) -> usize {
+        match self {
+            Self::Utf16 => 2usize,
+            _ => 4usize,
+        }
+    }
+
+    /// `bytes` as this type. `bytes` must be the right size.
+    #[must_use]
+    pub fn decode(self, bytes: &[u8]) -> Value {
+        match self {
+            Self::Int => Value::Int(i32::from_le_bytes(bytes.try_into().expect("Wrong size"))),
+            Self::Float => Value::Float(f32::from_le_bytes(bytes.try_into().expect("Wrong size"))),
+            Self::Double => {
+                Value::Double(f64::from_le_bytes(bytes.try_into().expect("Wrong size")))
+            },
+            Self::Utf16 => Value::Utf16(String::from_utf16_lossy(
+                &bytes
+                    .chunks_exact(2usize)
+                    .map(|c| u16::from_le_bytes([c[0usize], c[1usize]]))
+                    .collect::<Vec<_>>(),
+            )),
+        }
+    }
+
+    /// Compare `old` to `new`, if this type can be.
+    #[must_use]
+    pub fn compare(self, old: &[u8], new: &[u8]) -> Option<Ordering> {
+        match (self.decode(old), self.decode(new)) {
+            (Value::Int(old), Value::Int(new)) => Some(old.cmp(&new)),
+            (Value::Float(old), Value::Float(new)) => old.partial_cmp(&new),
+            (Value::Double(old), Value::Double(new)) => old.partial_cmp(&new),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Int => write!(f, "Int"),
+            Self::Float => write!(f, "Float"),
+            Self::Double => write!(f, "Double"),
+            Self::Utf16 => write!(f, "UTF-16"),
+        }
+    }
+}
+
+/// Serialized as just the value, like `1.5` or `"text"`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Int(i32),
+    Float(f32),
+    Double(f64),
+    Utf16(String),
+}
+
+impl Value {
+    pub fn parse(ty: ValueType, s: &str) -> Result<Self> {
+        Ok(match ty {
+            ValueType::Int => Self::Int(s.trim().parse()?),
+            ValueType::Float => Self::Float(s.trim().parse()?),
+            ValueType::Double => Self::Double(s.trim().parse()?),
+            ValueType::Utf16 if s.is_empty() => bail!("Nothing to search for"),
+            ValueType::Utf16 => Self::Utf16(s.to_owned()),
+        })
+    }
+
+    #[must_use]
+    pub const fn ty(&self) -> ValueType {
+        match *self {
+            Self::Int(_) => ValueType::Int,
+            Self::Float(_) => ValueType::Float,
+            Self::Double(_) => ValueType::Double,
+            Self::Utf16(_) => ValueType::Utf16,
+        }
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            Self::Int(v) => v.to_le_bytes().to_vec(),
+            Self::Float(v) => v.to_le_bytes().to_vec(),
+            Self::Double(v) => v.to_le_bytes().to_vec(),
+            Self::Utf16(ref v) => v.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        }
+    }
+
+    /// Whether `bytes` is this value. Floats only need to be within rounding
+    /// error of it.
+    #[must_use]
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        match *self {
+            Self::Float(v) => bytes
+                .try_into()
+                .map(f32::from_le_bytes)
+                .is_ok_and(|found| (found - v).abs() <= f32::EPSILON * v.abs()),
+            Self::Double(v) => bytes
+                .try_into()
+                .map(f64::from_le_bytes)
+                .is_ok_and(|found| (found - v).abs() <= f64::EPSILON * v.abs()),
+            _ => bytes == self.to_bytes(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Int(v) => write!(f, "{v}"),
+            Self::Float(v) => write!(f, "{v}"),
+            Self::Double(v) => write!(f, "{v}"),
+            Self::Utf16(ref v) => write!(f, "{v:?}"),
+        }
+    }
+}
+
+/// How a next scan narrows down hits, compared to the last scan.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Filter {
+    #[default]
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    /// Is a specific value now.
+    Exact,
+}
+
+impl Filter {
+    pub const ALL: [Self; 5usize] = [
+        Self::Changed,
+        Self::Unchanged,
+        Self::Increased,
+        Self::Decreased,
+        Self::Exact,
+    ];
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Changed => write!(f, "Changed"),
+            Self::Unchanged => write!(f, "Unchanged"),
+            Self::Increased => write!(f, "Increased"),
+            Self::Decreased => write!(f, "Decreased"),
+            Self::Exact => write!(f, "Exact value"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub address: usize,
+    /// As of the last scan.
+    pub value: Value,
+}
+
+/// Where `vec`'s elements can be, whether or not they're there yet.
+fn __allocation<T>(vec: &Vec<T>) -> Range<usize> {
+    let start = vec.as_ptr() as usize;
+
+    start..start + vec.capacity() * size_of::<T>()
+}
+
+/// Everywhere a value was found.
+#[derive(Clone, Debug)]
+pub struct Scan {
+    ty: ValueType,
+    /// Size of each value, in bytes.
+    len: usize,
+    addresses: Vec<usize>,
+    /// Each hit's value as of this scan, `len` bytes each.
+    values: Vec<u8>,
+    /// Whether hits were dropped because there were more than [`MAX_HITS`].
+    truncated: bool,
+}
+
+impl Scan {
+    /// Find everywhere `value` is in `memory`.
+    pub fn first(memory: &dyn Memory, value: &Value) -> Result<Self> {
+        let needle = value.to_bytes();
+        let align = value.ty().align();
+        let mut scan = Self {
+            ty: value.ty(),
+            len: needle.len(),
+            addresses: vec![],
+            values: vec![],
+            truncated: false,
+        };
+
+        // Comparing bytes is much faster, and all anything but floats needs
+        let fuzzy = matches!(value, Value::Float(_) | Value::Double(_));
+        // Room for values that start at the end of a chunk
+        let mut buf = vec![0u8; CHUNK_SIZE + needle.len()];
+
+        // Otherwise every hit is the same as the needle, so values are only kept
+        // as they're found if they can differ. Either way, nothing this has
+        // allocated can move while it's scanning
+        scan.addresses.reserve_exact(MAX_HITS);
+
+        if fuzzy {
+            scan.values.reserve_exact(MAX_HITS * needle.len());
+        }
+
+        // These have copies of what's being looked for (or of what's been found),
+        // so they shouldn't be found themselves
+        let own = [
+            __allocation(&buf),
+            __allocation(&needle),
+            __allocation(&scan.addresses),
+            __allocation(&scan.values),
+            match *value {
+                Value::Utf16(ref text) => {
+                    text.as_ptr() as usize..text.as_ptr() as usize + text.capacity()
+                },
+                _ => 0usize..0usize,
+            },
+        ];
+
+        'regions: for region in memory.regions()? {
+            let mut offset = 0usize;
+
+            while offset < region.len {
+                let end = (offset + CHUNK_SIZE + needle.len() - 1usize).min(region.len);
+                let chunk = &mut buf[..end - offset];
+
+                // Freed since it was found, most likely
+                if let Err(e) = memory.read(region.base + offset, chunk) {
+                    trace!("Skipping the rest of {:#X}: {e}", region.base);
+                    break;
+                }
+
+                // Only values starting in this chunk, the rest are in the next one
+                for start in (0usize..CHUNK_SIZE.min(chunk.len())).step_by(align) {
+                    let Some(bytes) = chunk.get(start..start + needle.len())
+                    else {
+                        break;
+                    };
+                    let address = region.base + offset + start;
+
+                    if !(bytes == needle || fuzzy && value.matches(bytes))
+                        || own.iter().any(|own| own.contains(&address))
+                    {
+                        continue;
+                    }
+
+                    if scan.addresses.len() == MAX_HITS {
+                        scan.truncated = true;
+
+                        break 'regions;
+                    }
+
+                    scan.addresses.push(address);
+
+                    if fuzzy {
+                        scan.values.extend_from_slice(bytes);
+                    }
+                }
+
+                offset += CHUNK_SIZE;
+            }
+        }
+
+        if !fuzzy {
+            scan.values = needle.repeat(scan.addresses.len());
+        }
+
+        Ok(scan)
+    }
+
+    /// Narrow down this scan's hits with `filter`. `value` is only used by
+    /// [`Filter::Exact`].
+    pub fn next(&self, memory: &dyn Memory, filter: Filter, value: Option<&Value>) -> Result<Self> {
+        let value = match (filter, value) {
+            (Filter::Exact, None) => bail!("Exact value needs a value"),
+            (Filter::Exact, Some(value)) if value.ty() != self.ty => {
+                bail!("This scan is for {}, not {}", self.ty, value.ty())
+            },
+            (Filter::Exact, Some(value)) if value.to_bytes().len() != self.len => {
+                bail!("Must be the same length as the first scan's")
+            },
+            (Filter::Increased | Filter::Decreased, _) if self.ty == ValueType::Utf16 => {
+                bail!("Strings can't increase or decrease")
+            },
+            (Filter::Exact, value) => value,
+            _ => None,
+        };
+
+        let mut scan = Self {
+            ty: self.ty,
+            len: self.len,
+            addresses: vec![],
+            values: vec![],
+            truncated: self.truncated,
+        };
+        let mut new = vec![0u8; self.len];
+
+        for (&address, old) in self
+            .addresses
+            .iter()
+            .zip(self.values.chunks_exact(self.len))
+        {
+            // Freed since, so it's definitely not what's being looked for
+            if memory.read(address, &mut new).is_err() {
+                continue;
+            }
+
+            let keep = match filter {
+                Filter::Changed => new != old,
+                Filter::Unchanged => new == old,
+                Filter::Increased => self.ty.compare(old, &new) == Some(Ordering::Less),
+                Filter::Decreased => self.ty.compare(old, &new) == Some(Ordering::Greater),
+                Filter::Exact => value.is_some_and(|value| value.matches(&new)),
+            };
+
+            if keep {
+                scan.addresses.push(address);
+                scan.values.extend_from_slice(&new);
+            }
+        }
+
+        Ok(scan)
+    }
+
+    #[must_use]
+    pub const fn ty(&self) -> ValueType {
+        self.ty
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Whether hits were dropped because there were more than [`MAX_HITS`].
+    #[must_use]
+    pub const fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn hits(&self) -> impl Iterator<Item = Hit> + '_ {
+        self.addresses
+            .iter()
+            .zip(self.values.chunks_exact(self.len))
+            .map(|(&address, value)| Hit {
+                address,
+                value: self.ty.decode(value),
+            })
+    }
+
+    /// Every hit as `<RVA> <type> <value>`, one per line, with RVAs relative
+    /// to `base`.
+    #[must_use]
+    pub fn export(&self, base: usize) -> String {
+        let mut text = String::new();
+
+        for hit in self.hits() {
+            let _ = writeln!(
+                text,
+                "{} {} {}",
+                format_rva(rva(hit.address, base)),
+                self.ty,
+                hit.value
+            );
+        }
+
+        text
+    }
+
+    /// Watch a hit, relative to `base`.
+    #[must_use]
+    pub fn watch(&self, hit: &Hit, base: usize) -> Watch {
+        Watch {
+            label: String::new(),
+            chain: PointerChain::new(rva(hit.address, base), []),
+            ty: self.ty,
+            len: self.len,
+        }
+    }
+}
+
+/// An address that's kept an eye on. These are saved, as [`PointerChain`]s
+/// relative to SE's base. That only lasts across restarts if the chain starts
+/// in SE's image, see [`Watch::survives_restart`]. Watching a hit on the heap
+/// is only good until SE restarts, unless a pointer to it is found.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Watch {
+    pub label: String,
+    pub chain: PointerChain,
+    pub ty: ValueType,
+    /// Size of the value, in bytes.
+    pub len: usize,
+}
+
+impl Watch {
+    #[must_use]
+    pub fn new(chain: PointerChain, ty: ValueType) -> Self {
+        Self {
+            label: String::new(),
+            chain,
+            ty,
+            len: ty.size().unwrap_or(WATCHED_CHARS * 2usize),
+        }
+    }
+
+    /// Whether this still points at the same thing after SE restarts, i.e.,
+    /// its chain starts inside SE's image, which is `image_size` bytes.
+    #[must_use]
+    pub fn survives_restart(&self, image_size: usize) -> bool {
+        usize::try_from(self.chain.rva).is_ok_and(|rva| rva < image_size)
+    }
+
+    /// Read it, with `chain` starting from `base`.
+    pub fn read(&self, memory: &dyn Memory, base: usize) -> Result<Value> {
+        let address = self.chain.resolve_with(base, |address| {
+            let mut link = [0u8; 8usize];
+
+            memory.read(address, &mut link)?;
+
+            Ok(usize::from_le_bytes(link))
+        })?;
+        let mut bytes = vec![0u8; self.len];
+
+        memory.read(address, &mut bytes)?;
+
+        Ok(self.ty.decode(&bytes))
+    }
+}
+
+/// The scanner tab. Only watches are saved.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Scanner {
+    #[serde(skip)]
+    ty: ValueType,
+    #[serde(skip)]
+    input: String,
+    #[serde(skip)]
+    filter: Filter,
+    #[serde(skip)]
+    scan: Option<Scan>,
+    #[serde(skip)]
+    running: Option<JoinHandle<Result<Scan>>>,
+    watches: Vec<Watch>,
+    /// Pointer chain to watch, typed in by hand.
+    #[serde(skip)]
+    chain_input: String,
+    #[serde(skip)]
+    chain_ty: ValueType,
+    #[serde(skip)]
+    error: Option<String>,
+}
+
+impl Scanner {
+    pub fn ui(&mut self, ui: &mut Ui) {
+        let base = base() as usize;
+
+        self.__poll(ui);
+
+        ui.label(
+            "Searches SE's writable memory for a value. Next scans narrow down the hits by how \
+             they've changed since the last scan.",
+        );
+        ui.separator();
+
+        let idle = self.running.is_none();
+
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(idle && self.scan.is_none(), |ui| {
+                ComboBox::from_id_source("scanner_type")
+                    .selected_text(self.ty.to_string())
+                    .show_ui(ui, |ui| {
+                        for ty in ValueType::ALL {
+                            ui.selectable_value(&mut self.ty, ty, ty.to_string());
+                        }
+                    });
+            });
+
+            ui.add(
+                TextEdit::singleline(&mut self.input)
+                    .hint_text("Value")
+                    .desired_width(160f32),
+            );
+
+            if ui.add_enabled(idle, Button::new("First scan")).clicked() {
+                self.__first_scan();
+            }
+
+            ui.add_enabled_ui(idle && self.scan.is_some(), |ui| {
+                ComboBox::from_id_source("scanner_filter")
+                    .selected_text(self.filter.to_string())
+                    .show_ui(ui, |ui| {
+                        for filter in Filter::ALL {
+                            ui.selectable_value(&mut self.filter, filter, filter.to_string());
+                        }
+                    });
+
+                if ui.button("Next scan").clicked() {
+                    self.__next_scan();
+                }
+
+                if ui.button("New scan").clicked() {
+                    self.scan = None;
+                    self.error = None;
+                }
+            });
+        });
+
+        if !idle {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Scanning...");
+            });
+        }
+
+        if let Some(e) = self.error.as_ref() {
+            ui.label(RichText::new(e).color(Color32::RED));
+        }
+
+        // Keep current values current
+        ui.ctx()
+            .request_repaint_after(Duration::from_millis(250u64));
+
+        if let Some(scan) = self.scan.as_ref() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} hits", scan.len()));
+
+                if scan.truncated() {
+                    ui.label(
+                        RichText::new(format!("(stopped at {MAX_HITS}, scan for something rarer)"))
+                            .color(Color32::YELLOW),
+                    );
+                }
+
+                if ui
+                    .button("Copy RVAs")
+                    .on_hover_text("Every hit, relative to SE's base address")
+                    .clicked()
+                {
+                    ui.output_mut(|output| output.copied_text = scan.export(base));
+                }
+            });
+
+            Grid::new("scanner_hits").striped(true).show(ui, |ui| {
+                ui.label("RVA");
+                ui.label("Last scan");
+                ui.label("Current");
+                ui.end_row();
+
+                for hit in scan.hits().take(SHOWN_HITS) {
+                    let watch = scan.watch(&hit, base);
+
+                    ui.monospace(format_rva(rva(hit.address, base)));
+                    ui.label(hit.value.to_string());
+                    ui.label(
+                        watch
+                            .read(&ProcessMemory, base)
+                            .map_or_else(|_| "???".to_owned(), |value| value.to_string()),
+                    );
+
+                    if ui.button("Watch").clicked() {
+                        self.watches.push(watch);
+                    }
+
+                    ui.end_row();
+                }
+            });
+
+            if scan.len() > SHOWN_HITS {
+                ui.label(format!("...and {} more", scan.len() - SHOWN_HITS));
+            }
+        }
+
+        ui.separator();
+        ui.heading("Watches");
+
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.chain_input)
+                    .hint_text("0x1046F08 -> 0x10")
+                    .desired_width(200f32),
+            )
+            .on_hover_text(
+                "RVA from SE's base address, then an offset for each pointer to follow, like \
+                 Cheat Engine's pointers",
+            );
+
+            ComboBox::from_id_source("scanner_chain_type")
+                .selected_text(self.chain_ty.to_string())
+                .show_ui(ui, |ui| {
+                    for ty in ValueType::ALL {
+                        ui.selectable_value(&mut self.chain_ty, ty, ty.to_string());
+                    }
+                });
+
+            if ui.button("Watch").clicked() {
+                match self.chain_input.parse() {
+                    Ok(chain) => {
+                        self.watches.push(Watch::new(chain, self.chain_ty));
+                        self.chain_input.clear();
+                        self.error = None;
+                    },
+                    Err(e) => self.error = Some(format!("Invalid pointer chain: {e}")),
+                }
+            }
+        });
+
+        if self.watches.is_empty() {
+            return;
+        }
+
+        let mut remove = None;
+
+        Grid::new("scanner_watches").striped(true).show(ui, |ui| {
+            for (i, watch) in self.watches.iter_mut().enumerate() {
+                ui.add(
+                    TextEdit::singleline(&mut watch.label)
+                        .hint_text("Label")
+                        .desired_width(120f32),
+                );
+                ui.monospace(watch.chain.to_string());
+                ui.label(watch.ty.to_string());
+                ui.label(
+                    watch
+                        .read(&ProcessMemory, base)
+                        .map_or_else(|_| "???".to_owned(), |value| value.to_string()),
+                );
+
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+
+                if !watch.survives_restart(image_size()) {
+                    ui.label(RichText::new("⚠").color(Color32::YELLOW))
+                        .on_hover_text(
+                            "This isn't in SE's image, so it's probably on the heap. It'll point \
+                             somewhere else after SE restarts, unless it's changed to a pointer \
+                             chain that starts in SE's image.",
+                        );
+                }
+
+                ui.end_row();
+            }
+        });
+
+        if let Some(i) = remove {
+            self.watches.remove(i);
+        }
+    }
+
+    fn __first_scan(&mut self) {
+        self.error = None;
+
+        match Value::parse(self.ty, &self.input) {
+            Ok(value) => {
+                info!("Scanning for {} {value}", value.ty());
+
+                self.running = Some(thread::spawn(move || Scan::first(&ProcessMemory, &value)));
+            },
+            Err(e) => self.error = Some(format!("Invalid value: {e}")),
+        }
+    }
+
+    fn __next_scan(&mut self) {
+        self.error = None;
+
+        let Some(scan) = self.scan.clone()
+        else {
+            return;
+        };
+
+        let value = match self.filter {
+            Filter::Exact => match Value::parse(scan.ty(), &self.input) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    self.error = Some(format!("Invalid value: {e}"));
+
+                    return;
+                },
+            },
+            _ => None,
+        };
+        let filter = self.filter;
+
+        self.running = Some(thread::spawn(move || {
+            scan.next(&ProcessMemory, filter, value.as_ref())
+        }));
+    }
+
+    /// Pick up the scan in progress, if it's done.
+    fn __poll(&mut self, ui: &Ui) {
+        if !self.running.as_ref().is_some_and(JoinHandle::is_finished) {
+            if self.running.is_some() {
+                ui.ctx().request_repaint();
+            }
+
+            return;
+        }
+
+        match self.running.take().expect("Unreachable").join() {
+            Ok(Ok(scan)) => {
+                info!("Scan found {} hits", scan.len());
+
+                self.scan = Some(scan);
+            },
+            Ok(Err(e)) => self.error = Some(format!("Scan failed: {e}")),
+            Err(_) => self.error = Some("Scan panicked".to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eyre::eyre;
+    use std::cell::RefCell;
+
+    /// Regions of made up memory, at made up addresses.
+    struct Synthetic(RefCell<Vec<(usize, Vec<u8>)>>);
+
+    impl Synthetic {
+        fn new(regions: &[(usize, usize)]) -> Self {
+            Self(RefCell::new(
+                regions
+                    .iter()
+                    .map(|&(base, len)| (base, vec![0u8; len]))
+                    .collect(),
+            ))
+        }
+
+        fn write(&self, address: usize, bytes: &[u8]) {
+            for &mut (base, ref mut memory) in self.0.borrow_mut().iter_mut() {
+                if (base..base + memory.len()).contains(&address) {
+                    memory[address - base..address - base + bytes.len()].copy_from_slice(bytes);
+                }
+            }
+        }
+
+        fn free(&self, address: usize) {
+            self.0.borrow_mut().retain(|region| region.0 != address);
+        }
+    }
+
+    impl Memory for Synthetic {
+        fn regions(&self) -> Result<Vec<Region>> {
+            Ok(self
+                .0
+                .borrow()
+                .iter()
+                .map(|&(base, ref memory)| Region {
+                    base,
+                    len: memory.len(),
+                })
+                .collect())
+        }
+
+        fn read(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+            for &(base, ref memory) in self.0.borrow().iter() {
+                if let Some(bytes) = address
+                    .checked_sub(base)
+                    .and_then(|offset| memory.get(offset..offset + buf.len()))
+                {
+                    buf.copy_from_slice(bytes);
+
+                    return Ok(());
+                }
+            }
+
+            bail!("Unmapped");
+        }
+    }
+
+    /// starb's own memory, only the region with `address` in it. That's
+    /// looked up when scanning, so it has anything allocated near it by then.
+    struct Own(usize);
+
+    impl Memory for Own {
+        fn regions(&self) -> Result<Vec<Region>> {
+            let region = region::query(self.0 as *const u8)?;
+
+            Ok(vec![Region {
+                base: region.as_range().start,
+                len: region.len(),
+            }])
+        }
+
+        fn read(&self, address: usize, buf: &mut [u8]) -> Result<()> {
+            // SAFETY: Only ever in the region, which this thread's still using
+            unsafe { ptr::copy_nonoverlapping(address as *const u8, buf.as_mut_ptr(), buf.len()) };
+
+            Ok(())
+        }
+    }
+
+    fn __addresses(scan: &Scan) -> Vec<usize> {
+        scan.hits().map(|hit| hit.address).collect()
+    }
+
+    fn __first(scan: &Scan) -> Result<Hit> {
+        scan.hits().next().ok_or_else(|| eyre!("No hits"))
+    }
+
+    #[test]
+    fn first_scan() -> Result<()> {
+        let memory = Synthetic::new(&[(0x10000usize, 0x1000usize), (0x20000usize, 0x1000usize)]);
+
+        memory.write(0x10010usize, &1234i32.to_le_bytes());
+        memory.write(0x20FFCusize, &1234i32.to_le_bytes());
+        // Unaligned, so not found
+        memory.write(0x10101usize, &1234i32.to_le_bytes());
+
+        let scan = Scan::first(&memory, &Value::Int(1234i32))?;
+
+        assert_eq!(__addresses(&scan), [0x10010usize, 0x20FFCusize]);
+        assert_eq!(__first(&scan)?.value, Value::Int(1234i32));
+        assert!(!scan.truncated());
+
+        Ok(())
+    }
+
+    #[test]
+    fn across_chunks() -> Result<()> {
+        let memory = Synthetic::new(&[(0x100000usize, CHUNK_SIZE * 2usize)]);
+        let address = 0x100000usize + CHUNK_SIZE - 4usize;
+
+        memory.write(address, &100.0f64.to_le_bytes());
+
+        let scan = Scan::first(&memory, &Value::Double(100.0f64))?;
+
+        assert_eq!(__addresses(&scan), [address]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn strings() -> Result<()> {
+        let memory = Synthetic::new(&[(0x10000usize, 0x100usize)]);
+        let bytes = Value::Utf16("Sol".to_owned()).to_bytes();
+
+        memory.write(0x10022usize, &bytes);
+
+        let scan = Scan::first(&memory, &Value::Utf16("Sol".to_owned()))?;
+
+        assert_eq!(__addresses(&scan), [0x10022usize]);
+        assert!(scan.next(&memory, Filter::Increased, None).is_err());
+        assert!(scan
+            .next(
+                &memory,
+                Filter::Exact,
+                Some(&Value::Utf16("Sun".to_owned()))
+            )?
+            .is_empty());
+        assert!(scan
+            .next(
+                &memory,
+                Filter::Exact,
+                Some(&Value::Utf16("Earth".to_owned()))
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn next_scans() -> Result<()> {
+        let memory = Synthetic::new(&[(0x10000usize, 0x100usize), (0x20000usize, 0x100usize)]);
+
+        for address in [0x10000usize, 0x10004usize, 0x10008usize, 0x20000usize] {
+            memory.write(address, &10.0f32.to_le_bytes());
+        }
+
+        let scan = Scan::first(&memory, &Value::Float(10.0f32))?;
+
+        assert_eq!(scan.len(), 4usize);
+
+        memory.write(0x10004usize, &11.0f32.to_le_bytes());
+        memory.write(0x10008usize, &9.0f32.to_le_bytes());
+        memory.free(0x20000usize);
+
+        let unchanged = scan.next(&memory, Filter::Unchanged, None)?;
+        let changed = scan.next(&memory, Filter::Changed, None)?;
+        let increased = scan.next(&memory, Filter::Increased, None)?;
+        let decreased = scan.next(&memory, Filter::Decreased, None)?;
+        let exact = scan.next(&memory, Filter::Exact, Some(&Value::Float(9.0f32)))?;
+
+        // Freed memory is dropped by every filter
+        assert_eq!(__addresses(&unchanged), [0x10000usize]);
+        assert_eq!(__addresses(&changed), [0x10004usize, 0x10008usize]);
+        assert_eq!(__addresses(&increased), [0x10004usize]);
+        assert_eq!(__addresses(&decreased), [0x10008usize]);
+        assert_eq!(__addresses(&exact), [0x10008usize]);
+
+        // Hits remember the values they were last seen with
+        assert_eq!(changed.hits().map(|hit| hit.value).collect::<Vec<_>>(), [
+            Value::Float(11.0f32),
+            Value::Float(9.0f32)
+        ]);
+        assert!(scan
+            .next(&memory, Filter::Exact, Some(&Value::Int(9i32)))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn own_memory() -> Result<()> {
+        // Small, so the needle's likely allocated right next to it
+        for value in [Value::Double(1234.5678f64), Value::Int(0x5CA4_5CA4i32)] {
+            let boxed = value.to_bytes().into_boxed_slice();
+            let address = boxed.as_ptr() as usize;
+
+            let scan = Scan::first(&Own(address), &value)?;
+
+            assert_eq!(__addresses(&scan), [address], "{value}");
+            assert_eq!(__first(&scan)?.value, value);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncated() -> Result<()> {
+        let memory = Synthetic::new(&[(0x10000usize, (MAX_HITS + 10usize) * 4usize)]);
+
+        let scan = Scan::first(&memory, &Value::Int(0i32))?;
+
+        assert_eq!(scan.len(), MAX_HITS);
+        assert!(scan.truncated());
+
+        Ok(())
+    }
+
+    #[test]
+    fn watches_and_exports() -> Result<()> {
+        let memory = Synthetic::new(&[(0x10000usize, 0x100usize)]);
+
+        memory.write(0x10010usize, &5i32.to_le_bytes());
+
+        let scan = Scan::first(&memory, &Value::Int(5i32))?;
+        let watch = scan.watch(&__first(&scan)?, 0x8000usize);
+
+        memory.write(0x10010usize, &6i32.to_le_bytes());
+
+        assert_eq!(watch.read(&memory, 0x8000usize)?, Value::Int(6i32));
+        assert_eq!(watch.chain.to_string(), "0x8010");
+
+        // Behind a pointer at 0x10020
+        memory.write(0x10020usize, &0x10008usize.to_le_bytes());
+
+        let watch = Watch::new("0x8020 -> 0x8".parse()?, ValueType::Int);
+
+        assert_eq!(watch.read(&memory, 0x8000usize)?, Value::Int(6i32));
+        assert!(Watch::new("0x8030 -> 0x8".parse()?, ValueType::Int)
+            .read(&memory, 0x8000usize)
+            .is_err());
+        assert_eq!(scan.export(0x8000usize), "0x8010 Int 5\n");
+
+        // Only the chain's start matters
+        assert!(watch.survives_restart(0x10000usize));
+        assert!(!watch.survives_restart(0x10usize));
+        assert!(!scan
+            .watch(&__first(&scan)?, 0x20000usize)
+            .survives_restart(0x10000usize));
+        assert_eq!(scan.export(0x20000usize), "-0xFFF0 Int 5\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn parsing() -> Result<()> {
+        assert_eq!(
+            Value::parse(ValueType::Double, " 100 ")?,
+            Value::Double(100.0f64)
+        );
+        assert!(Value::parse(ValueType::Int, "1.5").is_err());
+        assert!(Value::parse(ValueType::Utf16, "").is_err());
+        assert!(Value::Float(0.1f32).matches(&(0.1f64 as f32).to_le_bytes()));
+
+        Ok(())
+    }
+}
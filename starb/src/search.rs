@@ -0,0 +1,646 @@
+//! Star browser search progress, and how long past searches took.
+//!
+//! Where SE keeps its search counters isn't known yet, not even for the
+//! supported build. So, they're read from pointer chains the user sets (found
+//! with the scanner, for example), and nothing's shown until they do. A search
+//! is assumed to have started once systems checked (or found, if that's all
+//! there is) changes, and to have finished once it hasn't changed for [`IDLE`],
+//! or went back down since a new search started.
+
+use crate::app::PLUGINS;
+use crate::events;
+use crate::events::Event;
+use crate::scanner::Memory;
+use crate::scanner::ProcessMemory;
+use crate::scanner::Value as ScannerValue;
+use crate::scanner::ValueType;
+use crate::scanner::Watch;
+use crate::utils::base;
+use crate::utils::PointerChain;
+use eframe::Storage;
+use egui::CollapsingHeader;
+use egui::Color32;
+use egui::Grid;
+use egui::TextEdit;
+use egui::Ui;
+use eyre::Result;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::error;
+use tracing::info;
+
+const SEARCH_KEY: &str = "starb_search";
+const POLL_INTERVAL: Duration = Duration::from_millis(100u64);
+/// How long the counters have to stay the same for a search to be over.
+const IDLE: Duration = Duration::from_secs(1u64);
+/// How many past searches are kept.
+const MAX_HISTORY: usize = 50usize;
+
+static MONITOR: Mutex<Monitor> = Mutex::new(Monitor::new());
+
+/// Where SE's search counters are. Any of these can be missing.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Sources {
+    /// Systems checked so far.
+    pub checked: Option<PointerChain>,
+    /// Systems found so far.
+    pub found: Option<PointerChain>,
+    /// Systems to check in total, for the ETA.
+    pub total: Option<PointerChain>,
+}
+
+impl Sources {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            checked: None,
+            found: None,
+            total: None,
+        }
+    }
+}
+
+/// The counters at some point. `None` if there's nowhere to read it from, or
+/// it couldn't be read.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Sample {
+    pub checked: Option<u64>,
+    pub found: Option<u64>,
+    pub total: Option<u64>,
+}
+
+impl Sample {
+    /// Whatever tells whether the search is still going.
+    fn progress(self) -> Option<u64> {
+        self.checked.or(self.found)
+    }
+}
+
+/// A search that's running.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    started: Instant,
+    /// When the counters last changed.
+    changed: Instant,
+    /// [`Sample::progress`] when this started.
+    start: u64,
+    sample: Sample,
+    /// Every enabled plugin's settings when this started.
+    settings: Map<String, Value>,
+}
+
+impl Progress {
+    #[must_use]
+    pub const fn sample(&self) -> Sample {
+        self.sample
+    }
+
+    #[must_use]
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.started)
+    }
+
+    /// Systems checked (or found) per second.
+    #[must_use]
+    pub fn rate(&self, now: Instant) -> Option<f64> {
+        let elapsed = self.elapsed(now).as_secs_f64();
+        let done = self.sample.progress()?.saturating_sub(self.start);
+
+        (elapsed > 0.0f64 && done > 0u64).then(|| done as f64 / elapsed)
+    }
+
+    /// How much longer it'll take, at the current rate. Only known if there's
+    /// a total.
+    #[must_use]
+    pub fn eta(&self, now: Instant) -> Option<Duration> {
+        let remaining = self.sample.total?.saturating_sub(self.sample.checked?);
+
+        Some(Duration::from_secs_f64(remaining as f64 / self.rate(now)?))
+    }
+}
+
+/// A search that finished.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Record {
+    /// How long it took, from its first change to its last.
+    pub seconds: f64,
+    pub checked: Option<u64>,
+    pub found: Option<u64>,
+    /// Every enabled plugin's settings when it started, for comparing them.
+    pub settings: Map<String, Value>,
+}
+
+/// Keeps track of searches, from samples of the counters.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct Monitor {
+    /// Where the counters are read from, as set by the user.
+    pub sources: Sources,
+    history: VecDeque<Record>,
+    #[serde(skip)]
+    current: Option<Progress>,
+    #[serde(skip)]
+    last: Option<u64>,
+}
+
+impl Monitor {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            sources: Sources::new(),
+            history: VecDeque::new(),
+            current: None,
+            last: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn current(&self) -> Option<&Progress> {
+        self.current.as_ref()
+    }
+
+    /// Past searches, oldest first.
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &Record> {
+        self.history.iter()
+    }
+
+    /// Read the counters from [`Monitor::sources`], relative to `base`.
+    #[must_use]
+    pub fn read(&self, memory: &dyn Memory, base: usize) -> Sample {
+        let read = |chain: &Option<PointerChain>| {
+            let watch = Watch::new(chain.clone()?, ValueType::Int);
+
+            match watch.read(memory, base).ok()? {
+                ScannerValue::Int(value) => u64::try_from(value).ok(),
+                _ => None,
+            }
+        };
+
+        let sources = &self.sources;
+
+        Sample {
+            checked: read(&sources.checked),
+            found: read(&sources.found),
+            total: read(&sources.total),
+        }
+    }
+
+    /// Update from the counters at `now`. Returns what happened, in order.
+    pub fn sample(&mut self, sample: Sample, now: Instant) -> Vec<Event> {
+        let mut events = vec![];
+        let progress = sample.progress();
+        let last = self.last;
+
+        self.last = progress;
+
+        // Went back down, so a new search started before it looked like this one was
+        // over
+        if let (Some(current), Some(progress)) = (self.current.as_ref(), progress) {
+            if progress < current.sample.progress().unwrap_or(0u64) {
+                events.extend(self.__finish());
+            }
+        }
+
+        match (self.current.as_mut(), progress) {
+            (Some(current), Some(progress)) if Some(progress) != current.sample.progress() => {
+                current.sample = sample;
+                current.changed = now;
+            },
+            (Some(current), _) => {
+                // Found and the total can still change without progress
+                current.sample.found = sample.found.or(current.sample.found);
+                current.sample.total = sample.total.or(current.sample.total);
+
+                if now.saturating_duration_since(current.changed) >= IDLE {
+                    events.extend(self.__finish());
+                }
+            },
+            (None, Some(progress)) => {
+                // Only counting up, since it might be reset when a search is over too
+                if let Some(start) = last.filter(|&last| progress > last) {
+                    self.current = Some(Progress {
+                        started: now,
+                        changed: now,
+                        start,
+                        sample,
+                        settings: Map::new(),
+                    });
+
+                    events.push(Event::SearchStarted);
+                }
+            },
+            (None, None) => {},
+        }
+
+        events
+    }
+
+    /// Remember what the settings were for the current search.
+    pub fn set_settings(&mut self, settings: Map<String, Value>) {
+        if let Some(current) = self.current.as_mut() {
+            current.settings = settings;
+        }
+    }
+
+    fn __finish(&mut self) -> Option<Event> {
+        let current = self.current.take()?;
+        let record = Record {
+            seconds: current
+                .changed
+                .saturating_duration_since(current.started)
+                .as_secs_f64(),
+            checked: current.sample.checked,
+            found: current.sample.found,
+            settings: current.settings,
+        };
+
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(record.clone());
+
+        Some(Event::SearchFinished {
+            seconds: record.seconds,
+            checked: record.checked,
+            found: record.found,
+        })
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Search section of the context tab.
+#[derive(Default)]
+pub struct SearchPanel {
+    /// Pointer chains being typed in, as checked, found and total.
+    inputs: Option<[String; 3usize]>,
+    error: Option<String>,
+}
+
+impl SearchPanel {
+    pub fn ui(&mut self, ui: &mut Ui) {
+        let mut monitor = MONITOR.lock();
+        let now = Instant::now();
+
+        ui.heading("Search");
+
+        match monitor.current() {
+            Some(current) => {
+                let sample = current.sample();
+
+                ui.label(format!(
+                    "Searching: {} checked of {}, {} found",
+                    __count(sample.checked),
+                    __count(sample.total),
+                    __count(sample.found),
+                ));
+                ui.label(format!(
+                    "Elapsed: {}, {}/s, ETA: {}",
+                    __duration(current.elapsed(now)),
+                    current
+                        .rate(now)
+                        .map_or_else(|| "?".to_owned(), |rate| format!("{rate:.0}")),
+                    current.eta(now).map_or_else(|| "?".to_owned(), __duration),
+                ));
+
+                // Nothing else repaints while it's counting up
+                ui.ctx()
+                    .request_repaint_after(Duration::from_millis(250u64));
+            },
+            None if monitor.sources == Sources::new() => {
+                ui.label(
+                    "Where SE keeps its search progress isn't known yet, so nothing's shown until \
+                     it's set below.",
+                );
+            },
+            None => {
+                ui.label("Not searching");
+            },
+        }
+
+        CollapsingHeader::new("History").show(ui, |ui| {
+            Grid::new("search_history").striped(true).show(ui, |ui| {
+                ui.strong("Took");
+                ui.strong("Checked");
+                ui.strong("Found");
+                ui.end_row();
+
+                for record in monitor.history().rev() {
+                    ui.label(__duration(Duration::from_secs_f64(record.seconds)))
+                        .on_hover_text(__settings_text(&record.settings));
+                    ui.label(__count(record.checked));
+                    ui.label(__count(record.found));
+                    ui.end_row();
+                }
+            });
+        });
+
+        CollapsingHeader::new("Where to read it from").show(ui, |ui| {
+            let sources = &mut monitor.sources;
+            let inputs = self.inputs.get_or_insert_with(|| {
+                [&sources.checked, &sources.found, &sources.total].map(|chain| {
+                    chain
+                        .as_ref()
+                        .map_or_else(String::new, PointerChain::to_string)
+                })
+            });
+
+            Grid::new("search_sources").show(ui, |ui| {
+                for (label, input) in ["Systems checked", "Systems found", "Systems to check"]
+                    .into_iter()
+                    .zip(inputs.iter_mut())
+                {
+                    ui.label(label);
+                    ui.add(
+                        TextEdit::singleline(input)
+                            .hint_text("0x1046F08 -> 0x10")
+                            .desired_width(200f32),
+                    )
+                    .on_hover_text("Pointer chain to a 32-bit integer. Leave it empty if unknown");
+                    ui.end_row();
+                }
+            });
+
+            if ui.button("Apply").clicked() {
+                match __parse_sources(inputs) {
+                    Ok(parsed) => {
+                        info!("Reading search progress from {parsed:?}");
+
+                        *sources = parsed;
+                        self.error = None;
+                    },
+                    Err(e) => self.error = Some(format!("Invalid pointer chain: {e}")),
+                }
+            }
+
+            if let Some(e) = self.error.as_ref() {
+                ui.colored_label(Color32::RED, e);
+            }
+        });
+    }
+}
+
+/// Past searches, oldest first.
+#[must_use]
+pub fn history() -> Vec<Record> {
+    MONITOR.lock().history().cloned().collect()
+}
+
+/// Load where to read the counters from, and past searches, from `storage`.
+pub fn load(storage: Option<&dyn Storage>) {
+    let Some(monitor) =
+        storage.and_then(|storage| eframe::get_value::<String>(storage, SEARCH_KEY))
+    else {
+        return;
+    };
+
+    match serde_json::from_str::<Monitor>(&monitor) {
+        Ok(monitor) => {
+            let mut current = MONITOR.lock();
+
+            current.sources = monitor.sources;
+            current.history = monitor.history;
+        },
+        Err(e) => error!("Search history in settings is invalid: {e}"),
+    }
+}
+
+pub fn save(storage: &mut dyn Storage) {
+    match serde_json::to_string(&*MONITOR.lock()) {
+        Ok(monitor) => eframe::set_value(storage, SEARCH_KEY, &monitor),
+        Err(e) => error!("Failed to save search history: {e}"),
+    }
+}
+
+/// Start keeping track of searches on their own thread.
+pub fn spawn() -> Result<()> {
+    thread::Builder::new()
+        .name("starb-search".to_owned())
+        .spawn(|| loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let happened = {
+                let mut monitor = MONITOR.lock();
+                let sample = monitor.read(&ProcessMemory, base() as usize);
+
+                monitor.sample(sample, Instant::now())
+            };
+
+            for event in happened {
+                // Not while `MONITOR` is locked, the GUI locks it after `PLUGINS`
+                if event == Event::SearchStarted {
+                    let settings = __settings();
+
+                    MONITOR.lock().set_settings(settings);
+                }
+
+                events::publish(&event);
+            }
+        })?;
+
+    Ok(())
+}
+
+fn __settings() -> Map<String, Value> {
+    let Some(plugins) = PLUGINS.get()
+    else {
+        return Map::new();
+    };
+
+    plugins
+        .lock()
+        .iter()
+        .filter(|plugin| plugin.1)
+        .map(|plugin| (plugin.0.key(), plugin.0.settings()))
+        .collect()
+}
+
+fn __settings_text(settings: &Map<String, Value>) -> String {
+    if settings.is_empty() {
+        return "Settings weren't recorded".to_owned();
+    }
+
+    settings
+        .iter()
+        .map(|(key, settings)| format!("{key}: {settings}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn __parse_sources(inputs: &[String; 3usize]) -> Result<Sources> {
+    let [checked, found, total] = inputs.clone().map(|input| {
+        (!input.trim().is_empty())
+            .then(|| input.parse())
+            .transpose()
+    });
+
+    Ok(Sources {
+        checked: checked?,
+        found: found?,
+        total: total?,
+    })
+}
+
+fn __count(count: Option<u64>) -> String {
+    count.map_or_else(|| "?".to_owned(), |count| count.to_string())
+}
+
+/// Like `1h 02m 03s`, or `4.5s` for short ones.
+fn __duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    match secs {
+        0u64..=59u64 => format!("{:.1}s", duration.as_secs_f64()),
+        60u64..=3599u64 => format!("{}m {:02}s", secs / 60u64, secs % 60u64),
+        _ => format!(
+            "{}h {:02}m {:02}s",
+            secs / 3600u64,
+            secs / 60u64 % 60u64,
+            secs % 60u64
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eyre::eyre;
+
+    fn __sample(checked: u64, total: u64) -> Sample {
+        Sample {
+            checked: Some(checked),
+            found: Some(checked / 10u64),
+            total: Some(total),
+        }
+    }
+
+    #[test]
+    fn searches() -> Result<()> {
+        let mut monitor = Monitor::new();
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+
+        // Nothing's changed yet
+        assert!(monitor.sample(__sample(0u64, 0u64), at(0u64)).is_empty());
+        assert!(monitor.sample(__sample(0u64, 0u64), at(100u64)).is_empty());
+        assert_eq!(monitor.sample(__sample(100u64, 1000u64), at(200u64)), [
+            Event::SearchStarted
+        ]);
+
+        monitor.sample(__sample(300u64, 1000u64), at(1200u64));
+
+        let current = monitor
+            .current()
+            .ok_or_else(|| eyre!("Should be searching"))?;
+
+        // 300 checked in a second, 700 to go
+        assert_eq!(current.rate(at(1200u64)), Some(300.0f64));
+        assert_eq!(
+            current.eta(at(1200u64)).map(|eta| eta.as_secs()),
+            Some(2u64)
+        );
+
+        // Still searching until it's been the same for a second
+        assert!(monitor
+            .sample(__sample(300u64, 1000u64), at(2100u64))
+            .is_empty());
+        assert_eq!(monitor.sample(__sample(300u64, 1000u64), at(2200u64)), [
+            Event::SearchFinished {
+                seconds: 1.0f64,
+                checked: Some(300u64),
+                found: Some(30u64),
+            }
+        ]);
+        assert!(monitor.current().is_none());
+        assert_eq!(monitor.history().count(), 1usize);
+
+        Ok(())
+    }
+
+    #[test]
+    fn restarted() -> Result<()> {
+        let mut monitor = Monitor::new();
+        let start = Instant::now();
+
+        monitor.sample(__sample(0u64, 1000u64), start);
+        monitor.sample(
+            __sample(500u64, 1000u64),
+            start + Duration::from_millis(100u64),
+        );
+
+        // Went back down before it finished
+        assert_eq!(
+            monitor.sample(
+                __sample(10u64, 1000u64),
+                start + Duration::from_millis(200u64)
+            ),
+            [Event::SearchFinished {
+                seconds: 0.0f64,
+                checked: Some(500u64),
+                found: Some(50u64),
+            }]
+        );
+        assert_eq!(
+            monitor.sample(
+                __sample(20u64, 1000u64),
+                start + Duration::from_millis(300u64)
+            ),
+            [Event::SearchStarted]
+        );
+        assert_eq!(
+            monitor
+                .current()
+                .ok_or_else(|| eyre!("Should be searching"))?
+                .start,
+            10u64
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn history_is_capped() -> Result<()> {
+        let mut monitor = Monitor::new();
+        let mut now = Instant::now();
+
+        monitor.sample(__sample(0u64, 0u64), now);
+
+        for checked in 1u64..=MAX_HISTORY as u64 + 1u64 {
+            monitor.sample(__sample(checked, 0u64), now);
+            now += IDLE;
+            monitor.sample(__sample(checked, 0u64), now);
+        }
+
+        assert_eq!(monitor.history().count(), MAX_HISTORY);
+        assert_eq!(
+            monitor
+                .history()
+                .next()
+                .ok_or_else(|| eyre!("Should have a history"))?
+                .checked,
+            Some(2u64)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(__duration(Duration::from_millis(4500u64)), "4.5s");
+        assert_eq!(__duration(Duration::from_secs(65u64)), "1m 05s");
+        assert_eq!(__duration(Duration::from_secs(3723u64)), "1h 02m 03s");
+    }
+}
@@ -0,0 +1,266 @@
+//! Self-test. Reads back every patch site and hook of every plugin, and
+//! compares it with what should be there, so users (and bug reports) can tell
+//! whether a plugin is actually doing anything.
+
+use crate::app::se_version;
+use crate::app::Plugins;
+use crate::app::PLUGINS;
+use egui::Color32;
+use egui::RichText;
+use egui::Ui;
+use serde_json::json;
+use serde_json::Value;
+use std::fmt;
+use std::fmt::Write as _;
+use tracing::info;
+use tracing::warn;
+
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    Ok,
+    /// Something else is there.
+    Mismatch(Vec<u8>),
+    /// Couldn't even read it.
+    Failed(String),
+}
+
+/// A single patch site or hook.
+#[derive(Clone, Debug)]
+pub struct Check {
+    /// The patch or hook, as it's displayed.
+    pub site: String,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.outcome {
+            Outcome::Ok => write!(f, "OK: {}", self.site),
+            Outcome::Mismatch(ref found) => {
+                write!(f, "MISMATCH: {}, found {found:02X?}", self.site)
+            },
+            Outcome::Failed(ref e) => write!(f, "FAILED: {}: {e}", self.site),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PluginReport {
+    pub key: String,
+    pub name: String,
+    pub checks: Vec<Check>,
+}
+
+impl PluginReport {
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| matches!(check.outcome, Outcome::Ok))
+    }
+
+    /// Show this plugin's results, for the context tab.
+    pub fn ui(&self, ui: &mut Ui) {
+        for check in &self.checks {
+            let color = match check.outcome {
+                Outcome::Ok => Color32::GREEN,
+                _ => Color32::RED,
+            };
+
+            ui.label(RichText::new(check.to_string()).color(color));
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub plugins: Vec<PluginReport>,
+}
+
+impl Report {
+    #[must_use]
+    pub fn plugin(&self, key: &str) -> Option<&PluginReport> {
+        self.plugins.iter().find(|plugin| plugin.key == key)
+    }
+
+    /// (passed, total) checks.
+    #[must_use]
+    pub fn count(&self) -> (usize, usize) {
+        let checks = self.plugins.iter().flat_map(|plugin| &plugin.checks);
+
+        (
+            checks
+                .clone()
+                .filter(|check| matches!(check.outcome, Outcome::Ok))
+                .count(),
+            checks.count(),
+        )
+    }
+
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.plugins.iter().all(PluginReport::passed)
+    }
+
+    /// Same as the control API's `selftest.run`.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        json!({
+            "passed": self.passed(),
+            "plugins": self
+                .plugins
+                .iter()
+                .map(|plugin| {
+                    json!({
+                        "key": plugin.key,
+                        "passed": plugin.passed(),
+                        "checks": plugin.checks.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "diagnostics": self.to_string(),
+        })
+    }
+}
+
+/// Diagnostics, for copying into bug reports.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (passed, total) = self.count();
+
+        writeln!(f, "starb v{}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(
+            f,
+            "SE version: {}",
+            se_version().map_or_else(|| "Not checked yet".to_owned(), ToString::to_string)
+        )?;
+        writeln!(f, "Self-test: {passed}/{total} OK")?;
+
+        for plugin in &self.plugins {
+            writeln!(f, "{} ({}):", plugin.name, plugin.key)?;
+
+            for check in &plugin.checks {
+                writeln!(f, "    {check}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check every plugin.
+#[must_use]
+pub fn run() -> Report {
+    run_on(&PLUGINS.get().expect("Unreachable").lock())
+}
+
+/// Same as [`run`], for when the plugins are already locked.
+#[must_use]
+pub fn run_on(plugins: &Plugins) -> Report {
+    let report = Report {
+        plugins: plugins
+            .iter()
+            .map(|pair| {
+                let plugin = &pair.0;
+                let patches = plugin.patches().into_iter().map(|patch| Check {
+                    site: patch.to_string(),
+                    outcome: match patch.read() {
+                        Ok(found) if found == patch.expected() => Outcome::Ok,
+                        Ok(found) => Outcome::Mismatch(found),
+                        Err(e) => Outcome::Failed(e.to_string()),
+                    },
+                });
+                let hooks = plugin.hooks().into_iter().map(|hook| Check {
+                    site: hook.to_string(),
+                    outcome: match (hook.verify(), hook.read()) {
+                        (Ok(true), _) => Outcome::Ok,
+                        (Ok(false), Ok(found)) => Outcome::Mismatch(found),
+                        (Err(e), _) | (_, Err(e)) => Outcome::Failed(e.to_string()),
+                    },
+                });
+
+                PluginReport {
+                    key: plugin.key(),
+                    name: plugin.name(),
+                    checks: patches.chain(hooks).collect(),
+                }
+            })
+            .collect(),
+    };
+
+    let (passed, total) = report.count();
+
+    if report.passed() {
+        info!("Self-test: {passed}/{total} OK");
+    }
+    else {
+        let mut failed = String::new();
+
+        for check in report
+            .plugins
+            .iter()
+            .flat_map(|plugin| &plugin.checks)
+            .filter(|check| !matches!(check.outcome, Outcome::Ok))
+        {
+            let _ = write!(failed, "\n    {check}");
+        }
+
+        warn!("Self-test: {passed}/{total} OK{failed}");
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eyre::eyre;
+    use eyre::Result;
+
+    #[test]
+    fn report() -> Result<()> {
+        let report = Report {
+            plugins: vec![
+                PluginReport {
+                    key: "a".to_owned(),
+                    name: "A".to_owned(),
+                    checks: vec![Check {
+                        site: "Jump at 0x10: patched ([EB])".to_owned(),
+                        outcome: Outcome::Ok,
+                    }],
+                },
+                PluginReport {
+                    key: "b".to_owned(),
+                    name: "B".to_owned(),
+                    checks: vec![
+                        Check {
+                            site: "Jump at 0x20: vanilla ([76])".to_owned(),
+                            outcome: Outcome::Mismatch(vec![0xEBu8]),
+                        },
+                        Check {
+                            site: "Thing hook at 0x30: enabled".to_owned(),
+                            outcome: Outcome::Failed("Access denied".to_owned()),
+                        },
+                    ],
+                },
+            ],
+        };
+
+        assert_eq!(report.count(), (1usize, 3usize));
+        assert!(!report.passed());
+        assert!(report.plugin("a").ok_or_else(|| eyre!("No `a`"))?.passed());
+        assert!(!report.plugin("b").ok_or_else(|| eyre!("No `b`"))?.passed());
+        assert!(report.plugin("c").is_none());
+
+        let diagnostics = report.to_string();
+
+        assert!(diagnostics.contains("Self-test: 1/3 OK\n"));
+        assert!(diagnostics.contains(
+            "B (b):\n    MISMATCH: Jump at 0x20: vanilla ([76]), found [EB]\n    FAILED: Thing \
+             hook at 0x30: enabled: Access denied\n"
+        ));
+        assert_eq!(report.to_json()["plugins"][1usize]["passed"], false);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,371 @@
+//! Plugin settings that can't always be used as soon as they're changed. A
+//! [`Setting`] keeps track of what the user asked for, what's actually in
+//! effect, and its default, so plugins don't have to.
+
+use crate::app::request_restart;
+use crate::app::se_started;
+use crate::app::withdraw_restart;
+use egui::Color32;
+use egui::Response;
+use egui::RichText;
+use egui::Ui;
+use eyre::Result;
+use std::fmt;
+
+/// When a [`Setting`]'s requested value is applied.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ApplyPolicy {
+    /// As soon as it's changed.
+    Live,
+    /// Only before SE's main window opens. Changing it afterwards requests a
+    /// restart, unless it's within [`Setting::set_live_max`].
+    NextRestart,
+    /// As soon as it's changed, but it's only in effect once
+    /// [`Setting::verify`] says so.
+    Verified,
+}
+
+#[derive(Clone, Debug)]
+pub struct Setting<T> {
+    /// What this is called in the GUI and restart requests.
+    label: &'static str,
+    default: T,
+    requested: T,
+    /// `None` until it's been applied at least once.
+    applied: Option<T>,
+    /// Only used by [`ApplyPolicy::Verified`].
+    verified: bool,
+    /// Only used by [`ApplyPolicy::NextRestart`].
+    live_max: Option<T>,
+    policy: ApplyPolicy,
+}
+
+impl<T: Clone + PartialOrd + fmt::Display> Setting<T> {
+    /// A setting that's requested to be `default`, and hasn't been applied.
+    #[must_use]
+    pub fn new(label: &'static str, default: T, policy: ApplyPolicy) -> Self {
+        Self {
+            label,
+            requested: default.clone(),
+            default,
+            applied: None,
+            verified: false,
+            live_max: None,
+            policy,
+        }
+    }
+
+    /// Request `requested` instead of the default, like when loading it from
+    /// storage.
+    #[must_use]
+    pub fn with_requested(mut self, requested: T) -> Self {
+        self.requested = requested;
+        self
+    }
+
+    #[must_use]
+    pub const fn requested(&self) -> &T {
+        &self.requested
+    }
+
+    /// What's in effect, if it's been applied yet.
+    #[must_use]
+    pub const fn applied(&self) -> Option<&T> {
+        self.applied.as_ref()
+    }
+
+    /// What's in effect, or the default if it hasn't been applied yet.
+    #[must_use]
+    pub fn applied_or_default(&self) -> &T {
+        self.applied.as_ref().unwrap_or(&self.default)
+    }
+
+    #[must_use]
+    pub const fn default_value(&self) -> &T {
+        &self.default
+    }
+
+    #[must_use]
+    pub const fn policy(&self) -> ApplyPolicy {
+        self.policy
+    }
+
+    /// Whether what's requested isn't (known to be) in effect yet.
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        self.applied.as_ref() != Some(&self.requested)
+            || (self.policy == ApplyPolicy::Verified && !self.verified)
+    }
+
+    /// Let values up to `max` be applied after SE's started too, for a
+    /// [`ApplyPolicy::NextRestart`] setting that only needs a restart to go
+    /// above what SE started with.
+    pub fn set_live_max(&mut self, max: Option<T>) {
+        self.live_max = max;
+    }
+
+    #[must_use]
+    pub const fn live_max(&self) -> Option<&T> {
+        self.live_max.as_ref()
+    }
+
+    /// Whether the requested value can be applied right now.
+    #[must_use]
+    pub fn can_apply(&self) -> bool {
+        self.__can_apply(se_started())
+    }
+
+    fn __can_apply(&self, se_started: bool) -> bool {
+        self.policy != ApplyPolicy::NextRestart
+            || !se_started
+            || self
+                .live_max
+                .as_ref()
+                .is_some_and(|max| self.requested <= *max)
+    }
+
+    /// Change what's requested. If that can't be applied until SE restarts, a
+    /// restart is requested on behalf of `plugin`. Returns whether it should be
+    /// applied now.
+    pub fn request(&mut self, plugin: &str, value: T) -> bool {
+        self.__request(plugin, value, se_started())
+    }
+
+    fn __request(&mut self, plugin: &str, value: T, se_started: bool) -> bool {
+        self.requested = value;
+
+        if self.__can_apply(se_started) {
+            // Anything asked for before doesn't need a restart anymore
+            withdraw_restart(&plugin, self.label);
+
+            return true;
+        }
+
+        if self.is_pending() {
+            let reason = match self.live_max.as_ref() {
+                Some(max) => format!(
+                    "{} can only be raised above {max} before SE has started.",
+                    self.label
+                ),
+                None => format!("{} can only be changed before SE has started.", self.label),
+            };
+
+            request_restart(&plugin, &reason);
+        }
+        else {
+            // Back to what's in effect
+            withdraw_restart(&plugin, self.label);
+        }
+
+        false
+    }
+
+    /// Apply what's requested with `f`, if it can be applied right now.
+    pub fn apply(&mut self, f: impl FnOnce(&T) -> Result<()>) -> Result<()> {
+        if !self.can_apply() {
+            return Ok(());
+        }
+
+        f(&self.requested)?;
+
+        self.applied = Some(self.requested.clone());
+        self.verified = false;
+
+        Ok(())
+    }
+
+    /// Check what's applied is really in effect with `f`. Returns whether it
+    /// is.
+    pub fn verify(&mut self, f: impl FnOnce(&T) -> Result<bool>) -> Result<bool> {
+        let Some(applied) = self.applied.as_ref()
+        else {
+            return Ok(false);
+        };
+
+        self.verified = f(applied)?;
+
+        Ok(self.verified)
+    }
+
+    /// Show `widget` for the requested value, and whether it's pending. Returns
+    /// whether it was changed and should be applied now.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        plugin: &str,
+        widget: impl FnOnce(&mut Ui, &mut T) -> Response,
+    ) -> bool {
+        let mut value = self.requested.clone();
+        let changed = widget(ui, &mut value).changed();
+        let apply = changed && self.request(plugin, value);
+
+        if let Some(pending) = self.__pending() {
+            ui.label(RichText::new(pending).color(Color32::YELLOW));
+        }
+
+        apply
+    }
+
+    fn __pending(&self) -> Option<String> {
+        if !self.is_pending() {
+            return None;
+        }
+
+        Some(match (self.applied.as_ref(), self.policy) {
+            (None, _) => "Not applied yet".to_owned(),
+            (Some(applied), ApplyPolicy::NextRestart) => {
+                format!("Applies after restarting SE, currently {applied}")
+            },
+            (Some(applied), ApplyPolicy::Verified) if *applied == self.requested => {
+                "Applied, but not verified yet".to_owned()
+            },
+            (Some(applied), _) => format!("Not applied yet, currently {applied}"),
+        })
+    }
+}
+
+impl<T: Clone + PartialOrd + fmt::Display> fmt::Display for Setting<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.applied.as_ref() {
+            Some(applied) => write!(f, "{} is {applied}", self.label)?,
+            None => write!(f, "{} hasn't been applied", self.label)?,
+        }
+
+        if *self.applied_or_default() == self.default {
+            write!(f, " (default)")?;
+        }
+
+        if let Some(pending) = self.__pending() {
+            write!(f, ". {} is requested: {pending}", self.requested)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::pending_restarts;
+
+    #[test]
+    fn live() -> Result<()> {
+        let mut setting = Setting::new("Enabled", false, ApplyPolicy::Live);
+
+        assert!(setting.is_pending());
+        assert_eq!(setting.applied(), None);
+
+        setting.apply(|_| Ok(()))?;
+
+        assert!(!setting.is_pending());
+        assert_eq!(setting.to_string(), "Enabled is false (default)");
+
+        assert!(setting.request("Plugin", true));
+        assert!(setting.is_pending());
+        assert_eq!(
+            setting.to_string(),
+            "Enabled is false (default). true is requested: Not applied yet, currently false"
+        );
+
+        setting.apply(|_| Ok(()))?;
+
+        assert_eq!(setting.applied(), Some(&true));
+        assert_eq!(setting.to_string(), "Enabled is true");
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_apply() {
+        let mut setting = Setting::new("Max", 1u32, ApplyPolicy::Live).with_requested(5u32);
+
+        assert!(setting.apply(|_| eyre::bail!("No")).is_err());
+        assert_eq!(setting.applied(), None);
+        assert_eq!(*setting.applied_or_default(), 1u32);
+        assert!(setting.is_pending());
+    }
+
+    #[test]
+    fn verified() -> Result<()> {
+        let mut setting = Setting::new("Enabled", false, ApplyPolicy::Verified);
+
+        assert!(!setting.verify(|_| Ok(true))?);
+
+        setting.apply(|_| Ok(()))?;
+
+        assert!(setting.is_pending());
+        assert!(!setting.verify(|_| Ok(false))?);
+        assert!(setting.is_pending());
+        assert!(setting.verify(|applied| Ok(!*applied))?);
+        assert!(!setting.is_pending());
+
+        // Applying again needs verifying again
+        setting.apply(|_| Ok(()))?;
+
+        assert!(setting.is_pending());
+
+        Ok(())
+    }
+
+    #[test]
+    fn next_restart_before_start() -> Result<()> {
+        let mut setting = Setting::new("Max", 10000u32, ApplyPolicy::NextRestart);
+
+        // SE hasn't started in tests, so this can still be applied
+        assert!(setting.can_apply());
+        assert!(setting.request("Plugin", 50000u32));
+
+        setting.apply(|_| Ok(()))?;
+
+        assert_eq!(setting.applied(), Some(&50000u32));
+
+        Ok(())
+    }
+
+    #[test]
+    fn live_max() {
+        let mut setting = Setting::new("Max", 10000u32, ApplyPolicy::NextRestart);
+
+        setting.set_live_max(Some(50000u32));
+
+        for (requested, live) in [(20000u32, true), (50000u32, true), (50001u32, false)] {
+            setting.requested = requested;
+
+            assert_eq!(setting.__can_apply(true), live);
+        }
+
+        setting.set_live_max(None);
+        setting.requested = 20000u32;
+
+        assert!(!setting.__can_apply(true));
+        assert!(setting.__can_apply(false));
+    }
+
+    #[test]
+    fn withdrawn() -> Result<()> {
+        let requested = || {
+            pending_restarts()
+                .into_iter()
+                .any(|(name, _)| name == "Withdrawn")
+        };
+        let mut setting = Setting::new("Max", 10000u32, ApplyPolicy::NextRestart);
+
+        setting.apply(|_| Ok(()))?;
+
+        assert!(!setting.__request("Withdrawn", 20000u32, true));
+        assert!(requested());
+
+        // Back to what's applied, so there's nothing to restart for
+        assert!(!setting.__request("Withdrawn", 10000u32, true));
+        assert!(!requested());
+
+        // Or it became possible without one
+        setting.__request("Withdrawn", 20000u32, true);
+        setting.set_live_max(Some(20000u32));
+
+        assert!(setting.__request("Withdrawn", 20000u32, true));
+        assert!(!requested());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! Where starb's settings are stored. By default that's eframe's usual place in
+//! `AppData`, which every SE install on the machine shares. With a
+//! `starb_portable` file in SE's system folder, they're kept next to SE
+//! instead, and `STARB_SETTINGS` overrides both.
+//!
+//! `starb-ctl` finds the settings the same way, so keep it in sync.
+
+use crate::utils::sys_folder;
+use directories_next::ProjectDirs;
+use eyre::eyre;
+use eyre::Result;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use tracing::error;
+use tracing::info;
+
+/// If this exists in SE's system folder, settings are stored there too.
+pub const PORTABLE_MARKER: &str = "starb_portable";
+/// Name of the settings file in SE's system folder, when it's portable.
+pub const PORTABLE_FILE: &str = "starb_settings.ron";
+/// Environment variable with the path to use instead.
+pub const PATH_VAR: &str = "STARB_SETTINGS";
+
+/// What starb's window (and eframe's default settings folder) is called.
+#[must_use]
+pub fn app_name() -> String {
+    format!("Star Browser Utilities v{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Where eframe puts settings by default.
+#[must_use]
+pub fn default_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", &app_name()).map(|dirs| dirs.data_dir().join("app.ron"))
+}
+
+/// Where settings should be stored.
+pub fn path() -> Result<PathBuf> {
+    choose(
+        env::var_os(PATH_VAR).map(PathBuf::from),
+        &sys_folder()?,
+        default_path(),
+    )
+}
+
+/// Same as [`path`], but with everything it depends on passed in.
+pub fn choose(
+    var: Option<PathBuf>,
+    sys_folder: &Path,
+    default: Option<PathBuf>,
+) -> Result<PathBuf> {
+    if let Some(path) = var {
+        return Ok(path);
+    }
+
+    if sys_folder.join(PORTABLE_MARKER).exists() {
+        return Ok(sys_folder.join(PORTABLE_FILE));
+    }
+
+    default.ok_or_else(|| eyre!("Couldn't find anywhere to store settings"))
+}
+
+/// Copy settings from `from` to `to`, if `to` doesn't have any yet. Returns
+/// whether anything was copied. eframe's backup of them is copied too, so
+/// there's still something to recover from.
+///
+/// This copies rather than moves, so other SE installs still have theirs.
+pub fn migrate(from: &Path, to: &Path) -> Result<bool> {
+    if from == to || to.exists() || !from.exists() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let (from_backup, to_backup) = (__suffixed(from, ".bak"), __suffixed(to, ".bak"));
+
+    if from_backup.exists() {
+        __copy(&from_backup, &to_backup)?;
+    }
+
+    // Last, since `to` existing means this is done
+    __copy(from, to)?;
+
+    Ok(true)
+}
+
+/// Copy `from` to `to` through a temporary file, so `to` is never only partly
+/// there, same as how eframe saves.
+fn __copy(from: &Path, to: &Path) -> Result<()> {
+    let temp = __suffixed(to, ".tmp");
+
+    fs::copy(from, &temp)?;
+    fs::File::open(&temp)?.sync_all()?;
+    fs::rename(&temp, to)?;
+
+    Ok(())
+}
+
+/// `path` with `suffix` after its extension, like eframe's `app.ron.bak`.
+fn __suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+
+    path.push(suffix);
+
+    PathBuf::from(path)
+}
+
+/// Find where settings should be stored, and migrate them there if they were
+/// somewhere else before. `None` leaves it up to eframe.
+#[must_use]
+pub fn prepare() -> Option<PathBuf> {
+    let path = match path() {
+        Ok(path) => path,
+        Err(e) => {
+            error!("Failed to find where to store settings: {e}");
+
+            return None;
+        },
+    };
+
+    if let Some(default) = default_path() {
+        match migrate(&default, &path) {
+            Ok(true) => info!(
+                "Copied settings from {} to {}",
+                default.display(),
+                path.display()
+            ),
+            Ok(false) => {},
+            // Not worth losing the new location over
+            Err(e) => error!("Failed to copy settings from {}: {e}", default.display()),
+        }
+    }
+
+    // eframe doesn't create it, and won't save without it
+    if let Some(parent) = path.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            error!("Failed to create {}: {e}", parent.display());
+
+            return None;
+        }
+    }
+
+    info!("Storing settings in {}", path.display());
+
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty folder of its own for each test.
+    fn __test_dir(name: &str) -> Result<PathBuf> {
+        let dir = env::temp_dir().join(format!("starb_storage_{}_{name}", std::process::id()));
+
+        // Left over from a run that failed
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+
+        fs::create_dir_all(&dir)?;
+
+        Ok(dir)
+    }
+
+    #[test]
+    fn choosing() -> Result<()> {
+        let sys_folder = __test_dir("choosing")?;
+        let default = PathBuf::from("appdata/app.ron");
+
+        assert_eq!(choose(None, &sys_folder, Some(default.clone()))?, default);
+        assert!(choose(None, &sys_folder, None).is_err());
+
+        fs::write(sys_folder.join(PORTABLE_MARKER), "")?;
+
+        assert_eq!(
+            choose(None, &sys_folder, Some(default.clone()))?,
+            sys_folder.join(PORTABLE_FILE)
+        );
+        assert_eq!(
+            choose(Some("elsewhere.ron".into()), &sys_folder, Some(default))?,
+            PathBuf::from("elsewhere.ron")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrating() -> Result<()> {
+        let dir = __test_dir("migrating")?;
+        let from = dir.join("appdata").join("app.ron");
+        let to = dir.join("se").join(PORTABLE_FILE);
+
+        // Nothing to migrate yet
+        assert!(!migrate(&from, &to)?);
+        assert!(!to.exists());
+
+        fs::create_dir_all(dir.join("appdata"))?;
+        fs::write(&from, "old")?;
+        fs::write(dir.join("appdata").join("app.ron.bak"), "older")?;
+
+        assert!(migrate(&from, &to)?);
+        assert_eq!(fs::read_to_string(&to)?, "old");
+        assert_eq!(
+            fs::read_to_string(dir.join("se").join("starb_settings.ron.bak"))?,
+            "older"
+        );
+        assert!(!dir.join("se").join("starb_settings.ron.tmp").exists());
+        assert!(from.exists());
+
+        // Only on the first run
+        fs::write(&to, "new")?;
+
+        assert!(!migrate(&from, &to)?);
+        assert_eq!(fs::read_to_string(&to)?, "new");
+        assert!(!migrate(&to, &to)?);
+
+        Ok(())
+    }
+}
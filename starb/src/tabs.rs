@@ -0,0 +1,141 @@
+//! Tabs on the menu bar. starb's own tabs and plugins' tabs are registered the
+//! same way, see [`Tabs::register`] and [`Plugin::tabs`].
+//!
+//! [`Plugin::tabs`]: crate::plugin::Plugin::tabs
+
+use crate::app::StarApp;
+use eframe::Frame;
+use egui::Context;
+use egui::Ui;
+use tracing::warn;
+
+/// Shows a tab built into starb.
+pub type BuiltinUi = fn(&mut StarApp, &Context, &mut Frame, &mut Ui);
+
+/// A tab on the menu bar.
+#[derive(Clone, Debug)]
+pub struct Tab {
+    id: String,
+    title: String,
+    icon: Option<String>,
+    order: i32,
+    scroll: bool,
+}
+
+impl Tab {
+    /// `id` is what's saved as the selected tab, so it shouldn't change. A
+    /// plugin's tabs' IDs only need to be unique to that plugin.
+    #[must_use]
+    pub fn new(id: impl Into<String>, title: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            title: title.into(),
+            icon: None,
+            order: 0i32,
+            scroll: true,
+        }
+    }
+
+    /// Shown before the title. Should be an emoji egui's default fonts have.
+    #[must_use]
+    pub fn icon(mut self, icon: impl Into<String>) -> Self {
+        self.icon = Some(icon.into());
+        self
+    }
+
+    /// Tabs are sorted by this, then by title. starb's own tabs are 0-99.
+    #[must_use]
+    pub const fn order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Don't put this tab in a scroll area, for tabs that have their own.
+    #[must_use]
+    pub const fn no_scroll(mut self) -> Self {
+        self.scroll = false;
+        self
+    }
+
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[must_use]
+    pub const fn scroll(&self) -> bool {
+        self.scroll
+    }
+
+    /// What's shown on the menu bar.
+    #[must_use]
+    pub fn label(&self) -> String {
+        match self.icon.as_ref() {
+            Some(icon) => format!("{icon} {}", self.title),
+            None => self.title.clone(),
+        }
+    }
+}
+
+/// What shows a tab.
+#[derive(Clone, Debug)]
+pub enum TabUi {
+    Builtin(BuiltinUi),
+    /// [`Plugin::add_tab`] of the plugin with `key`, with the tab's ID as the
+    /// plugin gave it.
+    ///
+    /// [`Plugin::add_tab`]: crate::plugin::Plugin::add_tab
+    Plugin {
+        key: String,
+        id: String,
+    },
+}
+
+/// Every tab, in the order they're shown.
+#[derive(Default)]
+pub struct Tabs(Vec<(Tab, TabUi)>);
+
+impl Tabs {
+    /// Add a tab. Ignored if there's already a tab with the same ID.
+    pub fn register(&mut self, tab: Tab, ui: TabUi) {
+        if self.get(tab.id()).is_some() {
+            warn!("There's already a tab called `{}`, ignoring it", tab.id());
+            return;
+        }
+
+        let i = self
+            .0
+            .partition_point(|other| (other.0.order, &other.0.title) <= (tab.order, &tab.title));
+
+        self.0.insert(i, (tab, ui));
+    }
+
+    /// Add a plugin's tabs. Their IDs are prefixed with the plugin's key.
+    pub fn register_plugin(&mut self, key: &str, tabs: Vec<Tab>) {
+        for mut tab in tabs {
+            let id = tab.id;
+            tab.id = format!("{key}/{id}");
+
+            self.register(tab, TabUi::Plugin {
+                key: key.to_owned(),
+                id,
+            });
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, id: &str) -> Option<&(Tab, TabUi)> {
+        self.0.iter().find(|pair| pair.0.id() == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tab> {
+        self.0.iter().map(|pair| &pair.0)
+    }
+
+    /// The tab with `id`, or the first tab if it's gone, like if the plugin
+    /// that added it has been removed.
+    #[must_use]
+    pub fn get_or_first(&self, id: &str) -> Option<&(Tab, TabUi)> {
+        self.get(id).or_else(|| self.0.first())
+    }
+}
@@ -0,0 +1,195 @@
+//! Runs [`Plugin::tick`] on its own thread, so anything a plugin has to keep
+//! doing keeps happening while starb's window is minimized, closed, or just
+//! not being repainted.
+
+use crate::app::Plugins;
+use crate::app::PLUGINS;
+use crate::safe_mode;
+use eyre::Result;
+use hashbrown::HashMap;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::info;
+use tracing::warn;
+
+/// Longest the scheduler sleeps, so plugins that start ticking (like when
+/// they're enabled) don't wait long for their first.
+const MAX_SLEEP: Duration = Duration::from_millis(250u64);
+
+/// When each plugin is next due.
+#[derive(Default)]
+pub struct Scheduler {
+    next: HashMap<String, Instant>,
+    /// Last error of each plugin, so failing every tick isn't logged every
+    /// tick.
+    errors: HashMap<String, String>,
+}
+
+impl Scheduler {
+    /// Tick every plugin that's due at `now`. Returns when to run this again.
+    pub fn run(&mut self, plugins: &mut Plugins, now: Instant) -> Instant {
+        let mut wake = now + MAX_SLEEP;
+
+        for plugin in plugins.iter_mut() {
+            let key = plugin.0.key();
+
+            let Some(interval) = plugin.0.tick_interval()
+            else {
+                self.next.remove(&key);
+                continue;
+            };
+
+            let next = self.next.entry(key.clone()).or_insert(now);
+
+            if *next <= now {
+                let _active = safe_mode::enter(key.clone());
+
+                match plugin.0.tick() {
+                    Ok(()) => {
+                        if self.errors.remove(&key).is_some() {
+                            info!("`{}` is ticking fine again", plugin.0.name());
+                        }
+                    },
+                    Err(e) => {
+                        let e = e.to_string();
+
+                        if self.errors.get(&key) != Some(&e) {
+                            warn!("`{}` failed to tick: {e}", plugin.0.name());
+
+                            self.errors.insert(key, e);
+                        }
+                    },
+                }
+
+                // From now, so a slow tick doesn't cause a burst to catch up
+                *next = now + interval;
+            }
+
+            wake = wake.min(*next);
+        }
+
+        wake
+    }
+}
+
+/// Start ticking plugins.
+pub fn spawn() -> Result<()> {
+    thread::Builder::new()
+        .name("starb-tick".to_owned())
+        .spawn(|| {
+            let mut scheduler = Scheduler::default();
+
+            loop {
+                let wake = scheduler.run(
+                    &mut PLUGINS.get().expect("Unreachable").lock(),
+                    Instant::now(),
+                );
+
+                thread::sleep(wake.saturating_duration_since(Instant::now()));
+            }
+        })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::PluginTy;
+    use crate::plugin::Plugin;
+    use crate::plugin::PluginPass;
+    use eframe::CreationContext;
+    use eyre::bail;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    struct Ticker {
+        key: &'static str,
+        interval: Option<Duration>,
+        ticks: Arc<AtomicUsize>,
+        fail: bool,
+    }
+
+    impl Plugin for Ticker {
+        fn load(_cc: &CreationContext<'_>) -> Result<Self> {
+            bail!("Only made by `__ticker`");
+        }
+
+        fn pass(&self) -> PluginPass {
+            PluginPass::Late
+        }
+
+        fn name(&self) -> String {
+            self.key.to_owned()
+        }
+
+        fn key(&self) -> String {
+            self.key.to_owned()
+        }
+
+        fn tick_interval(&self) -> Option<Duration> {
+            self.interval
+        }
+
+        fn tick(&mut self) -> Result<()> {
+            self.ticks.fetch_add(1usize, Ordering::Relaxed);
+
+            if self.fail {
+                bail!("Oops");
+            }
+
+            Ok(())
+        }
+    }
+
+    fn __ticker(
+        key: &'static str,
+        millis: Option<u64>,
+        fail: bool,
+    ) -> (PluginTy, Arc<AtomicUsize>) {
+        let ticks = Arc::new(AtomicUsize::new(0usize));
+
+        (
+            Box::new(Ticker {
+                key,
+                interval: millis.map(Duration::from_millis),
+                ticks: Arc::clone(&ticks),
+                fail,
+            }),
+            ticks,
+        )
+    }
+
+    #[test]
+    fn scheduling() {
+        let (fast, fast_ticks) = __ticker("fast", Some(10u64), false);
+        let (slow, slow_ticks) = __ticker("slow", Some(100u64), true);
+        let (never, never_ticks) = __ticker("never", None, false);
+        let mut plugins = vec![(fast, true), (slow, true), (never, true)];
+        let mut scheduler = Scheduler::default();
+        let start = Instant::now();
+
+        // Everything's due the first time
+        assert_eq!(
+            scheduler.run(&mut plugins, start),
+            start + Duration::from_millis(10u64)
+        );
+
+        for millis in (10u64..=100u64).step_by(10usize) {
+            scheduler.run(&mut plugins, start + Duration::from_millis(millis));
+        }
+
+        assert_eq!(fast_ticks.load(Ordering::Relaxed), 11usize);
+        assert_eq!(slow_ticks.load(Ordering::Relaxed), 2usize);
+        assert_eq!(never_ticks.load(Ordering::Relaxed), 0usize);
+        // Only logged once
+        assert_eq!(scheduler.errors.len(), 1usize);
+
+        // Nothing to tick doesn't mean sleeping forever
+        let mut plugins = vec![(__ticker("never", None, false).0, true)];
+
+        assert_eq!(scheduler.run(&mut plugins, start), start + MAX_SLEEP);
+    }
+}
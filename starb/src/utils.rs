@@ -1,13 +1,28 @@
+use crate::game::Located;
+use crate::game::Value as GameValue;
+use eyre::bail;
+use eyre::eyre;
 use eyre::Result;
+use eyre::WrapErr;
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
 use path_clean::PathClean;
 use region::protect_with_handle;
 use region::Protection;
+use serde::Deserialize;
+use serde::Serialize;
 use std::env::current_exe;
+use std::fmt;
 use std::mem::size_of;
 use std::path::PathBuf;
+use std::ptr;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
 use tracing::trace;
 use windows_sys::Win32::System::ProcessStatus::EnumProcessModules;
+use windows_sys::Win32::System::ProcessStatus::GetModuleInformation;
+use windows_sys::Win32::System::ProcessStatus::MODULEINFO;
 use windows_sys::Win32::System::Threading::GetCurrentProcess;
 
 /// Base address of SE module
@@ -42,6 +57,40 @@ pub fn base() -> *mut () {
     }) as *mut ()
 }
 
+/// Size of SE's image in memory, from [`base`]. Anything outside of this
+/// moves every time SE starts.
+///
+/// # Panics
+///
+/// Panics if getting SE's module info fails.
+#[must_use]
+pub fn image_size() -> usize {
+    static SIZE: OnceCell<usize> = OnceCell::new();
+
+    *SIZE.get_or_init(|| {
+        let mut info = MODULEINFO {
+            lpBaseOfDll: ptr::null_mut(),
+            SizeOfImage: 0u32,
+            EntryPoint: ptr::null_mut(),
+        };
+
+        unsafe {
+            assert_ne!(
+                GetModuleInformation(
+                    GetCurrentProcess(),
+                    base() as isize,
+                    ptr::addr_of_mut!(info),
+                    size_of::<MODULEINFO>() as u32,
+                ),
+                0i32,
+                "Failed to get SE's module info",
+            );
+        }
+
+        info.SizeOfImage as usize
+    })
+}
+
 /// Get SE's system folder.
 pub fn sys_folder() -> Result<PathBuf> {
     Ok(current_exe()?.join("../").clean())
@@ -70,6 +119,23 @@ pub unsafe fn write<T>(p: *mut T, val: T) -> Result<()> {
     Ok(())
 }
 
+/// Same as [`write`], but for any number of bytes.
+///
+/// # Safety
+///
+/// * `p` must point to `bytes.len()` bytes of mapped memory.
+/// * The caller must uphold writing to `p` will maintain memory safety.
+pub unsafe fn write_bytes(p: *mut u8, bytes: &[u8]) -> Result<()> {
+    trace!("Writing {} bytes to {p:?}", bytes.len());
+
+    let _guard = unsafe {
+        protect_with_handle(p.cast_const(), bytes.len(), Protection::READ_WRITE_EXECUTE)?
+    };
+
+    unsafe { p.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len()) };
+    Ok(())
+}
+
 /// Change `p`'s memory protection and read it, then revert the protection.
 ///
 /// This is common in plugins, so it is here.
@@ -85,3 +151,305 @@ pub unsafe fn read<T>(p: *const T) -> Result<T> {
 
     Ok(unsafe { p.read() })
 }
+
+/// Same as [`read`], but for any number of bytes.
+///
+/// # Safety
+///
+/// * `p` must point to `len` bytes of mapped memory.
+pub unsafe fn read_bytes(p: *const u8, len: usize) -> Result<Vec<u8>> {
+    trace!("Reading {len} bytes at {p:?}");
+
+    let _guard = unsafe { protect_with_handle(p, len, Protection::READ_EXECUTE)? };
+
+    Ok(unsafe { std::slice::from_raw_parts(p, len) }.to_vec())
+}
+
+/// `address` relative to `base`, which is negative if it's before it.
+#[must_use]
+pub const fn rva(address: usize, base: usize) -> isize {
+    address.wrapping_sub(base) as isize
+}
+
+/// Same as `{:#X}`, but signed.
+#[must_use]
+pub fn format_rva(rva: isize) -> String {
+    if rva < 0isize {
+        format!("-{:#X}", rva.unsigned_abs())
+    }
+    else {
+        format!("{rva:#X}")
+    }
+}
+
+/// Opposite of [`format_rva`]. This is always hex, with or without `0x`.
+pub fn parse_rva(s: &str) -> Result<isize> {
+    let s = s.trim();
+    let (negative, s) = s.strip_prefix('-').map_or((false, s), |s| (true, s));
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    let rva = isize::from_str_radix(s, 16u32).wrap_err_with(|| format!("Invalid offset `{s}`"))?;
+
+    Ok(if negative { -rva } else { rva })
+}
+
+/// When a [`PointerChain`]'s address is resolved again.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Invalidation {
+    /// Every time. Anything SE reallocates, like the current search, needs
+    /// this.
+    #[default]
+    Always,
+    /// After this long.
+    After(Duration),
+    /// Only after [`PointerChain::invalidate`].
+    Manual,
+}
+
+/// An address behind any number of pointers, like Cheat Engine's: start at
+/// `rva` from SE's base, then for each offset, follow the pointer there and add
+/// the offset to it. With no offsets, this is just `rva`.
+///
+/// Null and unreadable links are errors, and the result is cached according to
+/// its [`Invalidation`].
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PointerChain {
+    pub rva: isize,
+    pub offsets: Vec<isize>,
+    #[serde(default)]
+    pub invalidation: Invalidation,
+    /// Last address and when it was resolved.
+    #[serde(skip)]
+    cache: Mutex<Option<(usize, Instant)>>,
+}
+
+impl PointerChain {
+    #[must_use]
+    pub fn new(rva: isize, offsets: impl Into<Vec<isize>>) -> Self {
+        Self {
+            rva,
+            offsets: offsets.into(),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub const fn invalidation(mut self, invalidation: Invalidation) -> Self {
+        self.invalidation = invalidation;
+        self
+    }
+
+    /// Resolve this again next time, no matter what.
+    pub fn invalidate(&self) {
+        *self.cache.lock() = None;
+    }
+
+    /// Where this ends up in SE.
+    pub fn resolve(&self) -> Result<usize> {
+        self.resolve_with(base() as usize, __read_link)
+    }
+
+    /// Same as [`resolve`](Self::resolve), but from `base` and reading links
+    /// with `read`.
+    pub fn resolve_with(
+        &self,
+        base: usize,
+        read: impl Fn(usize) -> Result<usize>,
+    ) -> Result<usize> {
+        let mut cache = self.cache.lock();
+
+        if let Some((address, resolved)) = *cache {
+            match self.invalidation {
+                Invalidation::Always => {},
+                Invalidation::After(duration) if resolved.elapsed() >= duration => {},
+                _ => return Ok(address),
+            }
+        }
+
+        let mut address = base.wrapping_add_signed(self.rva);
+
+        for (i, &offset) in self.offsets.iter().enumerate() {
+            let link = read(address)
+                .wrap_err_with(|| format!("Link {i} of {self} at {address:#X} isn't readable"))?;
+
+            if link == 0usize {
+                bail!("Link {i} of {self} at {address:#X} is null");
+            }
+
+            address = link.wrapping_add_signed(offset);
+        }
+
+        *cache = Some((address, Instant::now()));
+
+        Ok(address)
+    }
+
+    /// What's at the end, see [`game`](crate::game).
+    pub fn get<T: Located>(&self) -> Result<T> {
+        self.resolve().map(T::at)
+    }
+}
+
+impl Clone for PointerChain {
+    fn clone(&self) -> Self {
+        Self {
+            rva: self.rva,
+            offsets: self.offsets.clone(),
+            invalidation: self.invalidation,
+            cache: Mutex::new(*self.cache.lock()),
+        }
+    }
+}
+
+impl PartialEq for PointerChain {
+    fn eq(&self, other: &Self) -> bool {
+        self.rva == other.rva
+            && self.offsets == other.offsets
+            && self.invalidation == other.invalidation
+    }
+}
+
+impl Eq for PointerChain {}
+
+/// Like `0x1046F08 -> 0x10 -> -0x8`.
+impl fmt::Display for PointerChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_rva(self.rva))?;
+
+        for &offset in &self.offsets {
+            write!(f, " -> {}", format_rva(offset))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for PointerChain {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split("->");
+        let rva = parse_rva(parts.next().ok_or_else(|| eyre!("Empty pointer chain"))?)?;
+        let offsets = parts.map(parse_rva).collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::new(rva, offsets))
+    }
+}
+
+/// Through `ReadProcessMemory`, like the rest of [`game`](crate::game), since
+/// what the chain goes through can be freed by SE at any time.
+fn __read_link(address: usize) -> Result<usize> {
+    GameValue::<usize>::at(address).read()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn resolving() -> Result<()> {
+        let links = HashMap::from([
+            (0x1100usize, 0x5000usize),
+            (0x5010usize, 0x9000usize),
+            (0x5020usize, 0usize),
+        ]);
+        let read = |address| {
+            links
+                .get(&address)
+                .copied()
+                .ok_or_else(|| eyre!("Unmapped"))
+        };
+
+        assert_eq!(
+            PointerChain::new(0x100isize, []).resolve_with(0x1000usize, read)?,
+            0x1100usize
+        );
+        assert_eq!(
+            PointerChain::new(0x100isize, [0x10isize, -0x8isize])
+                .resolve_with(0x1000usize, read)?,
+            0x8FF8usize
+        );
+
+        let null = PointerChain::new(0x100isize, [0x20isize, 0x0isize]);
+        let unmapped = PointerChain::new(0x200isize, [0x0isize]);
+
+        assert!(null
+            .resolve_with(0x1000usize, read)
+            .is_err_and(|e| e.to_string().contains("null")));
+        assert!(unmapped.resolve_with(0x1000usize, read).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn caching() -> Result<()> {
+        let chain = PointerChain::new(0x0isize, [0x0isize]);
+        let links = Mutex::new(0x5000usize);
+        let read = |_| Ok(*links.lock());
+
+        assert_eq!(chain.resolve_with(0usize, read)?, 0x5000usize);
+        *links.lock() = 0x6000usize;
+        assert_eq!(chain.resolve_with(0usize, read)?, 0x6000usize);
+
+        let chain = chain.invalidation(Invalidation::Manual);
+
+        assert_eq!(chain.resolve_with(0usize, read)?, 0x6000usize);
+        *links.lock() = 0x7000usize;
+        assert_eq!(chain.resolve_with(0usize, read)?, 0x6000usize);
+        chain.invalidate();
+        assert_eq!(chain.resolve_with(0usize, read)?, 0x7000usize);
+
+        let chain = chain.invalidation(Invalidation::After(Duration::ZERO));
+
+        *links.lock() = 0x8000usize;
+        assert_eq!(chain.resolve_with(0usize, read)?, 0x8000usize);
+
+        Ok(())
+    }
+
+    #[test]
+    fn real_memory() -> Result<()> {
+        let mut memory = region::alloc(0x1000usize, Protection::READ_WRITE)?;
+        let base = memory.as_mut_ptr::<usize>();
+
+        // Points to itself, plus 8
+        unsafe { base.write(base as usize + 8usize) };
+
+        let chain = PointerChain::new(0x0isize, [0x8isize, 0x0isize]);
+
+        // The second link is at 16, which is still 0
+        assert!(chain.resolve_with(base as usize, __read_link).is_err());
+        assert!(PointerChain::new(0x1000isize, [0x0isize])
+            .resolve_with(0x1000usize, __read_link)
+            .is_err());
+        assert_eq!(
+            PointerChain::new(0x0isize, [0x8isize]).resolve_with(base as usize, __read_link)?,
+            base as usize + 16usize
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn formatting() -> Result<()> {
+        let chain = PointerChain::new(0x1046F08isize, [0x10isize, -0x8isize]);
+
+        assert_eq!(chain.to_string(), "0x1046F08 -> 0x10 -> -0x8");
+        assert_eq!(chain.to_string().parse::<PointerChain>()?, chain);
+        assert_eq!(
+            " 1046f08 ".parse::<PointerChain>()?,
+            PointerChain::new(0x1046F08isize, [])
+        );
+        assert!("0x10 -> ".parse::<PointerChain>().is_err());
+        assert!("0xZZ".parse::<PointerChain>().is_err());
+
+        let json = serde_json::to_string(&chain)?;
+
+        assert_eq!(serde_json::from_str::<PointerChain>(&json)?, chain);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! Which version of SE this is. This used to ask Steam, which meant calling
+//! `SteamAPI_Init` from inside of SE (which SE doesn't like), and didn't work
+//! for copies of SE that aren't from Steam.
+//!
+//! Instead, this reads `appmanifest_314650.acf` for the Steam build ID, then
+//! SE's exe (on disk, since starb patches it in memory) for its timestamp,
+//! version resource and a hash of its code. [`SeVersion::supported`] checks
+//! the build ID, then the timestamp and version resource (which copies that
+//! aren't from Steam have too), then the hash, stopping at the first one it
+//! can check.
+
+use crate::utils::sys_folder;
+use eyre::eyre;
+use eyre::Result;
+use starb_formats::pe::FileVersion;
+use starb_formats::pe::Pe;
+use starb_formats::vdf;
+use std::env::current_exe;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// SE's Steam app ID.
+pub const APP_ID: u32 = 314650u32;
+
+/// Every version of SE starb is created for.
+const SUPPORTED: Supported = Supported {
+    build_ids: &[11154210u32],
+    // TODO: These need the exe of a supported build to fill in. Until then,
+    // they're skipped
+    timestamps: &[],
+    versions: &[[0u16, 990u16, 46u16]],
+    text_hashes: &[],
+};
+
+/// What versions of SE are supported, by each way of telling them apart. An
+/// empty list is skipped, rather than supporting nothing.
+struct Supported {
+    /// Steam build IDs.
+    build_ids: &'static [u32],
+    /// When SE's exe was linked.
+    timestamps: &'static [u32],
+    /// Without the last part, since that's the same for every build of a
+    /// version.
+    versions: &'static [[u16; 3usize]],
+    /// FNV-1a of SE's exe's `.text` section, for when there's nothing else.
+    text_hashes: &'static [u64],
+}
+
+/// Everything starb could find out about SE's version.
+#[derive(Clone, Debug, Default)]
+pub struct SeVersion {
+    /// From `appmanifest_314650.acf`, if SE's from Steam.
+    pub build_id: Option<u32>,
+    /// When SE's exe was linked.
+    pub timestamp: Option<u32>,
+    /// From SE's exe's version resource.
+    pub file_version: Option<FileVersion>,
+    /// FNV-1a of SE's exe's `.text` section, as it is on disk.
+    pub text_hash: Option<u64>,
+}
+
+impl SeVersion {
+    /// Whether starb supports this version. `None` if it can't tell.
+    #[must_use]
+    pub fn supported(&self) -> Option<bool> {
+        self.__supported(&SUPPORTED)
+    }
+
+    fn __supported(&self, supported: &Supported) -> Option<bool> {
+        let version = self.file_version.map(|FileVersion([a, b, c, _])| [a, b, c]);
+
+        __check(self.build_id, supported.build_ids)
+            .or_else(|| __check(self.timestamp, supported.timestamps))
+            .or_else(|| __check(version, supported.versions))
+            .or_else(|| __check(self.text_hash, supported.text_hashes))
+    }
+}
+
+impl fmt::Display for SeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.file_version {
+            Some(file_version) => write!(f, "v{file_version}")?,
+            None => write!(f, "Unknown version")?,
+        }
+
+        if let Some(build_id) = self.build_id {
+            write!(f, ", build {build_id}")?;
+        }
+
+        if let Some(timestamp) = self.timestamp {
+            write!(f, ", linked at {timestamp:#X}")?;
+        }
+
+        if let Some(text_hash) = self.text_hash {
+            write!(f, ", code hash {text_hash:016X}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Find out which version of SE this is. Nothing here is fatal, whatever
+/// can't be found is left as `None`.
+#[must_use]
+pub fn detect() -> SeVersion {
+    let mut version = SeVersion::default();
+
+    match __build_id() {
+        Ok(build_id) => version.build_id = Some(build_id),
+        Err(e) => debug!("No Steam build ID, SE's likely not from Steam: {e}"),
+    }
+
+    let exe = match current_exe().and_then(fs::read) {
+        Ok(exe) => exe,
+        Err(e) => {
+            debug!("Failed to read SE's exe: {e}");
+            return version;
+        },
+    };
+
+    match Pe::new(&exe) {
+        Ok(pe) => {
+            version.timestamp = Some(pe.timestamp());
+            version.file_version = pe.file_version().unwrap_or_else(|e| {
+                debug!("Failed to read SE's version resource: {e}");
+                None
+            });
+            version.text_hash = pe.section(".text").map(__fnv1a);
+        },
+        Err(e) => debug!("Failed to parse SE's exe: {e}"),
+    }
+
+    version
+}
+
+/// Whether `value` is in `supported`. `None` if there's no `value`, or nothing
+/// to check it against.
+fn __check<T: PartialEq>(value: Option<T>, supported: &[T]) -> Option<bool> {
+    value
+        .filter(|_| !supported.is_empty())
+        .map(|value| supported.contains(&value))
+}
+
+fn __build_id() -> Result<u32> {
+    let manifest = __manifest_path()?;
+    let manifest = vdf::parse(&fs::read_to_string(manifest)?)?;
+
+    manifest
+        .path(&["AppState", "buildid"])
+        .and_then(vdf::Vdf::as_str)
+        .ok_or_else(|| eyre!("appmanifest has no build ID"))?
+        .parse()
+        .map_err(Into::into)
+}
+
+/// `steamapps/appmanifest_314650.acf`. SE is in `steamapps/common/SpaceEngine`.
+fn __manifest_path() -> Result<PathBuf> {
+    sys_folder()?
+        .ancestors()
+        .find(|dir| {
+            dir.file_name()
+                .is_some_and(|name| name.eq_ignore_ascii_case("steamapps"))
+        })
+        .map(|steamapps| steamapps.join(format!("appmanifest_{APP_ID}.acf")))
+        .ok_or_else(|| eyre!("SE isn't in a steamapps folder"))
+}
+
+fn __fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF29CE484222325u64, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001B3u64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported() {
+        let version = |build_id, file_version: Option<[u16; 4usize]>| SeVersion {
+            build_id,
+            file_version: file_version.map(FileVersion),
+            ..SeVersion::default()
+        };
+
+        assert_eq!(version(None, None).supported(), None);
+        assert_eq!(
+            version(None, Some([0u16, 990u16, 46u16, 1830u16])).supported(),
+            Some(true)
+        );
+        assert_eq!(
+            version(None, Some([0u16, 990u16, 45u16, 1830u16])).supported(),
+            Some(false)
+        );
+        // The build ID wins, since it's more specific
+        assert_eq!(
+            version(Some(1u32), Some([0u16, 990u16, 46u16, 1830u16])).supported(),
+            Some(false)
+        );
+        assert_eq!(version(Some(11154210u32), None).supported(), Some(true));
+    }
+
+    #[test]
+    fn fallback() {
+        let supported = Supported {
+            build_ids: &[1u32],
+            timestamps: &[2u32],
+            versions: &[[0u16, 990u16, 46u16]],
+            text_hashes: &[3u64],
+        };
+        let version = SeVersion {
+            build_id: Some(1u32),
+            timestamp: Some(0u32),
+            file_version: Some(FileVersion([0u16, 0u16, 0u16, 0u16])),
+            text_hash: Some(0u64),
+        };
+
+        assert_eq!(
+            version.__supported(&supported),
+            Some(true),
+            "The build ID should be checked first"
+        );
+
+        let version = SeVersion {
+            build_id: None,
+            timestamp: Some(2u32),
+            ..version
+        };
+
+        assert_eq!(
+            version.__supported(&supported),
+            Some(true),
+            "The timestamp should be checked without a build ID"
+        );
+
+        let version = SeVersion {
+            timestamp: None,
+            file_version: Some(FileVersion([0u16, 990u16, 46u16, 1830u16])),
+            ..version
+        };
+
+        assert_eq!(
+            version.__supported(&supported),
+            Some(true),
+            "The version should be checked without a timestamp"
+        );
+
+        let version = SeVersion {
+            file_version: None,
+            ..version
+        };
+
+        assert_eq!(
+            version.__supported(&supported),
+            Some(false),
+            "The hash should be checked without anything else"
+        );
+        assert_eq!(
+            SeVersion {
+                text_hash: Some(3u64),
+                ..version
+            }
+            .__supported(&supported),
+            Some(true),
+            "The hash should be checked without anything else"
+        );
+
+        let nothing = Supported {
+            build_ids: &[],
+            timestamps: &[],
+            versions: &[],
+            text_hashes: &[],
+        };
+
+        assert_eq!(
+            version.__supported(&nothing),
+            None,
+            "Empty lists should be skipped"
+        );
+    }
+}